@@ -1,9 +1,10 @@
 use iai_callgrind::{library_benchmark, library_benchmark_group, main};
 use std::hint::black_box;
+use Mnemosyne::audio::NullSink;
 use Mnemosyne::gb::GameBoy;
 
 fn run_gameboy(rom_path: &str, duration: f32) {
-    let mut gameboy = GameBoy::new();
+    let mut gameboy = GameBoy::with_audio_sink(Box::new(NullSink::default()));
     gameboy.load_rom(rom_path);
     let mut cycles = 0;
     while cycles < (4194304.0 * duration) as u64 {
@@ -1,99 +1,148 @@
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{FromSample, Sample, SampleRate, Stream};
+use crate::config::THREAD_LOCAL_CONFIG;
+use crate::gb::apu::recorder::AudioRecorder;
 use log::{log, Level};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 
-pub(crate) struct AudioPlayer {
-    buffer: Arc<Mutex<Vec<(f32, f32)>>>,
-    pub(crate) sample_rate: cpal::SampleRate,
-    stream: Option<Stream>,
+#[cfg(feature = "frontend")]
+mod player;
+
+#[cfg(feature = "frontend")]
+pub(crate) use player::{output_devices, AudioPlayer};
+
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+// Destination for the samples produced by the APU
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+    fn add_samples(&mut self, buf_left: &[f32], buf_right: &[f32]);
+    fn set_volume(&mut self, _volume: f32) {}
+    fn underflowed(&self) -> bool {
+        false
+    }
 }
 
-impl AudioPlayer {
-    pub(crate) fn new() -> Self {
-        let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .expect("No output device available");
-
-        let wanted_samplerate = SampleRate(44100);
-        let supported_configs_range = match device.supported_output_configs() {
-            Ok(config_range) => config_range,
-            Err(_) => return Self::dummy(),
-        };
-        let mut supported_config = None;
-        for config in supported_configs_range {
-            if config.channels() == 2 && config.sample_format() == cpal::SampleFormat::F32 {
-                if wanted_samplerate >= config.min_sample_rate()
-                    && wanted_samplerate <= config.max_sample_rate()
-                {
-                    supported_config = Some(config.with_sample_rate(wanted_samplerate));
-                } else {
-                    supported_config = Some(config.with_max_sample_rate());
-                }
-            }
+// Opens the configured output device, falling back to a null sink when it is unavailable
+#[cfg(feature = "frontend")]
+pub(crate) fn default_sink() -> Box<dyn AudioSink> {
+    let config = THREAD_LOCAL_CONFIG.with(|c| c.borrow_mut().load().audio_config.clone());
+    match AudioPlayer::new(&config) {
+        Ok(player) => Box::new(player),
+        Err(err) => {
+            log!(Level::Warn, "Audio output disabled: {}", err);
+            Box::new(NullSink::new(config.sample_rate))
         }
+    }
+}
 
-        if supported_config.is_none() {
-            panic!();
-        }
+// Without the frontend there is no output device to play on
+#[cfg(not(feature = "frontend"))]
+pub(crate) fn default_sink() -> Box<dyn AudioSink> {
+    let config = THREAD_LOCAL_CONFIG.with(|c| c.borrow_mut().load().audio_config.clone());
+    Box::new(NullSink::new(config.sample_rate))
+}
 
-        let supported_config = supported_config.unwrap();
-        let shared_buffer = Arc::new(Mutex::new(Vec::new()));
-        let stream_buffer = shared_buffer.clone();
-
-        let stream = device
-            .build_output_stream(
-                &supported_config.config(),
-                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                    cpal_thread(data, &stream_buffer)
-                },
-                move |err| log!(Level::Error, "{}", err),
-                None,
-            )
-            .expect("Failed to build output stream");
-
-        stream.play().expect("Failed to start stream");
-
-        AudioPlayer {
-            buffer: shared_buffer,
-            sample_rate: supported_config.sample_rate(),
-            stream: Some(stream),
-        }
+// Discards all samples, for tests and benchmarks
+pub struct NullSink {
+    sample_rate: u32,
+}
+
+impl NullSink {
+    pub fn new(sample_rate: u32) -> Self {
+        NullSink { sample_rate }
+    }
+}
+
+impl Default for NullSink {
+    fn default() -> Self {
+        NullSink::new(DEFAULT_SAMPLE_RATE)
     }
+}
+
+impl AudioSink for NullSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn add_samples(&mut self, _buf_left: &[f32], _buf_right: &[f32]) {}
+}
+
+// Collects samples in memory, clones share the same buffer so the output can be inspected
+// after the sink has been handed to the emulator
+#[derive(Clone)]
+pub struct MemorySink {
+    sample_rate: u32,
+    samples: Arc<Mutex<Vec<(f32, f32)>>>,
+}
 
-    pub(crate) fn dummy() -> Self {
-        let shared_buffer = Arc::new(Mutex::new(Vec::new()));
-        AudioPlayer {
-            buffer: shared_buffer,
-            sample_rate: SampleRate(44100),
-            stream: None,
+impl MemorySink {
+    pub fn new(sample_rate: u32) -> Self {
+        MemorySink {
+            sample_rate,
+            samples: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
-    pub(crate) fn add_samples(&mut self, buf_left: &[f32], buf_right: &[f32]) {
-        let mut buffer = self.buffer.lock().unwrap();
+    pub fn samples(&self) -> Vec<(f32, f32)> {
+        self.samples.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.samples.lock().unwrap().clear();
+    }
+}
+
+impl AudioSink for MemorySink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn add_samples(&mut self, buf_left: &[f32], buf_right: &[f32]) {
+        let mut samples = self.samples.lock().unwrap();
+        samples.extend(buf_left.iter().copied().zip(buf_right.iter().copied()));
+    }
+}
+
+// Streams samples to a 16-bit stereo WAV file, finalized when dropped
+pub struct FileSink {
+    sample_rate: u32,
+    recorder: Option<AudioRecorder>,
+}
+
+impl FileSink {
+    pub fn new(path: &Path, sample_rate: u32) -> Result<Self, hound::Error> {
+        Ok(FileSink {
+            sample_rate,
+            recorder: Some(AudioRecorder::new(path, sample_rate, false)?),
+        })
+    }
+}
+
+impl AudioSink for FileSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
 
+    fn add_samples(&mut self, buf_left: &[f32], buf_right: &[f32]) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
         for (l, r) in buf_left.iter().zip(buf_right) {
-            if buffer.len() < self.sample_rate.0 as usize {
-                buffer.push((*l, *r));
+            if let Err(err) = recorder.write_mix(*l, *r) {
+                log!(Level::Error, "Failed to write audio file: {}", err);
+                self.recorder = None;
+                return;
             }
         }
     }
-
-    pub(crate) fn underflowed(&self) -> bool {
-        self.buffer.lock().unwrap().is_empty()
-    }
 }
 
-fn cpal_thread<T: Sample + FromSample<f32>>(
-    outbuffer: &mut [T],
-    audio_buffer: &Arc<Mutex<Vec<(f32, f32)>>>,
-) {
-    let mut inbuffer = audio_buffer.lock().unwrap();
-    let outlen = inbuffer.len().min(outbuffer.len() / 2);
-    for (i, (in_l, in_r)) in inbuffer.drain(..outlen).enumerate() {
-        outbuffer[i * 2] = T::from_sample(in_l);
-        outbuffer[i * 2 + 1] = T::from_sample(in_r);
+impl Drop for FileSink {
+    fn drop(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(err) = recorder.finish() {
+                log!(Level::Error, "Failed to finish audio file: {}", err);
+            }
+        }
     }
 }
@@ -0,0 +1,186 @@
+use crate::audio::AudioSink;
+use crate::config::AudioConfig;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, Sample, SampleRate, Stream};
+use log::{log, Level};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+// Largest deviation from the nominal sample rate used by the dynamic rate control
+const MAX_RATE_DELTA: f64 = 0.005;
+
+pub(crate) fn output_devices() -> Vec<String> {
+    match cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(err) => {
+            log!(Level::Error, "Failed to enumerate output devices: {}", err);
+            Vec::new()
+        }
+    }
+}
+
+fn find_device(name: &Option<String>) -> Option<Device> {
+    let host = cpal::default_host();
+    if let Some(name) = name {
+        let device = host.output_devices().ok().and_then(|mut devices| {
+            devices.find(|device| device.name().is_ok_and(|device_name| device_name == *name))
+        });
+        if device.is_some() {
+            return device;
+        }
+        log!(
+            Level::Warn,
+            "Output device {} not found, using default",
+            name
+        );
+    }
+    host.default_output_device()
+}
+
+// Plays samples on an output device through cpal
+pub(crate) struct AudioPlayer {
+    buffer: Arc<Mutex<VecDeque<(f32, f32)>>>,
+    sample_rate: cpal::SampleRate,
+    stream: Stream,
+    volume: f32,
+    // Buffer fill in samples that the dynamic rate control aims for
+    target_fill: usize,
+    dynamic_rate_control: bool,
+    // Resampler state, the position is relative to the last sample of the previous call
+    resample_position: f64,
+    previous_sample: (f32, f32),
+}
+
+impl AudioPlayer {
+    pub(crate) fn new(config: &AudioConfig) -> Result<Self, String> {
+        let device = find_device(&config.output_device).ok_or("No output device available")?;
+
+        let wanted_samplerate = SampleRate(config.sample_rate);
+        let supported_configs_range = device
+            .supported_output_configs()
+            .map_err(|err| err.to_string())?;
+        let mut supported_config = None;
+        for config in supported_configs_range {
+            if config.channels() == 2 && config.sample_format() == cpal::SampleFormat::F32 {
+                if wanted_samplerate >= config.min_sample_rate()
+                    && wanted_samplerate <= config.max_sample_rate()
+                {
+                    supported_config = Some(config.with_sample_rate(wanted_samplerate));
+                } else {
+                    supported_config = Some(config.with_max_sample_rate());
+                }
+            }
+        }
+
+        let supported_config =
+            supported_config.ok_or("No stereo f32 output configuration available")?;
+        let shared_buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let stream_buffer = shared_buffer.clone();
+
+        let stream = device
+            .build_output_stream(
+                &supported_config.config(),
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    cpal_thread(data, &stream_buffer)
+                },
+                move |err| log!(Level::Error, "{}", err),
+                None,
+            )
+            .map_err(|err| err.to_string())?;
+
+        stream.play().map_err(|err| err.to_string())?;
+
+        let sample_rate = supported_config.sample_rate();
+        Ok(AudioPlayer {
+            buffer: shared_buffer,
+            sample_rate,
+            stream,
+            volume: 1.0,
+            target_fill: (config.latency_ms as usize * sample_rate.0 as usize / 1000).max(1),
+            dynamic_rate_control: config.dynamic_rate_control,
+            resample_position: 0.0,
+            previous_sample: (0.0, 0.0),
+        })
+    }
+
+    // Produce slightly more samples when the buffer runs low and fewer when it fills up
+    fn rate_adjustment(&self) -> f64 {
+        let fill = self.buffer.lock().unwrap().len() as f64 / self.target_fill as f64;
+        1.0 + (1.0 - fill).clamp(-1.0, 1.0) * MAX_RATE_DELTA
+    }
+
+    // The correction is only applied to the device output, so recordings keep the nominal rate
+    fn resample(&mut self, buf_left: &[f32], buf_right: &[f32]) -> Vec<(f32, f32)> {
+        let step = 1.0 / self.rate_adjustment();
+        let length = buf_left.len().min(buf_right.len());
+        let mut samples = Vec::with_capacity(length + length / 100 + 1);
+        while self.resample_position < length as f64 {
+            let index = self.resample_position as usize;
+            let fraction = (self.resample_position - index as f64) as f32;
+            let (l0, r0) = if index == 0 {
+                self.previous_sample
+            } else {
+                (buf_left[index - 1], buf_right[index - 1])
+            };
+            let (l1, r1) = (buf_left[index], buf_right[index]);
+            samples.push((l0 + (l1 - l0) * fraction, r0 + (r1 - r0) * fraction));
+            self.resample_position += step;
+        }
+        self.resample_position -= length as f64;
+        if length > 0 {
+            self.previous_sample = (buf_left[length - 1], buf_right[length - 1]);
+        }
+        samples
+    }
+}
+
+impl AudioSink for AudioPlayer {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate.0
+    }
+
+    fn add_samples(&mut self, buf_left: &[f32], buf_right: &[f32]) {
+        let samples = if self.dynamic_rate_control {
+            self.resample(buf_left, buf_right)
+        } else {
+            buf_left
+                .iter()
+                .copied()
+                .zip(buf_right.iter().copied())
+                .collect()
+        };
+        let mut buffer = self.buffer.lock().unwrap();
+
+        // Anything beyond twice the latency target is dropped to keep the delay bounded
+        for (l, r) in samples {
+            if buffer.len() < self.target_fill * 2 {
+                buffer.push_back((l * self.volume, r * self.volume));
+            }
+        }
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+    }
+
+    fn underflowed(&self) -> bool {
+        self.buffer.lock().unwrap().is_empty()
+    }
+}
+
+fn cpal_thread<T: Sample + FromSample<f32>>(
+    outbuffer: &mut [T],
+    audio_buffer: &Arc<Mutex<VecDeque<(f32, f32)>>>,
+) {
+    let mut inbuffer = audio_buffer.lock().unwrap();
+    let outlen = inbuffer.len().min(outbuffer.len() / 2);
+    for (i, (in_l, in_r)) in inbuffer.drain(..outlen).enumerate() {
+        outbuffer[i * 2] = T::from_sample(in_l);
+        outbuffer[i * 2 + 1] = T::from_sample(in_r);
+    }
+
+    // Output silence on underflow instead of stale data
+    for sample in &mut outbuffer[outlen * 2..] {
+        *sample = T::EQUILIBRIUM;
+    }
+}
@@ -0,0 +1,378 @@
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+use Mnemosyne::audio::NullSink;
+use Mnemosyne::gb::filters::FilterSettings;
+use Mnemosyne::gb::joypad::Button;
+use Mnemosyne::gb::movie::InputMovie;
+use Mnemosyne::gb::palette::PalettePreset;
+use Mnemosyne::gb::run_until::{StopCondition, CYCLES_PER_FRAME};
+use Mnemosyne::gb::screenshot::ScreenshotMode;
+use Mnemosyne::gb::GameBoy;
+
+const USAGE: &str = "\
+Runs a ROM without a window, audio device or GPU
+
+Usage: mnemosyne-headless <rom> [options]
+
+Run limits, defaults to 3600 frames when neither is given:
+  --frames <n>             Stop after n frames, every 17556 M-cycles count as one while the LCD is off
+  --cycles <n>             Stop after n M-cycles
+
+Stop conditions, the run passes when one of them is met before the limit:
+  --until-breakpoint       Stop on a software breakpoint (LD B,B)
+  --until-serial <text>    Stop once the serial output contains text
+  --until-pc <address>     Stop when PC reaches the address, e.g. 0x0150
+  --fail-serial <text>     Stop and fail once the serial output contains text
+  --mooneye                Stop on a breakpoint and require the mooneye Fibonacci registers
+
+Input:
+  --movie <file>           Input movie, one `<frame> <buttons>` line per change, frames are counted
+                           the same way as for --frames
+
+Output:
+  --screenshot <file>      Save the last frame as PNG
+  --raw                    Store color IDs in the screenshot instead of grayscale
+  --dump-serial            Print the serial output
+  --dump-registers         Print the CPU registers
+  --dump-ram <file>        Write WRAM, HRAM and cartridge RAM to a file
+
+Exit codes: 0 on pass, 1 on fail, 2 on invalid arguments or errors";
+
+const DEFAULT_FRAMES: u64 = 3600;
+
+#[derive(Default)]
+struct Options {
+    rom: String,
+    frames: Option<u64>,
+    cycles: Option<u64>,
+    until_breakpoint: bool,
+    until_serial: Option<String>,
+    until_pc: Option<u16>,
+    fail_serial: Option<String>,
+    mooneye: bool,
+    movie: Option<String>,
+    screenshot: Option<String>,
+    raw: bool,
+    dump_serial: bool,
+    dump_registers: bool,
+    dump_ram: Option<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut rom = None;
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+            match arg.as_str() {
+                "--frames" => options.frames = Some(parse_number(&value()?)?),
+                "--cycles" => options.cycles = Some(parse_number(&value()?)?),
+                "--until-breakpoint" => options.until_breakpoint = true,
+                "--until-serial" => options.until_serial = Some(value()?),
+                "--until-pc" => {
+                    let address = parse_number(&value()?)?;
+                    options.until_pc =
+                        Some(u16::try_from(address).map_err(|_| "PC out of range".to_string())?);
+                }
+                "--fail-serial" => options.fail_serial = Some(value()?),
+                "--mooneye" => {
+                    options.mooneye = true;
+                    options.until_breakpoint = true;
+                }
+                "--movie" => options.movie = Some(value()?),
+                "--screenshot" => options.screenshot = Some(value()?),
+                "--raw" => options.raw = true,
+                "--dump-serial" => options.dump_serial = true,
+                "--dump-registers" => options.dump_registers = true,
+                "--dump-ram" => options.dump_ram = Some(value()?),
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+                _ if rom.is_none() => rom = Some(arg.clone()),
+                _ => return Err(format!("Unexpected argument {}", arg)),
+            }
+        }
+        options.rom = rom.ok_or("No ROM given")?;
+        if options.frames.is_none() && options.cycles.is_none() {
+            options.frames = Some(DEFAULT_FRAMES);
+        }
+        Ok(options)
+    }
+
+    fn has_stop_condition(&self) -> bool {
+        self.until_breakpoint || self.until_serial.is_some() || self.until_pc.is_some()
+    }
+}
+
+fn parse_number(text: &str) -> Result<u64, String> {
+    let result = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse::<u64>(),
+    };
+    result.map_err(|_| format!("Invalid number {}", text))
+}
+
+enum Outcome {
+    Pass(String),
+    Fail(String),
+}
+
+fn run(gameboy: &mut GameBoy, options: &Options, movie: &InputMovie) -> Outcome {
+    let mut conditions = Vec::new();
+    if options.until_breakpoint {
+        conditions.push(StopCondition::SoftwareBreakpoint);
+    }
+    if let Some(pc) = options.until_pc {
+        conditions.push(StopCondition::Pc(pc));
+    }
+    if let Some(text) = &options.fail_serial {
+        conditions.push(StopCondition::SerialContains(text.clone()));
+    }
+    if let Some(text) = &options.until_serial {
+        conditions.push(StopCondition::SerialContains(text.clone()));
+    }
+
+    let mut cycles: u64 = 0;
+    // Frames produced by the PPU, plus one for every frame's worth of M-cycles without one so the
+    // frame limit and the movie keep advancing while the LCD is off
+    let mut frame = gameboy.frame_count();
+    let mut held: Vec<Button> = Vec::new();
+    apply_movie(gameboy, movie, frame, &mut held);
+
+    loop {
+        let frame_limit = options.frames.is_some_and(|frames| frame >= frames);
+        let cycle_limit = options.cycles.is_some_and(|limit| cycles >= limit);
+        if frame_limit || cycle_limit {
+            let message = format!("Stopped after {} frames and {} M-cycles", frame, cycles);
+            return if options.has_stop_condition() {
+                Outcome::Fail(format!("{} without meeting the stop condition", message))
+            } else {
+                Outcome::Pass(message)
+            };
+        }
+
+        // Runs at most a frame at a time so the movie input can be applied in between
+        let mut step = conditions.clone();
+        step.push(StopCondition::Frames(1));
+        if let Some(limit) = options.cycles {
+            step.push(StopCondition::Cycles(limit - cycles));
+        }
+        step.push(StopCondition::Cycles(CYCLES_PER_FRAME));
+        let reason = gameboy.run_until(&step);
+        cycles += reason.cycles;
+        if reason.frames > 0 || reason.cycles >= CYCLES_PER_FRAME {
+            frame += 1;
+        }
+
+        match reason.condition {
+            StopCondition::SoftwareBreakpoint if options.mooneye => return check_mooneye(gameboy),
+            StopCondition::SoftwareBreakpoint => {
+                return Outcome::Pass("Reached breakpoint".to_string());
+            }
+            StopCondition::Pc(pc) => return Outcome::Pass(format!("Reached PC {:#06X}", pc)),
+            StopCondition::SerialContains(text) if options.fail_serial.as_ref() == Some(&text) => {
+                return Outcome::Fail(format!("Serial output contains '{}'", text));
+            }
+            StopCondition::SerialContains(text) => {
+                return Outcome::Pass(format!("Serial output contains '{}'", text));
+            }
+            _ => apply_movie(gameboy, movie, frame, &mut held),
+        }
+    }
+}
+
+fn apply_movie(gameboy: &mut GameBoy, movie: &InputMovie, frame: u64, held: &mut Vec<Button>) {
+    let buttons = movie.buttons(frame);
+    for button in Button::ALL {
+        let pressed = buttons.contains(&button);
+        if pressed != held.contains(&button) {
+            gameboy.set_button(button, pressed);
+        }
+    }
+    *held = buttons.to_vec();
+}
+
+fn check_mooneye(gameboy: &mut GameBoy) -> Outcome {
+    let registers = gameboy.dump_registers();
+    let values = [
+        registers.B,
+        registers.C,
+        registers.D,
+        registers.E,
+        registers.H,
+        registers.L,
+    ];
+    if values == [3, 5, 8, 13, 21, 34] {
+        Outcome::Pass("Mooneye test passed".to_string())
+    } else {
+        Outcome::Fail(format!(
+            "Mooneye test failed, B-L registers are {:?}",
+            values
+        ))
+    }
+}
+
+fn dump(gameboy: &mut GameBoy, options: &Options) -> Result<(), String> {
+    if let Some(path) = &options.screenshot {
+        let mode = if options.raw {
+            ScreenshotMode::Raw
+        } else {
+            ScreenshotMode::Palette
+        };
+        gameboy
+            .screenshot(
+                mode,
+                &PalettePreset::Grayscale.palette(),
+                &FilterSettings::default(),
+            )
+            .save_png(path)?;
+    }
+
+    if options.dump_serial {
+        println!("{}", gameboy.serial_buffer().iter().collect::<String>());
+    }
+
+    if options.dump_registers {
+        let registers = gameboy.dump_registers();
+        println!(
+            "A: {:02X} F: {:02X} B: {:02X} C: {:02X} D: {:02X} E: {:02X} H: {:02X} L: {:02X} SP: {:04X} PC: {:04X} IME: {}",
+            registers.A,
+            registers.F,
+            registers.B,
+            registers.C,
+            registers.D,
+            registers.E,
+            registers.H,
+            registers.L,
+            registers.SP,
+            registers.PC,
+            registers.IME
+        );
+    }
+
+    if let Some(path) = &options.dump_ram {
+        fs::write(path, gameboy.snapshot_ram()).map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let movie = match &options.movie {
+        Some(path) => match InputMovie::load(path) {
+            Ok(movie) => movie,
+            Err(err) => {
+                eprintln!("Failed to load input movie: {}", err);
+                return ExitCode::from(2);
+            }
+        },
+        None => InputMovie::default(),
+    };
+
+    if !Path::new(&options.rom).is_file() {
+        eprintln!("ROM {} does not exist", options.rom);
+        return ExitCode::from(2);
+    }
+    let mut gameboy = GameBoy::with_audio_sink(Box::new(NullSink::default()));
+    gameboy.load_rom(&options.rom);
+    gameboy.skip_boot_rom();
+
+    let outcome = run(&mut gameboy, &options, &movie);
+
+    if let Err(err) = dump(&mut gameboy, &options) {
+        eprintln!("Failed to write output: {}", err);
+        return ExitCode::from(2);
+    }
+
+    match outcome {
+        Outcome::Pass(message) => {
+            eprintln!("PASS: {}", message);
+            ExitCode::SUCCESS
+        }
+        Outcome::Fail(message) => {
+            eprintln!("FAIL: {}", message);
+            ExitCode::from(1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_numbers() {
+        assert_eq!(parse_number("150"), Ok(150));
+        assert_eq!(parse_number("0x150"), Ok(0x150));
+        assert_eq!(parse_number("0xFFff"), Ok(0xFFFF));
+        assert_eq!(parse_number("0x"), Err("Invalid number 0x".to_string()));
+        assert_eq!(parse_number("-1"), Err("Invalid number -1".to_string()));
+        assert_eq!(parse_number("ten"), Err("Invalid number ten".to_string()));
+    }
+
+    #[test]
+    fn parse_options() {
+        let options = parse(&[
+            "--cycles",
+            "0x1000",
+            "game.gb",
+            "--until-serial",
+            "Passed",
+            "--until-pc",
+            "0x0150",
+            "--movie",
+            "inputs.txt",
+            "--raw",
+        ])
+        .unwrap();
+        assert_eq!(options.rom, "game.gb");
+        assert_eq!(options.cycles, Some(0x1000));
+        assert_eq!(options.frames, None);
+        assert_eq!(options.until_serial.as_deref(), Some("Passed"));
+        assert_eq!(options.until_pc, Some(0x0150));
+        assert_eq!(options.movie.as_deref(), Some("inputs.txt"));
+        assert!(options.raw);
+        assert!(!options.until_breakpoint);
+        assert!(options.has_stop_condition());
+    }
+
+    #[test]
+    fn parse_defaults() {
+        let options = parse(&["game.gb"]).unwrap();
+        assert_eq!(options.frames, Some(DEFAULT_FRAMES));
+        assert_eq!(options.cycles, None);
+        assert!(!options.has_stop_condition());
+
+        // Mooneye tests finish on a breakpoint
+        let options = parse(&["--mooneye", "game.gb"]).unwrap();
+        assert!(options.mooneye && options.until_breakpoint);
+        assert!(options.has_stop_condition());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let error = |args: &[&str]| parse(args).err().unwrap();
+        assert_eq!(error(&[]), "No ROM given");
+        assert_eq!(
+            error(&["game.gb", "--frames"]),
+            "Missing value for --frames"
+        );
+        assert_eq!(error(&["game.gb", "--frames", "x"]), "Invalid number x");
+        assert_eq!(
+            error(&["game.gb", "--until-pc", "0x10000"]),
+            "PC out of range"
+        );
+        assert_eq!(error(&["game.gb", "--fast"]), "Unknown option --fast");
+        assert_eq!(error(&["a.gb", "b.gb"]), "Unexpected argument b.gb");
+    }
+}
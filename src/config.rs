@@ -1,9 +1,13 @@
+use crate::gb::filters::FilterSettings;
+use crate::gb::palette::{colorize, ColorPalette, PalettePreset};
+use crate::gb::screenshot::ScreenshotMode;
 use arc_swap::{ArcSwap, Cache};
 use directories::ProjectDirs;
 use figment::providers::{Format, Serialized, Toml};
 use figment::Figment;
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs;
 use std::ops::Deref;
 use std::path::PathBuf;
@@ -30,10 +34,93 @@ pub(crate) struct GameBoyConfig {
     pub(crate) save_path: String,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct AudioConfig {
+    // None uses the default output device
+    pub(crate) output_device: Option<String>,
+    pub(crate) sample_rate: u32,
+    pub(crate) latency_ms: u32,
+    pub(crate) dynamic_rate_control: bool,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            output_device: None,
+            sample_rate: 44100,
+            latency_ms: 60,
+            dynamic_rate_control: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub(crate) enum PaletteSelection {
+    Preset(PalettePreset),
+    Custom(String),
+    // CGB boot ROM colorization based on the cartridge title
+    Automatic,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct CustomPalette {
+    pub(crate) name: String,
+    pub(crate) palette: ColorPalette,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct PaletteConfig {
+    pub(crate) selected: PaletteSelection,
+    pub(crate) custom: Vec<CustomPalette>,
+    // Overrides keyed by ROM file name
+    pub(crate) per_game: HashMap<String, PaletteSelection>,
+}
+
+impl Default for PaletteConfig {
+    fn default() -> Self {
+        PaletteConfig {
+            selected: PaletteSelection::Preset(PalettePreset::Grayscale),
+            custom: Vec::new(),
+            per_game: HashMap::new(),
+        }
+    }
+}
+
+impl PaletteConfig {
+    pub(crate) fn palette_for(&self, rom_name: Option<&str>, rom: &[u8]) -> ColorPalette {
+        let selection = rom_name
+            .and_then(|name| self.per_game.get(name))
+            .unwrap_or(&self.selected);
+        match selection {
+            PaletteSelection::Preset(preset) => preset.palette(),
+            PaletteSelection::Custom(name) => self
+                .custom
+                .iter()
+                .find(|custom| custom.name == *name)
+                .map(|custom| custom.palette)
+                .unwrap_or(PalettePreset::Grayscale.palette()),
+            PaletteSelection::Automatic => colorize(rom),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default, Debug)]
+pub(crate) enum RendererBackend {
+    #[default]
+    Vulkan,
+    // Presents the game screen through an egui texture, used when the Vulkan pipeline fails
+    Software,
+}
+
 #[derive(Default, Serialize, Deserialize, Clone)]
 pub(crate) struct Config {
     pub(crate) ui_config: UIConfig,
     pub(crate) gameboy_config: GameBoyConfig,
+    pub(crate) audio_config: AudioConfig,
+    pub(crate) palette_config: PaletteConfig,
+    pub(crate) filter_config: FilterSettings,
+    pub(crate) screenshot_mode: ScreenshotMode,
+    pub(crate) renderer: RendererBackend,
 }
 
 static GLOBAL_CONFIG: LazyLock<ArcSwap<Config>> = LazyLock::new(|| {
@@ -56,6 +143,12 @@ thread_local! {
     pub static THREAD_LOCAL_CONFIG: RefCell<Cache<&'static ArcSwap<Config>, Arc<Config>>> = RefCell::new(Cache::from(GLOBAL_CONFIG.deref()));
 }
 
+pub(crate) fn update_config(update: impl FnOnce(&mut Config)) {
+    let mut config = Config::clone(&GLOBAL_CONFIG.load());
+    update(&mut config);
+    GLOBAL_CONFIG.store(Arc::new(config));
+}
+
 pub(crate) fn save_config() {
     let config = THREAD_LOCAL_CONFIG.with(|c| c.borrow_mut().load().clone());
 
@@ -174,19 +174,7 @@ impl EguiRenderer {
         vulkano_context: &VulkanoContext,
         vulkano_windows: &VulkanoWindows,
     ) {
-        let egui_context = Context::default();
-
-        egui_material_icons::initialize(&egui_context);
-        egui_extras::install_image_loaders(&egui_context);
-
-        let cached_ehttp_loader = Arc::new(CachedEhttpLoader::default());
-        egui_context.add_bytes_loader(cached_ehttp_loader.clone());
-
-        let project_dirs = ProjectDirs::from("", "", "Mnemosyne").unwrap();
-        let mut cache_path = PathBuf::new();
-        cache_path.push(project_dirs.cache_dir());
-        cache_path.push("boxart/");
-        std::fs::create_dir_all(&cache_path).expect("Failed to create cache directory");
+        let egui_context = create_egui_context();
 
         let egui_winit_state = State::new(
             egui_context.clone(),
@@ -876,6 +864,25 @@ impl EguiRenderer {
     }
 }
 
+// Shared by the Vulkan and the software presentation path
+pub(crate) fn create_egui_context() -> Context {
+    let egui_context = Context::default();
+
+    egui_material_icons::initialize(&egui_context);
+    egui_extras::install_image_loaders(&egui_context);
+
+    let cached_ehttp_loader = Arc::new(CachedEhttpLoader::default());
+    egui_context.add_bytes_loader(cached_ehttp_loader.clone());
+
+    let project_dirs = ProjectDirs::from("", "", "Mnemosyne").unwrap();
+    let mut cache_path = PathBuf::new();
+    cache_path.push(project_dirs.cache_dir());
+    cache_path.push("boxart/");
+    std::fs::create_dir_all(&cache_path).expect("Failed to create cache directory");
+
+    egui_context
+}
+
 pub struct CallbackContext<'a> {
     pub builder: &'a mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
     pub resources: &'a VulkanoContext,
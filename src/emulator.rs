@@ -1,7 +1,21 @@
+use crate::audio::default_sink;
+use crate::config::THREAD_LOCAL_CONFIG;
+use crate::gb::apu::ChannelStatus;
+use crate::gb::cheats::{load_cheats, save_cheats, Cheat};
+use crate::gb::events::Event;
+use crate::gb::gbs::GbsFile;
+use crate::gb::joypad::Button;
+use crate::gb::palette::{ColorPalette, DmgPalette};
+use crate::gb::ppu::PixelSource;
+use crate::gb::ram_search::{Comparison, RamSearch, RamSearchResult, SearchFormat, SearchTarget};
 use crate::gb::registers::Registers;
-use crate::gb::GameBoy;
+use crate::gb::screenshot::screenshot_path;
+use crate::gb::video::VideoFormat;
+use crate::gb::{GameBoy, Memories};
+use crate::scripting::{OverlayShape, ScriptEngine};
 use crate::ui::UIState;
 use crate::vulkan_renderer::EmulatorRenderer;
+use log::{log, Level};
 use puffin::{internal_profile_reporter, ThreadProfiler};
 use std::path::Path;
 use std::sync::mpsc::{Receiver, SyncSender};
@@ -11,6 +25,7 @@ use std::thread::JoinHandle;
 use std::time::Duration;
 use vulkano::buffer::Subbuffer;
 use winit::event::{ElementState, KeyEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
 
 pub(crate) enum SyncMessage {
     FrameStart(UIState),
@@ -34,8 +49,49 @@ pub enum EmulatorState {
 pub struct GameBoyState {
     pub(crate) registers: Registers,
     pub(crate) ram: Vec<u8>,
+    pub(crate) rom_banks: usize,
+    pub(crate) ram_banks: usize,
+    pub(crate) io_registers: Vec<u8>,
+    pub(crate) vram: Vec<u8>,
+    pub(crate) oam: Vec<u8>,
+    pub(crate) events: Vec<Event>,
     pub(crate) hit_breakpoint: bool,
+    pub(crate) frame_count: u64,
     pub(crate) frame_buffer: Vec<u8>,
+    pub(crate) palette_buffer: Vec<DmgPalette>,
+    pub(crate) color_palette: ColorPalette,
+    pub(crate) rom_name: Option<String>,
+    pub(crate) pixel_provenance: Vec<PixelSource>,
+    pub(crate) overlay: Vec<OverlayShape>,
+    pub(crate) cheats: Vec<Cheat>,
+    pub(crate) ram_search: RamSearchState,
+    pub(crate) audio: AudioDebugState,
+    pub(crate) recording_audio: bool,
+    pub(crate) logging_vgm: bool,
+    pub(crate) recording_video: bool,
+    pub(crate) gbs_player: Option<GbsPlayerState>,
+}
+
+#[derive(Default)]
+pub struct RamSearchState {
+    pub(crate) candidate_count: usize,
+    pub(crate) results: Vec<RamSearchResult>,
+    pub(crate) watch_values: Vec<Option<u32>>,
+}
+
+#[derive(Default)]
+pub struct AudioDebugState {
+    pub(crate) channels: [ChannelStatus; 4],
+    pub(crate) scope: [Vec<f32>; 5],
+}
+
+pub struct GbsPlayerState {
+    pub(crate) title: String,
+    pub(crate) author: String,
+    pub(crate) copyright: String,
+    pub(crate) song_count: u8,
+    pub(crate) track: u8,
+    pub(crate) elapsed: f64,
 }
 
 pub enum EmulatorControlMessage {
@@ -45,8 +101,11 @@ pub enum EmulatorControlMessage {
     Pause,
     FastForward(u8),
     FastRewind(u8),
+    // Audio output, recreated from the current audio config
+    ReloadAudio,
     // Load / save
     Load(String),
+    LoadGbs(String),
     LoadState,
     SaveState,
     // Debugging
@@ -56,6 +115,30 @@ pub enum EmulatorControlMessage {
     StepOut,
     Breakpoints,
     Watchpoints,
+    WriteMemory(Memories, usize, u8),
+    // Scripting
+    LoadScript(String),
+    UnloadScript,
+    // Cheats
+    AddCheat(Cheat),
+    RemoveCheat(usize),
+    ToggleCheat(usize, bool),
+    // Recording
+    StartAudioRecording(String, bool),
+    StopAudioRecording,
+    StartVgmLogging(String),
+    SetVgmLoopPoint,
+    StopVgmLogging,
+    // Path, format, apply filters, record audio
+    StartVideoRecording(String, VideoFormat, bool, bool),
+    StopVideoRecording,
+    // Saves the last frame to the screenshot folder of the loaded ROM
+    TakeScreenshot,
+    // GBS player
+    PlayGbsTrack(u8),
+    // RAM search
+    RamSearchReset,
+    RamSearchFilter(SearchFormat, Comparison, SearchTarget),
 }
 
 #[derive(PartialEq)]
@@ -93,6 +176,11 @@ impl Emulator {
 
     fn run(&mut self) {
         let mut gameboy = GameBoy::new();
+        let mut script_engine = ScriptEngine::new();
+        let mut rom_path: Option<String> = None;
+        let mut cheats: Vec<Cheat> = Vec::new();
+        let mut ram_search = RamSearch::new();
+        let mut gbs: Option<(GbsFile, u8)> = None;
 
         let mut hit_breakpoint: bool = false;
 
@@ -103,12 +191,72 @@ impl Emulator {
                 SyncMessage::FrameStart(state) => {
                     {
                         puffin::profile_scope!("sync to render thread");
-                        let emu_state = EmulatorState::new(
-                            gameboy.dump_registers(),
-                            gameboy.dump_ram(state.selected_memory),
+                        let mut ram_search_state = RamSearchState::default();
+                        if state.show_ram_search || !state.watch_list.is_empty() {
+                            let snapshot = gameboy.snapshot_ram();
+                            ram_search_state.candidate_count = ram_search.candidate_count();
+                            ram_search_state.results =
+                                ram_search.results(&snapshot, state.ram_search_format, 100);
+                            ram_search_state.watch_values = state
+                                .watch_list
+                                .iter()
+                                .map(|watch| watch.format.read(&snapshot, watch.index))
+                                .collect();
+                        }
+                        gameboy.set_pixel_provenance(state.inspect_pixels);
+                        gameboy.set_layer_visibility(state.layer_visibility);
+                        gameboy.set_event_logging(state.show_event_viewer);
+                        gameboy.set_audio_channels(state.audio_channels_enabled());
+                        gameboy.set_audio_scope(state.show_audio_debugger);
+                        gameboy.set_volume(state.volume / 100.0);
+                        let mut audio_state = AudioDebugState::default();
+                        if state.show_audio_debugger {
+                            audio_state.channels = gameboy.audio_channels();
+                            audio_state.scope = gameboy.audio_scope();
+                        }
+                        let (vram, oam) = if state.show_vram_viewer {
+                            (gameboy.dump_vram(), gameboy.dump_oam())
+                        } else {
+                            (Vec::new(), Vec::new())
+                        };
+                        let rom_name = rom_path.as_deref().and_then(|path| {
+                            Path::new(path)
+                                .file_name()
+                                .map(|name| name.to_string_lossy().to_string())
+                        });
+                        let color_palette = THREAD_LOCAL_CONFIG.with(|c| {
+                            c.borrow_mut()
+                                .load()
+                                .palette_config
+                                .palette_for(rom_name.as_deref(), gameboy.rom())
+                        });
+                        let emu_state = EmulatorState::GameBoy(GameBoyState {
+                            registers: gameboy.dump_registers(),
+                            ram: gameboy.dump_ram(state.selected_memory),
+                            rom_banks: gameboy.rom_bank_count(),
+                            ram_banks: gameboy.ram_bank_count(),
+                            io_registers: gameboy.dump_io_registers(),
+                            vram,
+                            oam,
+                            events: gameboy.events(),
                             hit_breakpoint,
-                            gameboy.get_framebuffer(),
-                        );
+                            frame_count: gameboy.frame_count(),
+                            frame_buffer: gameboy.get_framebuffer(),
+                            palette_buffer: gameboy.get_palette_buffer(),
+                            color_palette,
+                            rom_name,
+                            pixel_provenance: gameboy.pixel_provenance(),
+                            overlay: script_engine.overlay(),
+                            cheats: cheats.clone(),
+                            ram_search: ram_search_state,
+                            audio: audio_state,
+                            recording_audio: gameboy.is_recording_audio(),
+                            logging_vgm: gameboy.is_logging_vgm(),
+                            recording_video: gameboy.is_recording_video(),
+                            gbs_player: gbs
+                                .as_ref()
+                                .map(|(gbs, track)| GbsPlayerState::new(gbs, *track, &gameboy)),
+                        });
                         let mut renderer = self
                             .emulator_renderer
                             .lock()
@@ -123,28 +271,163 @@ impl Emulator {
                                 self.runtime_state = RuntimeState::Running;
                             }
                             EmulatorControlMessage::Load(path) => {
+                                gameboy.stop_audio_recording();
+                                gameboy.stop_vgm_logging();
+                                gameboy.stop_video_recording();
                                 gameboy = GameBoy::new();
                                 gameboy.load_rom(&path);
                                 self.runtime_state = RuntimeState::Stopped;
                                 // TODO: skip bootrom or not based on settings
                                 gameboy.skip_boot_rom();
+                                script_engine.attach(&mut gameboy);
+                                cheats = load_cheats(gameboy.rom());
+                                gameboy.set_cheats(&cheats);
+                                rom_path = Some(path);
+                                gbs = None;
+                            }
+                            EmulatorControlMessage::LoadGbs(path) => match GbsFile::load(&path) {
+                                Ok(file) => {
+                                    let track = file.first_song - 1;
+                                    gameboy.stop_audio_recording();
+                                    gameboy.stop_vgm_logging();
+                                    gameboy.stop_video_recording();
+                                    gameboy = GameBoy::new();
+                                    gameboy.load_gbs(&file, track);
+                                    self.runtime_state = RuntimeState::Stopped;
+                                    script_engine.attach(&mut gameboy);
+                                    cheats.clear();
+                                    rom_path = None;
+                                    gbs = Some((file, track));
+                                }
+                                Err(error) => {
+                                    log!(Level::Error, "Failed to load GBS file: {}", error);
+                                }
+                            },
+                            EmulatorControlMessage::PlayGbsTrack(track) => {
+                                if let Some((file, current_track)) = &mut gbs {
+                                    if track < file.song_count {
+                                        gameboy.stop_audio_recording();
+                                        gameboy.stop_vgm_logging();
+                                        gameboy.stop_video_recording();
+                                        gameboy = GameBoy::new();
+                                        gameboy.load_gbs(file, track);
+                                        script_engine.attach(&mut gameboy);
+                                        *current_track = track;
+                                    }
+                                }
+                            }
+                            EmulatorControlMessage::ReloadAudio => {
+                                gameboy.set_audio_sink(default_sink());
                             }
                             EmulatorControlMessage::Pause => {
                                 self.runtime_state = RuntimeState::Paused;
                             }
                             EmulatorControlMessage::Stop => {
                                 self.runtime_state = RuntimeState::Stopped;
+                                gameboy.stop_audio_recording();
+                                gameboy.stop_vgm_logging();
+                                gameboy.stop_video_recording();
                                 gameboy = GameBoy::new();
+                                script_engine.attach(&mut gameboy);
+                                cheats.clear();
+                                rom_path = None;
+                                gbs = None;
                             }
                             EmulatorControlMessage::StepInto
                             | EmulatorControlMessage::StepOut
                             | EmulatorControlMessage::StepOver => {
                                 self.runtime_state = RuntimeState::Stepping;
                             }
+                            EmulatorControlMessage::WriteMemory(memory, offset, value) => {
+                                gameboy.edit_ram(memory, offset, value);
+                            }
+                            EmulatorControlMessage::LoadScript(path) => {
+                                script_engine.load(&path, &mut gameboy);
+                            }
+                            EmulatorControlMessage::UnloadScript => {
+                                script_engine.unload(&mut gameboy);
+                            }
+                            EmulatorControlMessage::AddCheat(cheat) => {
+                                cheats.push(cheat);
+                                Self::update_cheats(&mut gameboy, &rom_path, &cheats);
+                            }
+                            EmulatorControlMessage::RemoveCheat(index) => {
+                                if index < cheats.len() {
+                                    cheats.remove(index);
+                                    Self::update_cheats(&mut gameboy, &rom_path, &cheats);
+                                }
+                            }
+                            EmulatorControlMessage::ToggleCheat(index, enabled) => {
+                                if let Some(cheat) = cheats.get_mut(index) {
+                                    cheat.enabled = enabled;
+                                    Self::update_cheats(&mut gameboy, &rom_path, &cheats);
+                                }
+                            }
+                            EmulatorControlMessage::StartAudioRecording(path, stems) => {
+                                if let Err(err) =
+                                    gameboy.start_audio_recording(Path::new(&path), stems)
+                                {
+                                    log!(Level::Error, "Failed to start audio recording: {}", err);
+                                }
+                            }
+                            EmulatorControlMessage::StopAudioRecording => {
+                                gameboy.stop_audio_recording();
+                            }
+                            EmulatorControlMessage::StartVgmLogging(path) => {
+                                if let Err(err) = gameboy.start_vgm_logging(Path::new(&path)) {
+                                    log!(Level::Error, "Failed to start VGM logging: {}", err);
+                                }
+                            }
+                            EmulatorControlMessage::SetVgmLoopPoint => {
+                                gameboy.set_vgm_loop_point();
+                            }
+                            EmulatorControlMessage::StopVgmLogging => {
+                                gameboy.stop_vgm_logging();
+                            }
+                            EmulatorControlMessage::StartVideoRecording(
+                                path,
+                                format,
+                                filters,
+                                audio,
+                            ) => {
+                                let palette = Self::color_palette(&gameboy, &rom_path);
+                                let filters = filters.then(|| {
+                                    THREAD_LOCAL_CONFIG
+                                        .with(|c| c.borrow_mut().load().filter_config)
+                                });
+                                if let Err(err) = gameboy.start_video_recording(
+                                    Path::new(&path),
+                                    format,
+                                    palette,
+                                    filters,
+                                    audio,
+                                ) {
+                                    log!(Level::Error, "Failed to start video recording: {}", err);
+                                }
+                            }
+                            EmulatorControlMessage::StopVideoRecording => {
+                                gameboy.stop_video_recording();
+                            }
+                            EmulatorControlMessage::TakeScreenshot => {
+                                Self::take_screenshot(&gameboy, &rom_path);
+                            }
+                            EmulatorControlMessage::RamSearchReset => {
+                                ram_search.reset(gameboy.snapshot_ram());
+                            }
+                            EmulatorControlMessage::RamSearchFilter(format, comparison, target) => {
+                                ram_search.filter(
+                                    gameboy.snapshot_ram(),
+                                    format,
+                                    comparison,
+                                    target,
+                                );
+                            }
                             _ => {}
                         }
                     }
 
+                    script_engine.check_for_changes(&mut gameboy);
+
                     if self.runtime_state != RuntimeState::Running {
                         previous_time = fastant::Instant::now();
                     }
@@ -167,6 +450,7 @@ impl Emulator {
                         while cycles < (elapsed * (4194304.0 / 4.0)) as u64 {
                             let (hit_breakpoint_now, cycles_spent) = gameboy.tick();
                             cycles += cycles_spent as u64;
+                            script_engine.after_tick(&mut gameboy);
 
                             if hit_breakpoint_now {
                                 hit_breakpoint = hit_breakpoint_now;
@@ -174,10 +458,11 @@ impl Emulator {
                             }
 
                             while let Ok(key_event) = self.rx_controls.try_recv() {
-                                if key_event.state == ElementState::Pressed {
-                                    gameboy.key_pressed(key_event.physical_key);
-                                } else {
-                                    gameboy.key_released(key_event.physical_key);
+                                if let Some(button) = key_button(key_event.physical_key) {
+                                    gameboy.set_button(
+                                        button,
+                                        key_event.state == ElementState::Pressed,
+                                    );
                                 }
                             }
                         }
@@ -202,7 +487,7 @@ impl Emulator {
                                     tp.begin_scope_with_offset(
                                         *scope_id,
                                         "".as_ref(),
-                                        -(gameboy.cpu.time_ppu.as_nanos() as i64),
+                                        -(gameboy.cpu.mmu.time_ppu.as_nanos() as i64),
                                     )
                                 });
                                 ThreadProfiler::call(|tp| tp.end_scope(start_stream_offset));
@@ -226,18 +511,18 @@ impl Emulator {
                                     tp.begin_scope_with_offset(
                                         *scope_id2,
                                         "".as_ref(),
-                                        -(gameboy.cpu.time_ppu.as_nanos() as i64
-                                            + gameboy.cpu.time_io.as_nanos() as i64),
+                                        -(gameboy.cpu.mmu.time_ppu.as_nanos() as i64
+                                            + gameboy.cpu.mmu.time_io.as_nanos() as i64),
                                     )
                                 });
                                 ThreadProfiler::call(|tp| {
                                     tp.end_scope_with_offset(
                                         start_stream_offset2,
-                                        -(gameboy.cpu.time_ppu.as_nanos() as i64),
+                                        -(gameboy.cpu.mmu.time_ppu.as_nanos() as i64),
                                     )
                                 });
-                                gameboy.cpu.time_ppu = Duration::new(0, 0);
-                                gameboy.cpu.time_io = Duration::new(0, 0);
+                                gameboy.cpu.mmu.time_ppu = Duration::new(0, 0);
+                                gameboy.cpu.mmu.time_io = Duration::new(0, 0);
                             }
                         }
                     }
@@ -245,10 +530,12 @@ impl Emulator {
                     if self.runtime_state == RuntimeState::Stepping {
                         puffin::profile_scope!("emulate tick");
                         gameboy.tick();
+                        script_engine.after_tick(&mut gameboy);
                         self.runtime_state = RuntimeState::Paused;
                     }
                 }
                 SyncMessage::Exit => {
+                    gameboy.stop_video_recording();
                     gameboy.cpu.mmu.mbc.save_ram();
                     return;
                 }
@@ -258,20 +545,69 @@ impl Emulator {
             }
         }
     }
-}
 
-impl EmulatorState {
-    pub(crate) fn new(
-        registers: Registers,
-        ram: Vec<u8>,
-        hit_breakpoint: bool,
-        frame_buffer: Vec<u8>,
-    ) -> Self {
-        EmulatorState::GameBoy(GameBoyState {
-            registers,
-            ram,
-            hit_breakpoint,
-            frame_buffer,
+    fn update_cheats(gameboy: &mut GameBoy, rom_path: &Option<String>, cheats: &[Cheat]) {
+        gameboy.set_cheats(cheats);
+        if rom_path.is_some() {
+            if let Err(err) = save_cheats(gameboy.rom(), cheats) {
+                log!(Level::Error, "Failed to save cheats: {}", err);
+            }
+        }
+    }
+
+    fn color_palette(gameboy: &GameBoy, rom_path: &Option<String>) -> ColorPalette {
+        let rom_name = rom_path.as_deref().and_then(|path| {
+            Path::new(path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        });
+        THREAD_LOCAL_CONFIG.with(|c| {
+            c.borrow_mut()
+                .load()
+                .palette_config
+                .palette_for(rom_name.as_deref(), gameboy.rom())
         })
     }
+
+    fn take_screenshot(gameboy: &GameBoy, rom_path: &Option<String>) {
+        let config = THREAD_LOCAL_CONFIG.with(|c| c.borrow_mut().load().clone());
+        let palette = Self::color_palette(gameboy, rom_path);
+        let screenshot =
+            gameboy.screenshot(config.screenshot_mode, &palette, &config.filter_config);
+
+        let result = screenshot_path(rom_path.as_deref())
+            .and_then(|path| screenshot.save_png(&path).map(|_| path));
+        match result {
+            Ok(path) => log!(Level::Info, "Saved screenshot to {}", path.display()),
+            Err(err) => log!(Level::Error, "Failed to save screenshot: {}", err),
+        }
+    }
+}
+
+impl GbsPlayerState {
+    fn new(gbs: &GbsFile, track: u8, gameboy: &GameBoy) -> Self {
+        GbsPlayerState {
+            title: gbs.title.clone(),
+            author: gbs.author.clone(),
+            copyright: gbs.copyright.clone(),
+            song_count: gbs.song_count,
+            track,
+            // The LCD keeps running during playback, so frames track the time played
+            elapsed: gameboy.frame_count() as f64 * 70224.0 / 4194304.0,
+        }
+    }
+}
+
+fn key_button(physical_key: PhysicalKey) -> Option<Button> {
+    match physical_key {
+        PhysicalKey::Code(KeyCode::ArrowRight) => Some(Button::Right),
+        PhysicalKey::Code(KeyCode::ArrowLeft) => Some(Button::Left),
+        PhysicalKey::Code(KeyCode::ArrowUp) => Some(Button::Up),
+        PhysicalKey::Code(KeyCode::ArrowDown) => Some(Button::Down),
+        PhysicalKey::Code(KeyCode::KeyA) => Some(Button::A),
+        PhysicalKey::Code(KeyCode::KeyS) => Some(Button::B),
+        PhysicalKey::Code(KeyCode::KeyD) => Some(Button::Select),
+        PhysicalKey::Code(KeyCode::KeyF) => Some(Button::Start),
+        _ => None,
+    }
 }
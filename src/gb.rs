@@ -1,22 +1,63 @@
-use crate::audio::AudioPlayer;
+use crate::audio::{default_sink, AudioSink};
+use crate::gb::apu::ChannelStatus;
 use crate::gb::breakpoints::Breakpoints;
+use crate::gb::cheats::{Cheat, Cheats};
 use crate::gb::cpu::CPU;
+use crate::gb::events::Event;
+use crate::gb::filters::FilterSettings;
+use crate::gb::gbs::GbsFile;
+use crate::gb::joypad::Button;
+use crate::gb::mbc::create_GBS_mapper;
 use crate::gb::mmu::MMU;
+use crate::gb::palette::{ColorPalette, DmgPalette};
+use crate::gb::ppu::{LayerVisibility, PixelSource};
 use crate::gb::registers::Registers;
-use crate::ui::Memories;
+use crate::gb::run_until::{StopCondition, StopReason, CYCLES_PER_FRAME, TIMEOUT_CHECK_INTERVAL};
+use crate::gb::screenshot::{Screenshot, ScreenshotMode};
+use crate::gb::video::{VideoFormat, VideoRecorder};
 use intbits::Bits;
-use winit::keyboard::{KeyCode, PhysicalKey};
+use std::path::Path;
+use std::time::Instant;
 
-mod apu;
+pub(crate) mod apu;
 pub(crate) mod breakpoints;
+pub mod bus;
+pub mod cheats;
 pub mod cpu;
 pub(crate) mod disassembler;
+pub(crate) mod events;
+pub mod filters;
+pub mod gbs;
 mod io_registers;
+pub mod joypad;
 mod mbc;
 pub mod mmu;
-mod ppu;
+pub mod movie;
+pub mod palette;
+pub(crate) mod ppu;
+pub mod ram_search;
 pub mod registers;
+#[cfg(feature = "frontend")]
 pub mod renderer;
+pub mod run_until;
+pub mod screenshot;
+#[cfg(feature = "frontend")]
+pub(crate) mod software_renderer;
+pub mod video;
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Memories {
+    Bus,
+    ROMBank(usize),
+    ExternalRAM(usize),
+    WRAM1,
+    WRAM2,
+    TileData,
+    BackgroundMaps,
+    OAM,
+    HRAM,
+    IO,
+}
 
 pub struct GameBoy {
     pub(crate) cpu: CPU,
@@ -24,9 +65,12 @@ pub struct GameBoy {
 
 impl GameBoy {
     pub fn new() -> Self {
+        GameBoy::with_audio_sink(default_sink())
+    }
+
+    pub fn with_audio_sink(audio_sink: Box<dyn AudioSink>) -> Self {
         let registers = Registers::new();
-        let audio_player = AudioPlayer::new();
-        let mmu = MMU::new(audio_player);
+        let mmu = MMU::new(audio_sink);
         let cpu = CPU::new(registers, mmu);
         GameBoy { cpu }
     }
@@ -35,15 +79,95 @@ impl GameBoy {
         self.cpu.mmu.load_rom(rom_name);
     }
 
+    // Maps the GBS payload with a small driver and starts playing the given song (0 based)
+    pub fn load_gbs(&mut self, gbs: &GbsFile, song: u8) {
+        self.cpu.mmu.mbc = create_GBS_mapper(gbs.rom_image(song));
+        self.skip_boot_rom();
+    }
+
     pub fn tick(&mut self) -> (bool, u32) {
         let (hit_breakpoint, cycles) = self.cpu.process_instruction();
         (hit_breakpoint, cycles)
     }
 
+    // Conditions are checked after every instruction, in order. At least one is needed, there is
+    // nothing to stop on otherwise
+    pub fn run_until(&mut self, conditions: &[StopCondition]) -> StopReason {
+        assert!(!conditions.is_empty(), "run_until needs a stop condition");
+        let start_frame = self.frame_count();
+        let start_time = Instant::now();
+        let mut cycles: u64 = 0;
+        let mut instructions: u64 = 0;
+        let mut serial_length = None;
+
+        loop {
+            let (hit_breakpoint, cycles_spent) = self.tick();
+            cycles += cycles_spent as u64;
+            instructions += 1;
+            let frames = self.frame_count() - start_frame;
+
+            // The serial output only needs to be searched again when it has grown
+            let length = self.cpu.mmu.io_registers.serial_buffer().len();
+            let serial_changed = serial_length != Some(length);
+            serial_length = Some(length);
+
+            for condition in conditions {
+                let met = match condition {
+                    StopCondition::Frames(limit) => frames >= *limit,
+                    StopCondition::Cycles(limit) => cycles >= *limit,
+                    StopCondition::Pc(pc) => self.cpu.registers.PC == *pc,
+                    StopCondition::SerialContains(text) => {
+                        serial_changed
+                            && self
+                                .cpu
+                                .mmu
+                                .io_registers
+                                .serial_buffer()
+                                .iter()
+                                .collect::<String>()
+                                .contains(text.as_str())
+                    }
+                    StopCondition::MemoryEquals(address, value) => {
+                        self.cpu.mmu.peek(*address) == *value
+                    }
+                    // The breakpoint flag also covers debugger breakpoints
+                    StopCondition::SoftwareBreakpoint => {
+                        hit_breakpoint && self.cpu.registers.IR == 0x40
+                    }
+                    StopCondition::Timeout(timeout) => {
+                        instructions % TIMEOUT_CHECK_INTERVAL == 0
+                            && start_time.elapsed() >= *timeout
+                    }
+                };
+                if met {
+                    return StopReason {
+                        condition: condition.clone(),
+                        frames,
+                        cycles,
+                    };
+                }
+            }
+        }
+    }
+
+    // Runs until the PPU enters VBlank, or for a frame's worth of cycles while the LCD is off.
+    // Returns the M-cycles spent
+    pub fn run_frame(&mut self) -> u64 {
+        self.run_until(&[
+            StopCondition::Frames(1),
+            StopCondition::Cycles(CYCLES_PER_FRAME),
+        ])
+        .cycles
+    }
+
     pub(crate) fn set_breakpoints(&mut self, breakpoints: Breakpoints) {
         self.cpu.breakpoints = breakpoints;
     }
 
+    pub fn set_cheats(&mut self, cheats: &[Cheat]) {
+        self.cpu.mmu.cheats = Cheats::new(cheats);
+    }
+
     pub fn dump_registers(&mut self) -> Registers {
         self.cpu.registers.clone()
     }
@@ -64,12 +188,89 @@ impl GameBoy {
                 mem
             }
             Memories::OAM => self.cpu.mmu.ppu.object_attribute_memory.to_vec(),
-            _ => {
-                todo!("Implement remaining memories")
+            Memories::Bus => (0x0000..=0xFFFF)
+                .map(|address| self.cpu.mmu.peek(address))
+                .collect(),
+            Memories::ROMBank(bank) => Self::bank(self.cpu.mmu.mbc.rom(), bank, 0x4000).to_vec(),
+            Memories::ExternalRAM(bank) => {
+                Self::bank(self.cpu.mmu.mbc.ram(), bank, 0x2000).to_vec()
             }
+            Memories::IO => (0xFF00..=0xFF7F)
+                .map(|address| self.cpu.mmu.peek(address))
+                .collect(),
         }
     }
 
+    // Counterpart of dump_ram, offset is relative to the start of the dumped memory
+    pub(crate) fn edit_ram(&mut self, memory_to_edit: Memories, offset: usize, value: u8) {
+        let mmu = &mut self.cpu.mmu;
+        match memory_to_edit {
+            Memories::WRAM1 => mmu.internal_ram[offset] = value,
+            Memories::WRAM2 => mmu.internal_ram[4096 + offset] = value,
+            Memories::HRAM => mmu.poke(0xFF80 + offset as u16, value),
+            Memories::TileData => mmu.ppu.tile_data[offset] = value,
+            Memories::BackgroundMaps => mmu.poke(0x9800 + offset as u16, value),
+            Memories::OAM => mmu.ppu.object_attribute_memory[offset] = value,
+            Memories::Bus => mmu.poke(offset as u16, value),
+            Memories::ROMBank(bank) => {
+                if let Some(byte) = mmu.mbc.rom_mut().get_mut(bank * 0x4000 + offset) {
+                    *byte = value;
+                }
+            }
+            Memories::ExternalRAM(bank) => {
+                if let Some(byte) = mmu.mbc.ram_mut().get_mut(bank * 0x2000 + offset) {
+                    *byte = value;
+                }
+            }
+            Memories::IO => mmu.poke(0xFF00 + offset as u16, value),
+        }
+    }
+
+    pub(crate) fn dump_io_registers(&mut self) -> Vec<u8> {
+        (0xFF00..=0xFFFF)
+            .map(|address| match address {
+                // Write-only APU bits are shown so the debugger can edit single fields
+                0xFF10..=0xFF26 => self.cpu.mmu.apu.peek(address),
+                _ => self.cpu.mmu.peek(address),
+            })
+            .collect()
+    }
+
+    // Tile data followed by both background maps, as laid out in 0x8000..0xA000
+    pub(crate) fn dump_vram(&self) -> Vec<u8> {
+        let ppu = &self.cpu.mmu.ppu;
+        let mut vram = ppu.tile_data.to_vec();
+        vram.extend(ppu.background_map_1.iter());
+        vram.extend(ppu.background_map_2.iter());
+        vram
+    }
+
+    pub(crate) fn dump_oam(&self) -> Vec<u8> {
+        self.cpu.mmu.ppu.object_attribute_memory.to_vec()
+    }
+
+    pub(crate) fn rom_bank_count(&self) -> usize {
+        self.cpu.mmu.mbc.rom().len().div_ceil(0x4000)
+    }
+
+    pub(crate) fn ram_bank_count(&self) -> usize {
+        self.cpu.mmu.mbc.ram().len().div_ceil(0x2000)
+    }
+
+    fn bank(memory: &[u8], bank: usize, bank_size: usize) -> &[u8] {
+        let start = (bank * bank_size).min(memory.len());
+        let end = (start + bank_size).min(memory.len());
+        &memory[start..end]
+    }
+
+    // Snapshot of all general purpose RAM, laid out as expected by the RAM search
+    pub fn snapshot_ram(&self) -> Vec<u8> {
+        let mut snapshot = self.cpu.mmu.internal_ram.clone();
+        snapshot.extend(self.cpu.mmu.high_ram.iter());
+        snapshot.extend(self.cpu.mmu.mbc.ram().iter());
+        snapshot
+    }
+
     pub fn skip_boot_rom(&mut self) {
         // Setup registers
         self.cpu.registers.A = 0x01;
@@ -135,42 +336,182 @@ impl GameBoy {
         self.cpu.mmu.ppu.frame_buffer_vblanked.clone()
     }
 
-    pub fn key_pressed(&mut self, physical_key: PhysicalKey) {
-        // TODO: move function to io_registers to allow internals to remain private
-        if let PhysicalKey::Code(key_code) = physical_key {
-            self.cpu.mmu.io_registers.inputs.insert(key_code, true);
-            if self.cpu.mmu.io_registers.FF00_JOYP & 0x10 == 0 {
-                let dpad_keys = [
-                    KeyCode::ArrowUp,
-                    KeyCode::ArrowDown,
-                    KeyCode::ArrowLeft,
-                    KeyCode::ArrowRight,
-                ];
-                if dpad_keys.contains(&key_code) {
-                    self.cpu
-                        .mmu
-                        .io_registers
-                        .FF0F_IF_interrupt_flag
-                        .set_bit(4, true);
-                }
-            }
+    // Palette register used for each pixel of the last frame
+    pub fn get_palette_buffer(&self) -> Vec<DmgPalette> {
+        self.cpu.mmu.ppu.palette_buffer_vblanked.clone()
+    }
 
-            if self.cpu.mmu.io_registers.FF00_JOYP & 0x20 == 0 {
-                let dpad_keys = [KeyCode::KeyF, KeyCode::KeyD, KeyCode::KeyS, KeyCode::KeyA];
-                if dpad_keys.contains(&key_code) {
-                    self.cpu
-                        .mmu
-                        .io_registers
-                        .FF0F_IF_interrupt_flag
-                        .set_bit(4, true);
-                }
-            }
+    // Last completed frame, the palette and filters are ignored in raw mode
+    pub fn screenshot(
+        &self,
+        mode: ScreenshotMode,
+        palette: &ColorPalette,
+        filters: &FilterSettings,
+    ) -> Screenshot {
+        let ppu = &self.cpu.mmu.ppu;
+        match mode {
+            ScreenshotMode::Raw => Screenshot::raw(&ppu.frame_buffer_vblanked),
+            ScreenshotMode::Palette => Screenshot::with_palette(
+                &ppu.frame_buffer_vblanked,
+                &ppu.palette_buffer_vblanked,
+                palette,
+            ),
+            ScreenshotMode::Filtered => Screenshot::with_filters(
+                &ppu.frame_buffer_vblanked,
+                &ppu.palette_buffer_vblanked,
+                palette,
+                filters,
+            ),
         }
     }
 
-    pub fn key_released(&mut self, physical_key: PhysicalKey) {
-        if let PhysicalKey::Code(key_code) = physical_key {
-            self.cpu.mmu.io_registers.inputs.insert(key_code, false);
+    pub(crate) fn rom(&self) -> &[u8] {
+        self.cpu.mmu.mbc.rom()
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.cpu.mmu.ppu.frame_count
+    }
+
+    // Recording costs a copy per pixel, so it is only enabled while someone is looking at it
+    pub(crate) fn set_pixel_provenance(&mut self, enabled: bool) {
+        let ppu = &mut self.cpu.mmu.ppu;
+        ppu.record_provenance = enabled;
+        if !enabled {
+            ppu.provenance_vblanked.clear();
         }
     }
+
+    pub(crate) fn set_layer_visibility(&mut self, layer_visibility: LayerVisibility) {
+        self.cpu.mmu.ppu.layer_visibility = layer_visibility;
+    }
+
+    pub(crate) fn set_event_logging(&mut self, enabled: bool) {
+        let events = &mut self.cpu.mmu.events;
+        if events.enabled != enabled {
+            events.clear();
+        }
+        events.enabled = enabled;
+    }
+
+    pub(crate) fn events(&self) -> Vec<Event> {
+        self.cpu.mmu.events.last_frame.clone()
+    }
+
+    pub(crate) fn set_audio_channels(&mut self, enabled: [bool; 4]) {
+        self.cpu.mmu.apu.channel_enabled = enabled;
+    }
+
+    pub(crate) fn set_audio_scope(&mut self, enabled: bool) {
+        let apu = &mut self.cpu.mmu.apu;
+        if !enabled {
+            apu.clear_scope();
+        }
+        apu.capture_scope = enabled;
+    }
+
+    pub fn set_audio_sink(&mut self, audio_sink: Box<dyn AudioSink>) {
+        self.cpu.mmu.apu.set_audio_sink(audio_sink);
+    }
+
+    pub(crate) fn set_volume(&mut self, volume: f32) {
+        self.cpu.mmu.apu.set_volume(volume);
+    }
+
+    pub(crate) fn start_audio_recording(&mut self, path: &Path, stems: bool) -> Result<(), String> {
+        self.cpu.mmu.apu.start_recording(path, stems)
+    }
+
+    pub(crate) fn stop_audio_recording(&mut self) {
+        self.cpu.mmu.apu.stop_recording();
+    }
+
+    pub(crate) fn is_recording_audio(&self) -> bool {
+        self.cpu.mmu.apu.is_recording()
+    }
+
+    // Audio goes to a WAV file with the same name as the video
+    pub(crate) fn start_video_recording(
+        &mut self,
+        path: &Path,
+        format: VideoFormat,
+        palette: ColorPalette,
+        filters: Option<FilterSettings>,
+        with_audio: bool,
+    ) -> Result<(), String> {
+        self.stop_video_recording();
+        let recorder = VideoRecorder::new(path, format, palette, filters, with_audio)?;
+        if with_audio {
+            self.start_audio_recording(&path.with_extension("wav"), false)?;
+        }
+        self.cpu.mmu.ppu.start_recording(recorder);
+        Ok(())
+    }
+
+    pub(crate) fn stop_video_recording(&mut self) {
+        if self.cpu.mmu.ppu.stop_recording() {
+            self.stop_audio_recording();
+        }
+    }
+
+    pub(crate) fn is_recording_video(&self) -> bool {
+        self.cpu.mmu.ppu.is_recording()
+    }
+
+    pub(crate) fn start_vgm_logging(&mut self, path: &Path) -> Result<(), String> {
+        self.cpu.mmu.apu.start_vgm(path)
+    }
+
+    pub(crate) fn set_vgm_loop_point(&mut self) {
+        self.cpu.mmu.apu.set_vgm_loop_point();
+    }
+
+    pub(crate) fn stop_vgm_logging(&mut self) {
+        self.cpu.mmu.apu.stop_vgm();
+    }
+
+    pub(crate) fn is_logging_vgm(&self) -> bool {
+        self.cpu.mmu.apu.is_recording_vgm()
+    }
+
+    pub(crate) fn audio_channels(&self) -> [ChannelStatus; 4] {
+        self.cpu.mmu.apu.channel_status()
+    }
+
+    pub(crate) fn audio_scope(&self) -> [Vec<f32>; 5] {
+        self.cpu.mmu.apu.scope()
+    }
+
+    pub(crate) fn pixel_provenance(&self) -> Vec<PixelSource> {
+        self.cpu.mmu.ppu.provenance_vblanked.clone()
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        // TODO: move function to io_registers to allow internals to remain private
+        let io_registers = &mut self.cpu.mmu.io_registers;
+        io_registers.inputs.insert(button, pressed);
+        if !pressed {
+            return;
+        }
+
+        let dpad_buttons = [Button::Up, Button::Down, Button::Left, Button::Right];
+        let selected = if dpad_buttons.contains(&button) {
+            io_registers.FF00_JOYP & 0x10 == 0
+        } else {
+            io_registers.FF00_JOYP & 0x20 == 0
+        };
+        if selected {
+            io_registers.FF0F_IF_interrupt_flag.set_bit(4, true);
+        }
+    }
+
+    pub fn button_pressed(&self, button: Button) -> bool {
+        *self
+            .cpu
+            .mmu
+            .io_registers
+            .inputs
+            .get(&button)
+            .unwrap_or(&false)
+    }
 }
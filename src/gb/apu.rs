@@ -1,10 +1,17 @@
-mod registers;
+pub(crate) mod recorder;
+pub(crate) mod registers;
+mod vgm;
 
-use crate::audio::AudioPlayer;
+use crate::audio::AudioSink;
 use arbitrary_int::{u3, Number};
 use blip_buf::BlipBuf;
 use intbits::Bits;
+use log::{log, Level};
+use recorder::AudioRecorder;
 use registers::*;
+use std::collections::VecDeque;
+use std::path::Path;
+use vgm::VgmRecorder;
 
 const WAVE_PATTERN_DUTIES: [[u8; 8]; 4] = [
     [0, 0, 0, 0, 0, 0, 0, 1], // 12.5% duty cycle
@@ -13,6 +20,20 @@ const WAVE_PATTERN_DUTIES: [[u8; 8]; 4] = [
     [1, 1, 1, 1, 1, 1, 0, 0], // 75% duty cycle
 ];
 
+// Number of output samples kept per channel for the audio debugger
+const SCOPE_LENGTH: usize = 1024;
+
+#[derive(Clone, Copy, Default)]
+pub(crate) struct ChannelStatus {
+    pub(crate) on: bool,
+    pub(crate) frequency: f32,
+    pub(crate) volume: u8,
+    pub(crate) envelope_increasing: bool,
+    pub(crate) envelope_pace: u8,
+    // Index into the 12.5%, 25%, 50% and 75% duty cycles, only used by the pulse channels
+    pub(crate) duty: Option<u8>,
+}
+
 pub(crate) struct APU {
     // Registers
     reg_NR10: NR10,
@@ -75,8 +96,8 @@ pub(crate) struct APU {
     shadow_frequency: u32,
     sweep_timer: u8,
     done_sweep_calc: bool,
-    // Audio player
-    audio_player: AudioPlayer,
+    // Audio output
+    audio_sink: Box<dyn AudioSink>,
     output_period: u32,
     output_timer: u32,
     // Wave channel
@@ -91,27 +112,34 @@ pub(crate) struct APU {
     // high pass filter
     capacitor_left: f32,
     capacitor_right: f32,
+    // Debugging, muted channels are left out of the mix but still emulated
+    pub(crate) channel_enabled: [bool; 4],
+    pub(crate) capture_scope: bool,
+    scope: [VecDeque<f32>; 5],
+    recorder: Option<AudioRecorder>,
+    vgm: Option<VgmRecorder>,
 }
 
 const CLOCK_RATE: f64 = 4194304.0;
 const OUTPUT_SAMPLE_COUNT: u32 = 2000;
 
 impl APU {
-    pub(crate) fn new(audio_player: AudioPlayer) -> APU {
-        let mut blip_ch1 = BlipBuf::new(audio_player.sample_rate.0 / 10);
-        blip_ch1.set_rates(CLOCK_RATE, audio_player.sample_rate.0 as f64);
+    pub(crate) fn new(audio_sink: Box<dyn AudioSink>) -> APU {
+        let sample_rate = audio_sink.sample_rate();
+        let mut blip_ch1 = BlipBuf::new(sample_rate / 10);
+        blip_ch1.set_rates(CLOCK_RATE, sample_rate as f64);
 
-        let mut blip_ch2 = BlipBuf::new(audio_player.sample_rate.0 / 10);
-        blip_ch2.set_rates(CLOCK_RATE, audio_player.sample_rate.0 as f64);
+        let mut blip_ch2 = BlipBuf::new(sample_rate / 10);
+        blip_ch2.set_rates(CLOCK_RATE, sample_rate as f64);
 
-        let mut blip_ch3 = BlipBuf::new(audio_player.sample_rate.0 / 10);
-        blip_ch3.set_rates(CLOCK_RATE, audio_player.sample_rate.0 as f64);
+        let mut blip_ch3 = BlipBuf::new(sample_rate / 10);
+        blip_ch3.set_rates(CLOCK_RATE, sample_rate as f64);
 
-        let mut blip_ch4 = BlipBuf::new(audio_player.sample_rate.0 / 10);
-        blip_ch4.set_rates(CLOCK_RATE, audio_player.sample_rate.0 as f64);
+        let mut blip_ch4 = BlipBuf::new(sample_rate / 10);
+        blip_ch4.set_rates(CLOCK_RATE, sample_rate as f64);
 
-        let output_period = ((OUTPUT_SAMPLE_COUNT as u64 * CLOCK_RATE as u64)
-            / audio_player.sample_rate.0 as u64) as u32;
+        let output_period =
+            ((OUTPUT_SAMPLE_COUNT as u64 * CLOCK_RATE as u64) / sample_rate as u64) as u32;
 
         APU {
             reg_NR10: NR10::ZERO,
@@ -167,7 +195,7 @@ impl APU {
             shadow_frequency: 0,
             sweep_timer: 0,
             done_sweep_calc: false,
-            audio_player,
+            audio_sink,
             output_period,
             output_timer: 0,
             wave_duty_position_ch3: 0,
@@ -179,10 +207,19 @@ impl APU {
             LFSR: 0,
             capacitor_left: 0.0,
             capacitor_right: 0.0,
+            channel_enabled: [true; 4],
+            capture_scope: false,
+            scope: Default::default(),
+            recorder: None,
+            vgm: None,
         }
     }
 
     pub(crate) fn tick(&mut self, div_apu: bool) {
+        if let Some(vgm) = &mut self.vgm {
+            vgm.tick();
+        }
+
         if div_apu {
             self.DIV_APU = self.DIV_APU.wrapping_add(1);
             if self.reg_NR52.audio_on() {
@@ -513,6 +550,31 @@ impl APU {
                     0.0
                 };
 
+                if self.capture_scope {
+                    self.push_scope(
+                        [
+                            dac_output_ch1,
+                            dac_output_ch2,
+                            dac_output_ch3,
+                            dac_output_ch4,
+                        ],
+                        0,
+                    );
+                }
+
+                // Muted channels
+                let mute = |output: f32, channel: usize| {
+                    if self.channel_enabled[channel] {
+                        output
+                    } else {
+                        0.0
+                    }
+                };
+                let dac_output_ch1 = mute(dac_output_ch1, 0);
+                let dac_output_ch2 = mute(dac_output_ch2, 1);
+                let dac_output_ch3 = mute(dac_output_ch3, 2);
+                let dac_output_ch4 = mute(dac_output_ch4, 3);
+
                 // Mixing and panning
                 let mut sample_left = 0.0;
                 if self.reg_NR51.ch1_left() {
@@ -546,9 +608,29 @@ impl APU {
                 }
                 sample_right *= (1.0 / 15.0) * 0.25 * self.reg_NR50.right_volume().value() as f32;
                 buf_right[i] = sample_right;
+
+                if self.capture_scope {
+                    self.push_scope([(sample_left + sample_right) / 2.0], 4);
+                }
+
+                if let Some(recorder) = &mut self.recorder {
+                    let result = recorder.write_mix(sample_left, sample_right).and_then(|_| {
+                        // Stems skip the DAC offset so that silence stays at zero
+                        recorder.write_stems([
+                            buf_ch1[i] as f32 / 15.0,
+                            buf_ch2[i] as f32 / 15.0,
+                            buf_ch3[i] as f32 / 15.0,
+                            buf_ch4[i] as f32 / 15.0,
+                        ])
+                    });
+                    if let Err(err) = result {
+                        log!(Level::Error, "Failed to write audio recording: {}", err);
+                        self.recorder = None;
+                    }
+                }
             }
 
-            self.audio_player.add_samples(
+            self.audio_sink.add_samples(
                 &buf_left[..samples_avail as usize],
                 &buf_right[..samples_avail as usize],
             );
@@ -557,6 +639,203 @@ impl APU {
         }
     }
 
+    // Switching sinks restarts resampling at the new sample rate
+    pub(crate) fn set_audio_sink(&mut self, audio_sink: Box<dyn AudioSink>) {
+        let sample_rate = audio_sink.sample_rate();
+        for blip in [
+            &mut self.blip_ch1,
+            &mut self.blip_ch2,
+            &mut self.blip_ch3,
+            &mut self.blip_ch4,
+        ] {
+            *blip = BlipBuf::new(sample_rate / 10);
+            blip.set_rates(CLOCK_RATE, sample_rate as f64);
+        }
+        self.last_amp_ch1 = 0;
+        self.last_amp_ch2 = 0;
+        self.last_amp_ch3 = 0;
+        self.last_amp_ch4 = 0;
+        self.output_period =
+            ((OUTPUT_SAMPLE_COUNT as u64 * CLOCK_RATE as u64) / sample_rate as u64) as u32;
+        self.output_timer = 0;
+        self.audio_sink = audio_sink;
+    }
+
+    pub(crate) fn set_volume(&mut self, volume: f32) {
+        self.audio_sink.set_volume(volume);
+    }
+
+    pub(crate) fn start_recording(&mut self, path: &Path, stems: bool) -> Result<(), String> {
+        self.stop_recording();
+        let recorder = AudioRecorder::new(path, self.audio_sink.sample_rate(), stems)
+            .map_err(|err| err.to_string())?;
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+
+    pub(crate) fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(err) = recorder.finish() {
+                log!(Level::Error, "Failed to finish audio recording: {}", err);
+            }
+        }
+    }
+
+    pub(crate) fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    pub(crate) fn start_vgm(&mut self, path: &Path) -> Result<(), String> {
+        self.stop_vgm();
+        let mut vgm = VgmRecorder::new(path).map_err(|err| err.to_string())?;
+
+        // Recreate the current register state, the trigger bits are left out so that no channel
+        // restarts at the beginning of the file
+        vgm.write(0xFF26, self.reg_NR52.raw_value() & 0x80);
+        for (index, value) in self.wave_ram.iter().enumerate() {
+            vgm.write(0xFF30 + index as u16, *value);
+        }
+        let registers = [
+            (0xFF24, self.reg_NR50.raw_value()),
+            (0xFF25, self.reg_NR51.raw_value()),
+            (0xFF10, self.reg_NR10.raw_value()),
+            (0xFF11, self.reg_NR11.raw_value()),
+            (0xFF12, self.reg_NR12.raw_value()),
+            (0xFF13, self.reg_NR13),
+            (0xFF14, self.reg_NR14.raw_value() & 0x7F),
+            (0xFF16, self.reg_NR21.raw_value()),
+            (0xFF17, self.reg_NR22.raw_value()),
+            (0xFF18, self.reg_NR23),
+            (0xFF19, self.reg_NR24.raw_value() & 0x7F),
+            (0xFF1A, self.reg_NR30.raw_value()),
+            (0xFF1B, self.reg_NR31),
+            (0xFF1C, self.reg_NR32.raw_value()),
+            (0xFF1D, self.reg_NR33),
+            (0xFF1E, self.reg_NR34.raw_value() & 0x7F),
+            (0xFF20, self.reg_NR41.raw_value()),
+            (0xFF21, self.reg_NR42.raw_value()),
+            (0xFF22, self.reg_NR43.raw_value()),
+            (0xFF23, self.reg_NR44.raw_value() & 0x7F),
+        ];
+        for (address, value) in registers {
+            vgm.write(address, value);
+        }
+
+        self.vgm = Some(vgm);
+        Ok(())
+    }
+
+    pub(crate) fn set_vgm_loop_point(&mut self) {
+        if let Some(vgm) = &mut self.vgm {
+            vgm.set_loop_point();
+        }
+    }
+
+    pub(crate) fn stop_vgm(&mut self) {
+        if let Some(vgm) = self.vgm.take() {
+            if let Err(err) = vgm.finish() {
+                log!(Level::Error, "Failed to write VGM file: {}", err);
+            }
+        }
+    }
+
+    pub(crate) fn is_recording_vgm(&self) -> bool {
+        self.vgm.is_some()
+    }
+
+    fn push_scope<const N: usize>(&mut self, samples: [f32; N], first_channel: usize) {
+        for (offset, sample) in samples.into_iter().enumerate() {
+            let scope = &mut self.scope[first_channel + offset];
+            if scope.len() == SCOPE_LENGTH {
+                scope.pop_front();
+            }
+            scope.push_back(sample);
+        }
+    }
+
+    // Latest samples of the four channel DACs followed by the mono mix
+    pub(crate) fn scope(&self) -> [Vec<f32>; 5] {
+        std::array::from_fn(|channel| self.scope[channel].iter().copied().collect())
+    }
+
+    pub(crate) fn clear_scope(&mut self) {
+        self.scope.iter_mut().for_each(VecDeque::clear);
+    }
+
+    pub(crate) fn channel_status(&self) -> [ChannelStatus; 4] {
+        let pulse_frequency = |period: u32| 131072.0 / (2048 - period) as f32;
+        let period_ch1 = (u32::from(self.reg_NR14.period()) << 8) | self.reg_NR13 as u32;
+        let period_ch2 = (u32::from(self.reg_NR24.period()) << 8) | self.reg_NR23 as u32;
+        let period_ch3 = (u32::from(self.reg_NR34.period()) << 8) | self.reg_NR33 as u32;
+        let divisor = match self.reg_NR43.clock_divider().value() {
+            0 => 0.5,
+            divider => divider as f32,
+        };
+
+        [
+            ChannelStatus {
+                on: self.reg_NR52.ch1_on(),
+                frequency: pulse_frequency(period_ch1),
+                volume: self.current_volume_ch1,
+                envelope_increasing: self.reg_NR12.env_dir(),
+                envelope_pace: self.reg_NR12.sweep_pace().value(),
+                duty: Some(self.reg_NR11.wave_duty().value()),
+            },
+            ChannelStatus {
+                on: self.reg_NR52.ch2_on(),
+                frequency: pulse_frequency(period_ch2),
+                volume: self.current_volume_ch2,
+                envelope_increasing: self.reg_NR22.env_dir(),
+                envelope_pace: self.reg_NR22.sweep_pace().value(),
+                duty: Some(self.reg_NR21.wave_duty().value()),
+            },
+            ChannelStatus {
+                on: self.reg_NR52.ch3_on(),
+                frequency: 65536.0 / (2048 - period_ch3) as f32,
+                // Output level 0 is mute, 1-3 shift the sample right by 0-2
+                volume: match self.reg_NR32.output_level().value() {
+                    0 => 0,
+                    level => 15 >> (level - 1),
+                },
+                envelope_increasing: false,
+                envelope_pace: 0,
+                duty: None,
+            },
+            ChannelStatus {
+                on: self.reg_NR52.ch4_on(),
+                frequency: 262144.0
+                    / divisor
+                    / (1u32 << self.reg_NR43.clock_shift().value()) as f32,
+                volume: self.current_volume_ch4,
+                envelope_increasing: self.reg_NR42.env_dir(),
+                envelope_pace: self.reg_NR42.sweep_pace().value(),
+                duty: None,
+            },
+        ]
+    }
+
+    // Register contents including the write-only length and period bits, for the debugger.
+    // The trigger bits are never stored
+    pub(crate) fn peek(&self, address: u16) -> u8 {
+        match address {
+            0xFF10 => self.reg_NR10.raw_value(),
+            0xFF11 => self.reg_NR11.raw_value(),
+            0xFF13 => self.reg_NR13,
+            0xFF14 => self.reg_NR14.raw_value() & 0x7F,
+            0xFF16 => self.reg_NR21.raw_value(),
+            0xFF18 => self.reg_NR23,
+            0xFF19 => self.reg_NR24.raw_value() & 0x7F,
+            0xFF1A => self.reg_NR30.raw_value(),
+            0xFF1B => self.reg_NR31,
+            0xFF1C => self.reg_NR32.raw_value(),
+            0xFF1D => self.reg_NR33,
+            0xFF1E => self.reg_NR34.raw_value() & 0x7F,
+            0xFF20 => self.reg_NR41.raw_value(),
+            0xFF23 => self.reg_NR44.raw_value() & 0x7F,
+            _ => self.read(address),
+        }
+    }
+
     pub(crate) fn read(&self, address: u16) -> u8 {
         match address {
             0xFF10 => self.reg_NR10.raw_value() | 0x80,
@@ -596,6 +875,12 @@ impl APU {
     }
 
     pub(crate) fn write(&mut self, address: u16, value: u8) {
+        if let Some(vgm) = &mut self.vgm {
+            if (0xFF10..=0xFF3F).contains(&address) {
+                vgm.write(address, value);
+            }
+        }
+
         match address {
             0xFF10 => {
                 if self.reg_NR52.audio_on() {
@@ -762,6 +1047,7 @@ impl APU {
                 }
             }
             0xFF1B => {
+                self.reg_NR31 = value;
                 self.length_timer_ch3 = 256 - (value as u16);
             }
             0xFF1C => {
@@ -944,6 +1230,7 @@ impl APU {
                     self.reg_NR23 = 0;
                     self.reg_NR24 = PeriodHighControl::ZERO;
                     self.reg_NR30 = NR30::ZERO;
+                    self.reg_NR31 = 0;
                     self.reg_NR32 = NR32::ZERO;
                     self.reg_NR33 = 0;
                     self.reg_NR34 = PeriodHighControl::ZERO;
@@ -0,0 +1,110 @@
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+type Writer = WavWriter<BufWriter<File>>;
+
+// Writes the stereo mix, and optionally a mono stem per channel, to 16-bit WAV files
+pub(crate) struct AudioRecorder {
+    mix: Writer,
+    stems: Vec<Writer>,
+}
+
+impl AudioRecorder {
+    pub(crate) fn new(path: &Path, sample_rate: u32, stems: bool) -> Result<Self, hound::Error> {
+        let spec = |channels| WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+
+        let mix = WavWriter::create(path, spec(2))?;
+        let stems = if stems {
+            (1..=4)
+                .map(|channel| WavWriter::create(stem_path(path, channel), spec(1)))
+                .collect::<Result<Vec<Writer>, _>>()?
+        } else {
+            Vec::new()
+        };
+
+        Ok(AudioRecorder { mix, stems })
+    }
+
+    pub(crate) fn write_mix(&mut self, left: f32, right: f32) -> Result<(), hound::Error> {
+        self.mix.write_sample(to_pcm(left))?;
+        self.mix.write_sample(to_pcm(right))
+    }
+
+    pub(crate) fn write_stems(&mut self, channels: [f32; 4]) -> Result<(), hound::Error> {
+        for (stem, sample) in self.stems.iter_mut().zip(channels) {
+            stem.write_sample(to_pcm(sample))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> Result<(), hound::Error> {
+        self.mix.finalize()?;
+        for stem in self.stems {
+            stem.finalize()?;
+        }
+        Ok(())
+    }
+}
+
+// song.wav -> song_ch1.wav
+fn stem_path(path: &Path, channel: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}_ch{}.wav", stem, channel))
+}
+
+fn to_pcm(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::WavReader;
+    use std::fs;
+
+    #[test]
+    fn mix_and_stem_headers() {
+        let directory = std::env::temp_dir().join(format!("mnemosyne_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("song.wav");
+
+        let mut recorder = AudioRecorder::new(&path, 48000, true).unwrap();
+        for _ in 0..10 {
+            recorder.write_mix(0.5, -0.5).unwrap();
+            recorder.write_stems([0.0, 0.25, 0.5, 2.0]).unwrap();
+        }
+        recorder.finish().unwrap();
+
+        let mix = WavReader::open(&path).unwrap();
+        assert_eq!(mix.spec().channels, 2);
+        assert_eq!(mix.spec().sample_rate, 48000);
+        assert_eq!(mix.spec().bits_per_sample, 16);
+        assert_eq!(mix.spec().sample_format, SampleFormat::Int);
+        assert_eq!(mix.len(), 20);
+
+        for channel in 1..=4 {
+            let stem_path = directory.join(format!("song_ch{}.wav", channel));
+            let mut stem = WavReader::open(&stem_path).unwrap();
+            assert_eq!(stem.spec().channels, 1);
+            assert_eq!(stem.spec().sample_rate, 48000);
+            assert_eq!(stem.spec().bits_per_sample, 16);
+            assert_eq!(stem.len(), 10);
+            if channel == 4 {
+                // Samples out of range are clamped
+                let first = stem.samples::<i16>().next().unwrap().unwrap();
+                assert_eq!(first, i16::MAX);
+            }
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const VGM_SAMPLE_RATE: u64 = 44100;
+const CLOCK_RATE: u64 = 4194304;
+const HEADER_SIZE: usize = 0x100;
+
+// Logs APU register writes as a VGM 1.61 file for the Game Boy DMG chip. Commands are streamed
+// to disk and the header is filled in once the log is finished
+pub(crate) struct VgmRecorder {
+    file: BufWriter<File>,
+    cycles: u64,
+    written_samples: u64,
+    // Bytes of commands written after the header
+    data_length: usize,
+    // Offset into the commands and the sample at which playback loops back
+    loop_point: Option<(usize, u64)>,
+    // First failed write, reported when the log is finished
+    error: Option<io::Error>,
+}
+
+impl VgmRecorder {
+    pub(crate) fn new(path: &Path) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&[0; HEADER_SIZE])?;
+        Ok(VgmRecorder {
+            file,
+            cycles: 0,
+            written_samples: 0,
+            data_length: 0,
+            loop_point: None,
+            error: None,
+        })
+    }
+
+    pub(crate) fn tick(&mut self) {
+        self.cycles += 1;
+    }
+
+    pub(crate) fn write(&mut self, address: u16, value: u8) {
+        self.wait();
+        self.push(&[0xB3, (address - 0xFF10) as u8, value]);
+    }
+
+    pub(crate) fn set_loop_point(&mut self) {
+        self.wait();
+        self.loop_point = Some((self.data_length, self.written_samples));
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        self.wait();
+        self.push(&[0x66]);
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+
+        let mut header = [0u8; HEADER_SIZE];
+        let mut put = |offset: usize, value: u32| {
+            header[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        };
+        put(0x00, u32::from_le_bytes(*b"Vgm "));
+        put(0x04, (HEADER_SIZE + self.data_length - 0x04) as u32);
+        put(0x08, 0x161);
+        put(0x18, self.written_samples as u32);
+        if let Some((offset, sample)) = self.loop_point {
+            // Offsets in the header are relative to their own position
+            put(0x1C, (HEADER_SIZE + offset - 0x1C) as u32);
+            put(0x20, (self.written_samples - sample) as u32);
+        }
+        put(0x24, 60);
+        put(0x34, (HEADER_SIZE - 0x34) as u32);
+        put(0x80, CLOCK_RATE as u32);
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.flush()
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        if self.error.is_none() {
+            if let Err(err) = self.file.write_all(bytes) {
+                self.error = Some(err);
+            }
+        }
+        self.data_length += bytes.len();
+    }
+
+    // Emits wait commands up to the current cycle
+    fn wait(&mut self) {
+        let target = self.cycles * VGM_SAMPLE_RATE / CLOCK_RATE;
+        let mut remaining = target - self.written_samples;
+        while remaining > 0 {
+            let samples = match remaining {
+                1..=16 => {
+                    self.push(&[0x70 + (remaining - 1) as u8]);
+                    remaining
+                }
+                735 => {
+                    self.push(&[0x62]);
+                    735
+                }
+                882 => {
+                    self.push(&[0x63]);
+                    882
+                }
+                _ => {
+                    let samples = remaining.min(0xFFFF);
+                    let [lo, hi] = (samples as u16).to_le_bytes();
+                    self.push(&[0x61, lo, hi]);
+                    samples
+                }
+            };
+            remaining -= samples;
+        }
+        self.written_samples = target;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn header_and_commands() {
+        let path = std::env::temp_dir().join(format!("mnemosyne_{}.vgm", std::process::id()));
+        let mut vgm = VgmRecorder::new(&path).unwrap();
+        vgm.write(0xFF26, 0x80);
+        // 735 samples, one NTSC frame
+        for _ in 0..69906 {
+            vgm.tick();
+        }
+        vgm.write(0xFF12, 0xF0);
+        vgm.set_loop_point();
+        for _ in 0..100 {
+            vgm.tick();
+        }
+        vgm.finish().unwrap();
+
+        let file = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let word = |offset: usize| u32::from_le_bytes(file[offset..offset + 4].try_into().unwrap());
+        assert_eq!(&file[0x00..0x04], b"Vgm ");
+        assert_eq!(word(0x04) as usize, file.len() - 0x04);
+        assert_eq!(word(0x08), 0x161);
+        assert_eq!(word(0x18), 736);
+        assert_eq!(word(0x1C) as usize + 0x1C, HEADER_SIZE + 7);
+        assert_eq!(word(0x20), 1);
+        assert_eq!(word(0x24), 60);
+        assert_eq!(word(0x34) as usize + 0x34, HEADER_SIZE);
+        assert_eq!(word(0x80), 4194304);
+        assert_eq!(
+            &file[HEADER_SIZE..],
+            &[0xB3, 0x16, 0x80, 0x62, 0xB3, 0x02, 0xF0, 0x70, 0x66]
+        );
+    }
+}
@@ -0,0 +1,81 @@
+// Everything the CPU talks to. Reads and writes are bus accesses that take part of an M-cycle,
+// the other hardware only advances through `tick_dots`
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    fn tick_dots(&mut self, dots: u32);
+    // IE & IF, the interrupt lines are checked without a bus access
+    fn pending_interrupts(&mut self) -> u8;
+    // IF, read and written without a bus access while an interrupt is dispatched
+    fn interrupt_flags(&mut self) -> u8;
+    fn set_interrupt_flags(&mut self, value: u8);
+    // Debugger hooks
+    fn begin_instruction(&mut self, _pc: u16) {}
+    fn interrupt_dispatched(&mut self, _pc: u16, _vector: u16) {}
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BusAccess {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+// 64 KiB of plain RAM without any other hardware, recording the accesses made during every
+// M-cycle. Used to check the CPU against single-step test vectors. It is public because the
+// integration tests only see the public API, it holds no emulator state and nothing else uses it
+pub struct TestBus {
+    pub memory: Vec<u8>,
+    pub cycles: Vec<Vec<BusAccess>>,
+    current: Vec<BusAccess>,
+    dots: u32,
+}
+
+impl TestBus {
+    pub fn new() -> Self {
+        TestBus {
+            memory: vec![0; 0x10000],
+            cycles: Vec::new(),
+            current: Vec::new(),
+            dots: 0,
+        }
+    }
+}
+
+impl Default for TestBus {
+    fn default() -> Self {
+        TestBus::new()
+    }
+}
+
+impl Bus for TestBus {
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.memory[address as usize];
+        self.current.push(BusAccess::Read(address, value));
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+        self.current.push(BusAccess::Write(address, value));
+    }
+
+    fn tick_dots(&mut self, dots: u32) {
+        self.dots += dots;
+        while self.dots >= 4 {
+            self.dots -= 4;
+            self.cycles.push(std::mem::take(&mut self.current));
+        }
+    }
+
+    fn pending_interrupts(&mut self) -> u8 {
+        self.memory[0xFFFF] & self.memory[0xFF0F] & 0x1F
+    }
+
+    fn interrupt_flags(&mut self) -> u8 {
+        self.memory[0xFF0F]
+    }
+
+    fn set_interrupt_flags(&mut self, value: u8) {
+        self.memory[0xFF0F] = value;
+    }
+}
@@ -0,0 +1,353 @@
+use directories::ProjectDirs;
+use log::{log, Level};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Cheat {
+    pub name: String,
+    pub code: String,
+    pub enabled: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CheatCode {
+    // Replaces a ROM byte, optionally only when the original byte matches the compare value
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    // Writes a RAM byte every VBlank
+    GameShark {
+        bank: u8,
+        address: u16,
+        value: u8,
+    },
+    // Plain address/value pair, optionally limited to a single ROM or RAM bank
+    Raw {
+        bank: Option<usize>,
+        address: u16,
+        value: u8,
+    },
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct CheatFile {
+    cheats: Vec<Cheat>,
+}
+
+pub(crate) struct RomPatch {
+    address: u16,
+    value: u8,
+    compare: Option<u8>,
+    bank: Option<usize>,
+}
+
+pub(crate) struct RamWrite {
+    address: u16,
+    value: u8,
+    bank: Option<usize>,
+}
+
+#[derive(Default)]
+pub(crate) struct Cheats {
+    rom_patches: Vec<RomPatch>,
+    ram_writes: Vec<RamWrite>,
+}
+
+impl Cheats {
+    pub(crate) fn new(cheats: &[Cheat]) -> Self {
+        let mut active = Cheats::default();
+        for cheat in cheats.iter().filter(|cheat| cheat.enabled) {
+            let Ok(code) = CheatCode::decode(&cheat.code) else {
+                continue;
+            };
+
+            match code {
+                CheatCode::GameGenie {
+                    address,
+                    value,
+                    compare,
+                } => active.rom_patches.push(RomPatch {
+                    address,
+                    value,
+                    compare,
+                    bank: None,
+                }),
+                CheatCode::GameShark {
+                    bank,
+                    address,
+                    value,
+                } => active.ram_writes.push(RamWrite {
+                    address,
+                    value,
+                    // 0x8X selects cartridge RAM bank X, WRAM banks (0x9X) only exist on CGB
+                    bank: match bank {
+                        0x80..=0x8F => Some(bank as usize & 0x0F),
+                        _ => None,
+                    },
+                }),
+                CheatCode::Raw {
+                    bank,
+                    address,
+                    value,
+                } => {
+                    if address <= 0x7FFF {
+                        active.rom_patches.push(RomPatch {
+                            address,
+                            value,
+                            compare: None,
+                            bank,
+                        });
+                    } else {
+                        active.ram_writes.push(RamWrite {
+                            address,
+                            value,
+                            bank,
+                        });
+                    }
+                }
+            }
+        }
+        active
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rom_patches.is_empty() && self.ram_writes.is_empty()
+    }
+
+    pub(crate) fn patch_rom(&self, address: u16, value: u8, bank: usize) -> u8 {
+        for patch in &self.rom_patches {
+            if patch.address == address
+                && patch.compare.is_none_or(|compare| compare == value)
+                && patch.bank.is_none_or(|patch_bank| patch_bank == bank)
+            {
+                return patch.value;
+            }
+        }
+        value
+    }
+
+    // Returns the writes to perform this VBlank, skipping the ones for an unmapped RAM bank
+    pub(crate) fn ram_writes(&self, ram_bank: usize) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.ram_writes
+            .iter()
+            .filter(move |write| {
+                !(0xA000..=0xBFFF).contains(&write.address)
+                    || write.bank.is_none_or(|bank| bank == ram_bank)
+            })
+            .map(|write| (write.address, write.value))
+    }
+}
+
+impl CheatCode {
+    // Accepts Game Genie (ABC-DEF or ABC-DEF-GHI), GameShark (BBVVAAAA) and raw (AAAA:VV or
+    // BB:AAAA:VV) codes
+    pub fn decode(code: &str) -> Result<CheatCode, String> {
+        let code = code.trim().to_uppercase();
+
+        if code.contains(':') {
+            let parts = code
+                .split(':')
+                .map(|part| u32::from_str_radix(part.trim(), 16))
+                .collect::<Result<Vec<u32>, _>>()
+                .map_err(|_| format!("Invalid raw cheat: {}", code))?;
+            return match parts[..] {
+                [address, value] if address <= 0xFFFF && value <= 0xFF => Ok(CheatCode::Raw {
+                    bank: None,
+                    address: address as u16,
+                    value: value as u8,
+                }),
+                [bank, address, value] if address <= 0xFFFF && value <= 0xFF => {
+                    Ok(CheatCode::Raw {
+                        bank: Some(bank as usize),
+                        address: address as u16,
+                        value: value as u8,
+                    })
+                }
+                _ => Err(format!("Invalid raw cheat: {}", code)),
+            };
+        }
+
+        let digits = code
+            .chars()
+            .filter(|c| *c != '-')
+            .map(|c| c.to_digit(16).map(|digit| digit as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| format!("Invalid cheat code: {}", code))?;
+
+        match digits.len() {
+            6 | 9 => {
+                let value = (digits[0] << 4) | digits[1];
+                let address = (((digits[5] ^ 0xF) as u16) << 12)
+                    | ((digits[2] as u16) << 8)
+                    | ((digits[3] as u16) << 4)
+                    | (digits[4] as u16);
+                let compare = if digits.len() == 9 {
+                    Some(((digits[6] << 4) | digits[8]).rotate_right(2) ^ 0xBA)
+                } else {
+                    None
+                };
+
+                if address > 0x7FFF {
+                    return Err(format!("Game Genie code outside of ROM: {}", code));
+                }
+
+                Ok(CheatCode::GameGenie {
+                    address,
+                    value,
+                    compare,
+                })
+            }
+            8 => {
+                let byte = |index: usize| (digits[index] << 4) | digits[index + 1];
+                Ok(CheatCode::GameShark {
+                    bank: byte(0),
+                    value: byte(2),
+                    address: ((byte(6) as u16) << 8) | byte(4) as u16,
+                })
+            }
+            _ => Err(format!("Invalid cheat code: {}", code)),
+        }
+    }
+}
+
+// Cheat lists are keyed by the cartridge header, so ROMs with the same file name don't share one
+fn cheat_path(rom: &[u8]) -> Result<PathBuf, String> {
+    let project_dirs =
+        ProjectDirs::from("", "", "Mnemosyne").ok_or("No config directory available")?;
+    let mut cheat_path = PathBuf::new();
+    cheat_path.push(project_dirs.config_dir());
+    cheat_path.push("cheats");
+    fs::create_dir_all(&cheat_path).map_err(|err| err.to_string())?;
+
+    let title = rom
+        .get(0x134..0x144)
+        .unwrap_or_default()
+        .iter()
+        .take_while(|&&byte| byte != 0)
+        .map(|&byte| {
+            if byte.is_ascii_alphanumeric() {
+                byte as char
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    let header_checksum = rom.get(0x14D).copied().unwrap_or_default();
+    cheat_path.push(format!("{}_{:02X}.toml", title, header_checksum));
+    Ok(cheat_path)
+}
+
+pub(crate) fn load_cheats(rom: &[u8]) -> Vec<Cheat> {
+    let toml_string = match cheat_path(rom) {
+        Ok(path) => match fs::read_to_string(path) {
+            Ok(toml_string) => toml_string,
+            Err(_) => return Vec::new(),
+        },
+        Err(err) => {
+            log!(Level::Error, "Failed to load cheats: {}", err);
+            return Vec::new();
+        }
+    };
+
+    match toml::from_str::<CheatFile>(&toml_string) {
+        Ok(cheat_file) => cheat_file.cheats,
+        Err(err) => {
+            log!(Level::Error, "Failed to parse cheats: {}", err);
+            Vec::new()
+        }
+    }
+}
+
+pub(crate) fn save_cheats(rom: &[u8], cheats: &[Cheat]) -> Result<(), String> {
+    let cheat_file = CheatFile {
+        cheats: cheats.to_vec(),
+    };
+    let toml_string = toml::to_string_pretty(&cheat_file).map_err(|err| err.to_string())?;
+    fs::write(cheat_path(rom)?, toml_string).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Codes are built from the documented encoding: ABC-DEF-GHI where AB is the value, FCDE the
+    // address with the top nibble XORed with 0xF and GI the compare value XORed with 0xBA and
+    // rotated left by two
+    #[test]
+    fn game_genie() {
+        assert_eq!(
+            CheatCode::decode("3E5-D4F"),
+            Ok(CheatCode::GameGenie {
+                address: 0x05D4,
+                value: 0x3E,
+                compare: None,
+            })
+        );
+        assert_eq!(
+            CheatCode::decode(" 00a-17b-c49 "),
+            Ok(CheatCode::GameGenie {
+                address: 0x4A17,
+                value: 0x00,
+                compare: Some(0xC8),
+            })
+        );
+    }
+
+    #[test]
+    fn game_shark() {
+        assert_eq!(
+            CheatCode::decode("01FF42D1"),
+            Ok(CheatCode::GameShark {
+                bank: 0x01,
+                address: 0xD142,
+                value: 0xFF,
+            })
+        );
+    }
+
+    #[test]
+    fn raw() {
+        assert_eq!(
+            CheatCode::decode("C000:42"),
+            Ok(CheatCode::Raw {
+                bank: None,
+                address: 0xC000,
+                value: 0x42,
+            })
+        );
+        assert_eq!(
+            CheatCode::decode("02:4000:FF"),
+            Ok(CheatCode::Raw {
+                bank: Some(2),
+                address: 0x4000,
+                value: 0xFF,
+            })
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        for code in [
+            "",
+            "123",
+            "3E5-D4",
+            "01FF42D",
+            "01FF42D100",
+            "XYZ-123",
+            "01FF42DG",
+            "C000",
+            "C000:ZZ",
+            "10000:00",
+            "C000:100",
+            "01:02:C000:42",
+        ] {
+            assert!(CheatCode::decode(code).is_err(), "{} was accepted", code);
+        }
+        // The decoded address has to be in ROM
+        assert!(CheatCode::decode("3E5-D40").is_err());
+    }
+}
@@ -3,24 +3,22 @@
 #![allow(incomplete_features)]
 
 use crate::gb::breakpoints::Breakpoints;
+use crate::gb::bus::Bus;
 use crate::gb::mmu::MMU;
 use crate::gb::registers::{ConditionCode, Flag, Reg, Registers};
 use log::{log, Level};
-use std::time::Duration;
 
-pub struct CPU {
-    pub(crate) registers: Registers,
-    pub(crate) mmu: MMU,
+pub struct CPU<B: Bus = MMU> {
+    pub registers: Registers,
+    pub(crate) mmu: B,
     to_set_IME: u8,
     halted: bool,
     halt_bug: bool,
     pub breakpoints: Breakpoints,
-    pub(crate) time_ppu: Duration,
-    pub(crate) time_io: Duration,
 }
 
-impl CPU {
-    pub fn new(registers: Registers, mmu: MMU) -> Self {
+impl<B: Bus> CPU<B> {
+    pub fn new(registers: Registers, mmu: B) -> Self {
         CPU {
             registers,
             mmu,
@@ -28,11 +26,22 @@ impl CPU {
             halted: false,
             halt_bug: false,
             breakpoints: Breakpoints::new(),
-            time_ppu: Duration::new(0, 0),
-            time_io: Duration::new(0, 0),
         }
     }
 
+    pub fn bus(&self) -> &B {
+        &self.mmu
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.mmu
+    }
+
+    // Runs a single instruction or interrupt dispatch, returning the M-cycles spent
+    pub fn step(&mut self) -> u32 {
+        self.process_instruction().1
+    }
+
     fn fetch_byte(&mut self) -> u8 {
         let value = self.mmu.read(self.registers.PC);
         if self.halt_bug {
@@ -50,36 +59,23 @@ impl CPU {
         let value = self.registers.PC;
         self.registers.SP = self.registers.SP.wrapping_sub(1);
         self.mmu.write(self.registers.SP, (value >> 8) as u8);
-        let IE = self.mmu.read(0xFFFF);
-        let IF = self.mmu.read(0xFF0F);
+        // IE and IF are sampled before the low byte is pushed and IF is written back afterwards
+        let pending = self.mmu.pending_interrupts();
+        let interrupt_flags = self.mmu.interrupt_flags();
         self.tick_dot(4);
 
         self.registers.SP = self.registers.SP.wrapping_sub(1);
         self.mmu.write(self.registers.SP, value as u8);
 
         self.registers.IME = false;
+        // VBlank, LCD, Timer, Serial and Joypad, in order of priority
         let mut address = 0x00;
-        if IF & 0b1 > 0 && IE & 0b1 > 0 {
-            // Check VBlank
-            self.mmu.write(0xFF0F, IF & !0b1);
-            address = 0x40;
-        } else if IF & 0b10 > 0 && IE & 0b10 > 0 {
-            // Check LCD
-            self.mmu.write(0xFF0F, IF & !0b10);
-            address = 0x48;
-        } else if IF & 0b100 > 0 && IE & 0b100 > 0 {
-            // Check Timer
-            self.mmu.write(0xFF0F, IF & !0b100);
-            address = 0x50;
-        } else if IF & 0b1000 > 0 && IE & 0b1000 > 0 {
-            // Check Serial
-            self.mmu.write(0xFF0F, IF & !0b1000);
-            address = 0x58;
-        } else if IF & 0b10000 > 0 && IE & 0b10000 > 0 {
-            // Check Joypad
-            self.mmu.write(0xFF0F, IF & !0b10000);
-            address = 0x60;
+        if let Some(interrupt) = (0..5).find(|interrupt| pending & (1 << interrupt) > 0) {
+            self.mmu
+                .set_interrupt_flags(interrupt_flags & !(1 << interrupt));
+            address = 0x40 + interrupt as u16 * 8;
         }
+        self.mmu.interrupt_dispatched(value, address);
 
         self.registers.PC = address;
         self.tick_dot(4);
@@ -88,25 +84,12 @@ impl CPU {
     }
 
     fn tick_dot(&mut self, cycles: u32) {
-        let mut start = fastant::Instant::now();
-        for _ in 0..cycles {
-            self.mmu.ppu.tick();
-        }
-        self.time_ppu += start.elapsed();
-
-        start = fastant::Instant::now();
-        for _ in 0..cycles {
-            let DIV_APU = self.mmu.io_registers.update_timers();
-            self.mmu.tick();
-            self.mmu.handle_ppu_interrupts();
-            self.mmu.apu.tick(DIV_APU);
-        }
-        self.time_io += start.elapsed();
+        self.mmu.tick_dots(cycles);
     }
 
     pub(crate) fn process_instruction(&mut self) -> (bool, u32) {
         if self.halted {
-            if self.mmu.read(0xFFFF) & self.mmu.read(0xFF0F) & 0x1F > 0 {
+            if self.mmu.pending_interrupts() > 0 {
                 self.halted = false;
                 if self.registers.IME {
                     return (false, self.handle_interrupt());
@@ -117,10 +100,11 @@ impl CPU {
             }
         }
 
-        if self.registers.IME && (self.mmu.read(0xFFFF) & self.mmu.read(0xFF0F) & 0x1F > 0) {
+        if self.registers.IME && self.mmu.pending_interrupts() > 0 {
             return (false, self.handle_interrupt());
         }
 
+        self.mmu.begin_instruction(self.registers.PC);
         self.registers.IR = self.fetch_byte() as u16;
         self.tick_dot(4);
 
@@ -1207,7 +1191,7 @@ impl CPU {
     fn instr_HALT(&mut self) -> u32 {
         if self.registers.IME {
             self.halted = true;
-        } else if self.mmu.read(0xFFFF) & self.mmu.read(0xFF0F) & 0x1F > 0 {
+        } else if self.mmu.pending_interrupts() > 0 {
             self.halt_bug = true;
         } else {
             self.halted = true;
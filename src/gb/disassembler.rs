@@ -192,6 +192,24 @@ impl Disassembler {
         table
     }
 
+    // Nearest code symbol at or before the address, formatted as label+offset
+    pub(crate) fn symbol_for(&self, address: u16) -> Option<String> {
+        let symbol = self
+            .symbols
+            .iter()
+            .filter(|symbol| {
+                symbol.symbol_type == SymbolType::Code && symbol.label.address.address <= address
+            })
+            .max_by_key(|symbol| symbol.label.address.address)?;
+
+        let offset = address - symbol.label.address.address;
+        if offset == 0 {
+            Some(symbol.label.name.clone())
+        } else {
+            Some(format!("{}+{:#X}", symbol.label.name, offset))
+        }
+    }
+
     pub(crate) fn load_sym_file(sym_path: &Path) -> Vec<Symbol> {
         let mut symbols: Vec<Symbol> = Vec::new();
 
@@ -0,0 +1,50 @@
+// Timestamped bus events for the event viewer, recorded only while it is open
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum EventKind {
+    RegisterWrite { address: u16, value: u8 },
+    Interrupt { vector: u16 },
+    OamDma { source: u16 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Event {
+    pub(crate) ly: u8,
+    pub(crate) dot: u16,
+    pub(crate) pc: u16,
+    pub(crate) kind: EventKind,
+}
+
+#[derive(Default)]
+pub(crate) struct EventLog {
+    pub(crate) enabled: bool,
+    // Address of the instruction that is currently executing
+    pub(crate) pc: u16,
+    current_frame: Vec<Event>,
+    pub(crate) last_frame: Vec<Event>,
+}
+
+impl EventLog {
+    pub(crate) fn push(&mut self, ly: u8, dot: u16, kind: EventKind) {
+        self.current_frame.push(Event {
+            ly,
+            dot,
+            pc: self.pc,
+            kind,
+        });
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        self.last_frame = std::mem::take(&mut self.current_frame);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.current_frame.clear();
+        self.last_frame.clear();
+    }
+}
+
+// IO registers that affect rendering mid-frame
+pub(crate) fn is_tracked_register(address: u16) -> bool {
+    matches!(address, 0xFF40..=0xFF43 | 0xFF45 | 0xFF47..=0xFF4B)
+}
@@ -0,0 +1,608 @@
+use serde::{Deserialize, Serialize};
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+// Strength of the previous frame when ghosting, roughly matches the slow response of a DMG panel
+const GHOSTING_PERSISTENCE: f32 = 0.55;
+// Brightness of the gaps between LCD dots
+const LCD_GRID_BRIGHTNESS: f32 = 0.7;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Upscaler {
+    None,
+    Scale2x,
+    Scale3x,
+    Hq2x,
+    Xbr2x,
+}
+
+impl Upscaler {
+    pub const ALL: [Upscaler; 5] = [
+        Upscaler::None,
+        Upscaler::Scale2x,
+        Upscaler::Scale3x,
+        Upscaler::Hq2x,
+        Upscaler::Xbr2x,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Upscaler::None => "None",
+            Upscaler::Scale2x => "Scale2x",
+            Upscaler::Scale3x => "Scale3x",
+            Upscaler::Hq2x => "hq2x",
+            Upscaler::Xbr2x => "xBR 2x",
+        }
+    }
+
+    pub fn scale(&self) -> usize {
+        match self {
+            Upscaler::None => 1,
+            Upscaler::Scale3x => 3,
+            _ => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum FrameBlending {
+    Off,
+    // Averages the current and previous frame, makes flicker based transparency look solid
+    Mix,
+    // Exponential decay of previous frames
+    Ghosting,
+}
+
+impl FrameBlending {
+    pub const ALL: [FrameBlending; 3] = [
+        FrameBlending::Off,
+        FrameBlending::Mix,
+        FrameBlending::Ghosting,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FrameBlending::Off => "Off",
+            FrameBlending::Mix => "Mix",
+            FrameBlending::Ghosting => "Ghosting",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct FilterSettings {
+    pub upscaler: Upscaler,
+    pub lcd_grid: bool,
+    pub blending: FrameBlending,
+}
+
+impl Default for FilterSettings {
+    fn default() -> Self {
+        FilterSettings {
+            upscaler: Upscaler::None,
+            lcd_grid: false,
+            blending: FrameBlending::Off,
+        }
+    }
+}
+
+// RGBA image produced by the filters
+#[derive(Clone)]
+pub struct FilteredImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+type LastFrame = (u64, Option<Vec<[f32; 3]>>, Option<Vec<u8>>);
+
+// Runs the configured filters on RGBA frames, keeps the previous frame around for blending
+#[derive(Default)]
+pub struct FilterChain {
+    previous: Option<Vec<[f32; 3]>>,
+    previous_raw: Option<Vec<u8>>,
+    // Emulator frame of the last blend and the history from before it
+    last_frame: Option<LastFrame>,
+}
+
+impl FilterChain {
+    pub fn new() -> Self {
+        FilterChain::default()
+    }
+
+    pub fn reset(&mut self) {
+        self.previous = None;
+        self.previous_raw = None;
+        self.last_frame = None;
+    }
+
+    // For displays that may show the same emulator frame more than once. Showing a frame again
+    // blends it against the same history, so the history only advances when the frame changes
+    pub fn process_frame(
+        &mut self,
+        settings: &FilterSettings,
+        rgba: &[u8],
+        frame: u64,
+    ) -> FilteredImage {
+        if let Some((last_frame, previous, previous_raw)) = self.last_frame.take() {
+            if last_frame == frame {
+                self.previous = previous;
+                self.previous_raw = previous_raw;
+            }
+        }
+        let previous = self.previous.clone();
+        let previous_raw = self.previous_raw.clone();
+        let image = self.process(settings, rgba);
+        self.last_frame = Some((frame, previous, previous_raw));
+        image
+    }
+
+    pub fn process(&mut self, settings: &FilterSettings, rgba: &[u8]) -> FilteredImage {
+        let blended = self.blend(settings.blending, rgba);
+        let pixels = to_pixels(&blended);
+
+        let (mut pixels, mut width, mut height) = match settings.upscaler {
+            Upscaler::None => (pixels, SCREEN_WIDTH, SCREEN_HEIGHT),
+            Upscaler::Scale2x => (scale2x(&pixels, SCREEN_WIDTH, SCREEN_HEIGHT), 320, 288),
+            Upscaler::Scale3x => (scale3x(&pixels, SCREEN_WIDTH, SCREEN_HEIGHT), 480, 432),
+            Upscaler::Hq2x => (hq2x(&pixels, SCREEN_WIDTH, SCREEN_HEIGHT), 320, 288),
+            Upscaler::Xbr2x => (xbr2x(&pixels, SCREEN_WIDTH, SCREEN_HEIGHT), 320, 288),
+        };
+
+        if settings.lcd_grid {
+            // The grid needs a few output pixels per dot
+            let mut scale = settings.upscaler.scale();
+            if scale == 1 {
+                pixels = nearest(&pixels, width, height, 3);
+                width *= 3;
+                height *= 3;
+                scale = 3;
+            }
+            lcd_grid(&mut pixels, width, height, scale);
+        }
+
+        FilteredImage {
+            width,
+            height,
+            pixels: from_pixels(&pixels),
+        }
+    }
+
+    fn blend(&mut self, blending: FrameBlending, rgba: &[u8]) -> Vec<u8> {
+        match blending {
+            FrameBlending::Off => {
+                self.reset();
+                rgba.to_vec()
+            }
+            FrameBlending::Mix => {
+                self.previous = None;
+                let blended = match &self.previous_raw {
+                    Some(previous) if previous.len() == rgba.len() => rgba
+                        .iter()
+                        .zip(previous)
+                        .map(|(current, previous)| ((*current as u16 + *previous as u16) / 2) as u8)
+                        .collect(),
+                    _ => rgba.to_vec(),
+                };
+                self.previous_raw = Some(rgba.to_vec());
+                blended
+            }
+            FrameBlending::Ghosting => {
+                self.previous_raw = None;
+                let current: Vec<[f32; 3]> = rgba
+                    .chunks_exact(4)
+                    .map(|pixel| [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32])
+                    .collect();
+                let blended = match &self.previous {
+                    Some(previous) if previous.len() == current.len() => current
+                        .iter()
+                        .zip(previous)
+                        .map(|(current, previous)| {
+                            std::array::from_fn(|i| {
+                                current[i] * (1.0 - GHOSTING_PERSISTENCE)
+                                    + previous[i] * GHOSTING_PERSISTENCE
+                            })
+                        })
+                        .collect(),
+                    _ => current,
+                };
+                let rgba = blended
+                    .iter()
+                    .flat_map(|pixel| {
+                        [
+                            pixel[0].round() as u8,
+                            pixel[1].round() as u8,
+                            pixel[2].round() as u8,
+                            0xFF,
+                        ]
+                    })
+                    .collect();
+                self.previous = Some(blended);
+                rgba
+            }
+        }
+    }
+}
+
+// Pixels are packed as 0xRRGGBBAA so they can be compared directly
+fn to_pixels(rgba: &[u8]) -> Vec<u32> {
+    rgba.chunks_exact(4)
+        .map(|pixel| u32::from_be_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]))
+        .collect()
+}
+
+fn from_pixels(pixels: &[u32]) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|pixel| pixel.to_be_bytes())
+        .collect()
+}
+
+// Reads a pixel, clamping coordinates to the edges of the image
+fn sample(pixels: &[u32], width: usize, height: usize, x: isize, y: isize) -> u32 {
+    let x = x.clamp(0, width as isize - 1) as usize;
+    let y = y.clamp(0, height as isize - 1) as usize;
+    pixels[y * width + x]
+}
+
+fn nearest(pixels: &[u32], width: usize, height: usize, scale: usize) -> Vec<u32> {
+    let mut output = vec![0; width * height * scale * scale];
+    for y in 0..height * scale {
+        for x in 0..width * scale {
+            output[y * width * scale + x] = pixels[(y / scale) * width + x / scale];
+        }
+    }
+    output
+}
+
+fn scale2x(pixels: &[u32], width: usize, height: usize) -> Vec<u32> {
+    let mut output = vec![0; width * height * 4];
+    for y in 0..height {
+        for x in 0..width {
+            let at = |dx: isize, dy: isize| {
+                sample(pixels, width, height, x as isize + dx, y as isize + dy)
+            };
+            let (a, b, c, d, p) = (at(0, -1), at(1, 0), at(-1, 0), at(0, 1), at(0, 0));
+
+            let e0 = if c == a && c != d && a != b { a } else { p };
+            let e1 = if a == b && a != c && b != d { b } else { p };
+            let e2 = if d == c && d != b && c != a { c } else { p };
+            let e3 = if b == d && b != a && d != c { d } else { p };
+
+            let row = y * 2 * width * 2;
+            output[row + x * 2] = e0;
+            output[row + x * 2 + 1] = e1;
+            output[row + width * 2 + x * 2] = e2;
+            output[row + width * 2 + x * 2 + 1] = e3;
+        }
+    }
+    output
+}
+
+fn scale3x(pixels: &[u32], width: usize, height: usize) -> Vec<u32> {
+    let mut output = vec![0; width * height * 9];
+    for y in 0..height {
+        for x in 0..width {
+            let at = |dx: isize, dy: isize| {
+                sample(pixels, width, height, x as isize + dx, y as isize + dy)
+            };
+            let (a, b, c) = (at(-1, -1), at(0, -1), at(1, -1));
+            let (d, e, f) = (at(-1, 0), at(0, 0), at(1, 0));
+            let (g, h, i) = (at(-1, 1), at(0, 1), at(1, 1));
+
+            let block = if b != h && d != f {
+                [
+                    if d == b { d } else { e },
+                    if (d == b && e != c) || (b == f && e != a) {
+                        b
+                    } else {
+                        e
+                    },
+                    if b == f { f } else { e },
+                    if (d == b && e != g) || (d == h && e != a) {
+                        d
+                    } else {
+                        e
+                    },
+                    e,
+                    if (b == f && e != i) || (h == f && e != c) {
+                        f
+                    } else {
+                        e
+                    },
+                    if d == h { d } else { e },
+                    if (d == h && e != i) || (h == f && e != g) {
+                        h
+                    } else {
+                        e
+                    },
+                    if h == f { f } else { e },
+                ]
+            } else {
+                [e; 9]
+            };
+
+            for (index, pixel) in block.into_iter().enumerate() {
+                let output_x = x * 3 + index % 3;
+                let output_y = y * 3 + index / 3;
+                output[output_y * width * 3 + output_x] = pixel;
+            }
+        }
+    }
+    output
+}
+
+// Compact hq2x: each output quadrant is interpolated from the neighbours towards its corner
+// depending on which of them are perceptually different in YUV space
+fn hq2x(pixels: &[u32], width: usize, height: usize) -> Vec<u32> {
+    let mut output = vec![0; width * height * 4];
+    for y in 0..height {
+        for x in 0..width {
+            let at = |dx: isize, dy: isize| {
+                sample(pixels, width, height, x as isize + dx, y as isize + dy)
+            };
+            let center = at(0, 0);
+
+            for (corner_x, corner_y) in [(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+                let horizontal = at(corner_x, 0);
+                let vertical = at(0, corner_y);
+                let diagonal = at(corner_x, corner_y);
+
+                let pixel = if !yuv_differs(horizontal, vertical) && yuv_differs(center, horizontal)
+                {
+                    // Edge crossing the corner
+                    interpolate(&[(center, 2), (horizontal, 1), (vertical, 1)])
+                } else if yuv_differs(center, horizontal) && yuv_differs(center, vertical) {
+                    interpolate(&[(center, 3), (diagonal, 1)])
+                } else if yuv_differs(center, diagonal) {
+                    interpolate(&[(center, 6), (horizontal, 1), (vertical, 1)])
+                } else {
+                    center
+                };
+
+                let output_x = x * 2 + (corner_x + 1) as usize / 2;
+                let output_y = y * 2 + (corner_y + 1) as usize / 2;
+                output[output_y * width * 2 + output_x] = pixel;
+            }
+        }
+    }
+    output
+}
+
+// xBR level 1 at 2x: detects edges from weighted colour distances in a 5x5 neighbourhood and
+// blends each corner towards the dominant edge colour
+fn xbr2x(pixels: &[u32], width: usize, height: usize) -> Vec<u32> {
+    let mut output = vec![0; width * height * 4];
+    for y in 0..height {
+        for x in 0..width {
+            let e = sample(pixels, width, height, x as isize, y as isize);
+
+            // Offsets are written for the bottom right corner and rotated for the others
+            for rotation in 0..4 {
+                let at = |dx: isize, dy: isize| {
+                    let (dx, dy) = rotate(dx, dy, rotation);
+                    sample(pixels, width, height, x as isize + dx, y as isize + dy)
+                };
+                let (b, c, d, f, g, h, i) = (
+                    at(0, -1),
+                    at(1, -1),
+                    at(-1, 0),
+                    at(1, 0),
+                    at(-1, 1),
+                    at(0, 1),
+                    at(1, 1),
+                );
+                let (f4, i4, h5, i5) = (at(2, 0), at(2, 1), at(0, 2), at(1, 2));
+
+                let edge_e_i = distance(e, c)
+                    + distance(e, g)
+                    + distance(i, f4)
+                    + distance(i, h5)
+                    + 4 * distance(h, f);
+                let edge_h_f = distance(h, d)
+                    + distance(h, i5)
+                    + distance(f, i4)
+                    + distance(f, b)
+                    + 4 * distance(e, i);
+
+                let pixel = if edge_e_i < edge_h_f && e != f && e != h {
+                    let edge = if distance(e, f) <= distance(e, h) {
+                        f
+                    } else {
+                        h
+                    };
+                    interpolate(&[(e, 1), (edge, 1)])
+                } else {
+                    e
+                };
+
+                let (corner_x, corner_y) = rotate(1, 1, rotation);
+                let output_x = x * 2 + (corner_x + 1) as usize / 2;
+                let output_y = y * 2 + (corner_y + 1) as usize / 2;
+                output[output_y * width * 2 + output_x] = pixel;
+            }
+        }
+    }
+    output
+}
+
+fn rotate(dx: isize, dy: isize, rotation: usize) -> (isize, isize) {
+    (0..rotation).fold((dx, dy), |(dx, dy), _| (-dy, dx))
+}
+
+// Darkens the border of every scaled dot
+fn lcd_grid(pixels: &mut [u32], width: usize, height: usize, scale: usize) {
+    for y in 0..height {
+        for x in 0..width {
+            if x % scale == scale - 1 || y % scale == scale - 1 {
+                let [r, g, b, a] = pixels[y * width + x].to_be_bytes();
+                let darken = |value: u8| (value as f32 * LCD_GRID_BRIGHTNESS) as u8;
+                pixels[y * width + x] = u32::from_be_bytes([darken(r), darken(g), darken(b), a]);
+            }
+        }
+    }
+}
+
+fn interpolate(weighted: &[(u32, u32)]) -> u32 {
+    let total: u32 = weighted.iter().map(|(_, weight)| weight).sum();
+    let channel = |shift: u32| {
+        weighted
+            .iter()
+            .map(|(pixel, weight)| ((pixel >> shift) & 0xFF) * weight)
+            .sum::<u32>()
+            / total
+    };
+    (channel(24) << 24) | (channel(16) << 16) | (channel(8) << 8) | 0xFF
+}
+
+fn yuv(pixel: u32) -> (i32, i32, i32) {
+    let [r, g, b, _] = pixel.to_be_bytes();
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let y = (r * 299 + g * 587 + b * 114) / 1000;
+    let u = (b - y) * 492 / 1000;
+    let v = (r - y) * 877 / 1000;
+    (y, u, v)
+}
+
+// Thresholds used by the original hqx filters
+fn yuv_differs(a: u32, b: u32) -> bool {
+    let (y1, u1, v1) = yuv(a);
+    let (y2, u2, v2) = yuv(b);
+    (y1 - y2).abs() > 48 || (u1 - u2).abs() > 7 || (v1 - v2).abs() > 6
+}
+
+fn distance(a: u32, b: u32) -> u32 {
+    let (y1, u1, v1) = yuv(a);
+    let (y2, u2, v2) = yuv(b);
+    (48 * (y1 - y2).unsigned_abs() + 7 * (u1 - u2).unsigned_abs() + 6 * (v1 - v2).unsigned_abs())
+        / 8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: u32 = 0x000000FF;
+    const WHITE: u32 = 0xFFFFFFFF;
+
+    // A single black dot in the top left corner of a 2x2 image
+    fn corner() -> Vec<u32> {
+        vec![BLACK, WHITE, WHITE, WHITE]
+    }
+
+    #[test]
+    fn scale2x_rounds_corner() {
+        let (k, w) = (BLACK, WHITE);
+        #[rustfmt::skip]
+        let expected = vec![
+            k, k, w, w,
+            k, w, w, w,
+            w, w, w, w,
+            w, w, w, w,
+        ];
+        assert_eq!(scale2x(&corner(), 2, 2), expected);
+        assert_eq!(scale2x(&[WHITE; 4], 2, 2), vec![WHITE; 16]);
+    }
+
+    #[test]
+    fn scale3x_rounds_corner() {
+        let (k, w) = (BLACK, WHITE);
+        #[rustfmt::skip]
+        let expected = vec![
+            k, k, k, w, w, w,
+            k, k, w, w, w, w,
+            k, w, w, w, w, w,
+            w, w, w, w, w, w,
+            w, w, w, w, w, w,
+            w, w, w, w, w, w,
+        ];
+        assert_eq!(scale3x(&corner(), 2, 2), expected);
+        assert_eq!(scale3x(&[WHITE; 4], 2, 2), vec![WHITE; 36]);
+    }
+
+    #[test]
+    fn lcd_grid_darkens_cell_borders() {
+        let mut pixels = vec![WHITE; 6 * 6];
+        lcd_grid(&mut pixels, 6, 6, 3);
+
+        let dark = (0xFF as f32 * LCD_GRID_BRIGHTNESS) as u8;
+        let darkened = u32::from_be_bytes([dark, dark, dark, 0xFF]);
+        for y in 0..6 {
+            for x in 0..6 {
+                let expected = if x % 3 == 2 || y % 3 == 2 {
+                    darkened
+                } else {
+                    WHITE
+                };
+                assert_eq!(pixels[y * 6 + x], expected, "pixel {x},{y}");
+            }
+        }
+    }
+
+    #[test]
+    fn mix_averages_previous_frame() {
+        let mut chain = FilterChain::new();
+        assert_eq!(
+            chain.blend(FrameBlending::Mix, &[0, 100, 200, 255]),
+            vec![0, 100, 200, 255]
+        );
+        assert_eq!(
+            chain.blend(FrameBlending::Mix, &[200, 100, 0, 255]),
+            vec![100, 100, 100, 255]
+        );
+
+        // Switching blending off drops the history
+        chain.blend(FrameBlending::Off, &[0, 0, 0, 255]);
+        assert_eq!(
+            chain.blend(FrameBlending::Mix, &[200, 200, 200, 255]),
+            vec![200, 200, 200, 255]
+        );
+    }
+
+    #[test]
+    fn ghosting_decays_previous_frames() {
+        let mut chain = FilterChain::new();
+        assert_eq!(
+            chain.blend(FrameBlending::Ghosting, &[0, 0, 0, 255]),
+            vec![0, 0, 0, 255]
+        );
+
+        let current = 200.0 * (1.0 - GHOSTING_PERSISTENCE);
+        let value = current.round() as u8;
+        assert_eq!(
+            chain.blend(FrameBlending::Ghosting, &[200, 200, 200, 0]),
+            vec![value, value, value, 255]
+        );
+
+        // The unrounded history carries over to the next frame
+        let value = (200.0 * (1.0 - GHOSTING_PERSISTENCE) + current * GHOSTING_PERSISTENCE).round();
+        assert_eq!(
+            chain.blend(FrameBlending::Ghosting, &[200, 200, 200, 255])[0],
+            value as u8
+        );
+    }
+
+    #[test]
+    fn process_frame_keeps_history_for_repeated_frames() {
+        let settings = FilterSettings {
+            blending: FrameBlending::Mix,
+            ..FilterSettings::default()
+        };
+        let black = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+        let grey = vec![200; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+        let mut chain = FilterChain::new();
+
+        chain.process_frame(&settings, &black, 1);
+        let image = chain.process_frame(&settings, &grey, 2);
+        assert_eq!(image.pixels[0], 100);
+        assert_eq!((image.width, image.height), (SCREEN_WIDTH, SCREEN_HEIGHT));
+
+        // Showing frame 2 again still blends against frame 1
+        let image = chain.process_frame(&settings, &grey, 2);
+        assert_eq!(image.pixels[0], 100);
+
+        let image = chain.process_frame(&settings, &grey, 3);
+        assert_eq!(image.pixels[0], 200);
+    }
+}
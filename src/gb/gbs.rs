@@ -0,0 +1,231 @@
+use std::fs;
+
+const HEADER_SIZE: usize = 0x70;
+// Driver code placed in the unused space below the load address, which is at least 0x400
+const DRIVER_ADDRESS: u16 = 0x0100;
+const PLAY_HANDLER_ADDRESS: u16 = 0x0080;
+
+// Game Boy Sound System music rip
+#[derive(Clone)]
+pub struct GbsFile {
+    pub title: String,
+    pub author: String,
+    pub copyright: String,
+    pub song_count: u8,
+    pub first_song: u8,
+    load_address: u16,
+    init_address: u16,
+    play_address: u16,
+    stack_pointer: u16,
+    timer_modulo: u8,
+    timer_control: u8,
+    data: Vec<u8>,
+}
+
+impl GbsFile {
+    pub fn load(path: &str) -> Result<GbsFile, String> {
+        let bytes = fs::read(path).map_err(|err| format!("Failed to read GBS file: {}", err))?;
+        GbsFile::parse(&bytes)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<GbsFile, String> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..3] != b"GBS" {
+            return Err("Not a GBS file".to_string());
+        }
+        if bytes[3] != 1 {
+            return Err(format!("Unsupported GBS version: {}", bytes[3]));
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let text = |offset: usize| {
+            let field = &bytes[offset..offset + 32];
+            let end = field.iter().position(|c| *c == 0).unwrap_or(field.len());
+            String::from_utf8_lossy(&field[..end]).trim().to_string()
+        };
+
+        let gbs = GbsFile {
+            title: text(0x10),
+            author: text(0x30),
+            copyright: text(0x50),
+            song_count: bytes[0x04],
+            // Songs are numbered from 1, keep the first one within the file
+            first_song: bytes[0x05].clamp(1, bytes[0x04].max(1)),
+            load_address: word(0x06),
+            init_address: word(0x08),
+            play_address: word(0x0A),
+            stack_pointer: word(0x0C),
+            timer_modulo: bytes[0x0E],
+            timer_control: bytes[0x0F],
+            data: bytes[HEADER_SIZE..].to_vec(),
+        };
+
+        if gbs.load_address < 0x0400 || gbs.load_address >= 0x8000 {
+            return Err(format!(
+                "Invalid GBS load address: {:#06X}",
+                gbs.load_address
+            ));
+        }
+        if gbs.song_count == 0 {
+            return Err("GBS file contains no songs".to_string());
+        }
+        Ok(gbs)
+    }
+
+    // The play routine is driven by the timer when TAC enables it, otherwise by VBlank
+    pub fn uses_timer(&self) -> bool {
+        self.timer_control & 0x04 != 0
+    }
+
+    // Builds a ROM with the payload at its load address and a small driver that calls the init
+    // routine for the given song (0 based) and then calls the play routine from an interrupt
+    pub(crate) fn rom_image(&self, song: u8) -> Vec<u8> {
+        let end = self.load_address as usize + self.data.len();
+        let size = end.max(0x8000).div_ceil(0x4000) * 0x4000;
+        let mut rom = vec![0xFF; size];
+        rom[self.load_address as usize..end].copy_from_slice(&self.data);
+
+        // RST vectors jump to the same offset from the load address
+        for rst in (0x00..0x40).step_by(8) {
+            let [lo, hi] = (self.load_address + rst).to_le_bytes();
+            rom[rst as usize..rst as usize + 3].copy_from_slice(&[0xC3, lo, hi]);
+        }
+
+        // Interrupt vectors, only the one matching the play rate is enabled
+        let [handler_lo, handler_hi] = PLAY_HANDLER_ADDRESS.to_le_bytes();
+        for vector in [0x40, 0x48, 0x50, 0x58, 0x60] {
+            rom[vector] = 0xD9; // RETI
+        }
+        let play_vector = if self.uses_timer() { 0x50 } else { 0x40 };
+        rom[play_vector..play_vector + 3].copy_from_slice(&[0xC3, handler_lo, handler_hi]);
+
+        let mut handler = Vec::new();
+        handler.extend([0xF5, 0xC5, 0xD5, 0xE5]); // PUSH AF, BC, DE, HL
+        handler.extend([0xCD].into_iter().chain(self.play_address.to_le_bytes())); // CALL play
+        handler.extend([0xE1, 0xD1, 0xC1, 0xF1]); // POP HL, DE, BC, AF
+        handler.push(0xD9); // RETI
+        let handler_start = PLAY_HANDLER_ADDRESS as usize;
+        rom[handler_start..handler_start + handler.len()].copy_from_slice(&handler);
+
+        let interrupt_enable = if self.uses_timer() { 0x04 } else { 0x01 };
+        let mut driver = Vec::new();
+        driver.push(0xF3); // DI
+        driver.extend([0x31].into_iter().chain(self.stack_pointer.to_le_bytes())); // LD SP, nn
+        driver.extend([0x3E, self.timer_modulo, 0xE0, 0x06]); // LD A, n; LDH (TMA), A
+        driver.extend([0x3E, self.timer_control, 0xE0, 0x07]); // LD A, n; LDH (TAC), A
+        driver.extend([0x3E, interrupt_enable, 0xE0, 0xFF]); // LD A, n; LDH (IE), A
+        driver.extend([0xAF, 0xE0, 0x0F]); // XOR A; LDH (IF), A
+        driver.extend([0x3E, song]); // LD A, song
+        driver.extend([0xCD].into_iter().chain(self.init_address.to_le_bytes())); // CALL init
+        driver.push(0xFB); // EI
+        driver.extend([0x76, 0x00, 0x18, 0xFC]); // HALT; NOP; JR -4
+        let driver_start = DRIVER_ADDRESS as usize;
+        rom[driver_start..driver_start + driver.len()].copy_from_slice(&driver);
+
+        rom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gbs_bytes(timer_control: u8) -> Vec<u8> {
+        let mut bytes = vec![0; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(b"GBS\x01");
+        bytes[0x04] = 3; // Song count
+        bytes[0x05] = 2; // First song
+        bytes[0x06..0x08].copy_from_slice(&0x0400u16.to_le_bytes()); // Load
+        bytes[0x08..0x0A].copy_from_slice(&0x0400u16.to_le_bytes()); // Init
+        bytes[0x0A..0x0C].copy_from_slice(&0x0410u16.to_le_bytes()); // Play
+        bytes[0x0C..0x0E].copy_from_slice(&0xDFFFu16.to_le_bytes()); // Stack pointer
+        bytes[0x0E] = 0xAB;
+        bytes[0x0F] = timer_control;
+        bytes[0x10..0x14].copy_from_slice(b"Song");
+        bytes[0x30..0x36].copy_from_slice(b"Author");
+        bytes[0x50..0x54].copy_from_slice(b"2024");
+        bytes.extend([0xC9; 0x20]); // RET
+        bytes
+    }
+
+    #[test]
+    fn parse_header() {
+        let gbs = GbsFile::parse(&gbs_bytes(0)).unwrap();
+        assert_eq!(gbs.title, "Song");
+        assert_eq!(gbs.author, "Author");
+        assert_eq!(gbs.copyright, "2024");
+        assert_eq!(gbs.song_count, 3);
+        assert_eq!(gbs.first_song, 2);
+        assert_eq!(gbs.load_address, 0x0400);
+        assert_eq!(gbs.init_address, 0x0400);
+        assert_eq!(gbs.play_address, 0x0410);
+        assert_eq!(gbs.stack_pointer, 0xDFFF);
+        assert_eq!(gbs.timer_modulo, 0xAB);
+        assert_eq!(gbs.data.len(), 0x20);
+        assert!(!gbs.uses_timer());
+
+        let mut bytes = gbs_bytes(0);
+        bytes[0x05] = 5;
+        assert_eq!(GbsFile::parse(&bytes).unwrap().first_song, 3);
+        bytes[0x05] = 0;
+        assert_eq!(GbsFile::parse(&bytes).unwrap().first_song, 1);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(GbsFile::parse(b"GBS").is_err());
+
+        let mut bytes = gbs_bytes(0);
+        bytes[0] = b'X';
+        assert!(GbsFile::parse(&bytes).is_err());
+
+        let mut bytes = gbs_bytes(0);
+        bytes[0x03] = 2;
+        assert!(GbsFile::parse(&bytes).is_err());
+
+        let mut bytes = gbs_bytes(0);
+        bytes[0x06..0x08].copy_from_slice(&0x0200u16.to_le_bytes());
+        assert!(GbsFile::parse(&bytes).is_err());
+
+        let mut bytes = gbs_bytes(0);
+        bytes[0x04] = 0;
+        assert!(GbsFile::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn rom_image_vblank() {
+        let gbs = GbsFile::parse(&gbs_bytes(0)).unwrap();
+        let rom = gbs.rom_image(1);
+        assert_eq!(rom.len(), 0x8000);
+        assert_eq!(&rom[0x0400..0x0420], &[0xC9; 0x20]);
+        // RST 08 jumps to the load address + 8
+        assert_eq!(&rom[0x08..0x0B], &[0xC3, 0x08, 0x04]);
+        // VBlank calls the play routine, the timer interrupt returns
+        assert_eq!(&rom[0x40..0x43], &[0xC3, 0x80, 0x00]);
+        assert_eq!(rom[0x50], 0xD9);
+        assert_eq!(
+            &rom[0x80..0x8C],
+            &[0xF5, 0xC5, 0xD5, 0xE5, 0xCD, 0x10, 0x04, 0xE1, 0xD1, 0xC1, 0xF1, 0xD9]
+        );
+
+        let driver = &rom[DRIVER_ADDRESS as usize..];
+        assert_eq!(&driver[0..4], &[0xF3, 0x31, 0xFF, 0xDF]);
+        // LDH (IE), A enables VBlank only
+        assert_eq!(&driver[12..16], &[0x3E, 0x01, 0xE0, 0xFF]);
+        // LD A, song followed by CALL init
+        assert_eq!(&driver[19..24], &[0x3E, 0x01, 0xCD, 0x00, 0x04]);
+    }
+
+    #[test]
+    fn rom_image_timer() {
+        let gbs = GbsFile::parse(&gbs_bytes(0x04)).unwrap();
+        assert!(gbs.uses_timer());
+        let rom = gbs.rom_image(0);
+        assert_eq!(rom[0x40], 0xD9);
+        assert_eq!(&rom[0x50..0x53], &[0xC3, 0x80, 0x00]);
+
+        let driver = &rom[DRIVER_ADDRESS as usize..];
+        assert_eq!(&driver[4..8], &[0x3E, 0xAB, 0xE0, 0x06]);
+        assert_eq!(&driver[8..12], &[0x3E, 0x04, 0xE0, 0x07]);
+        assert_eq!(&driver[12..16], &[0x3E, 0x04, 0xE0, 0xFF]);
+    }
+}
@@ -1,9 +1,9 @@
 #![allow(non_snake_case)]
 
+use crate::gb::joypad::Button;
 use intbits::Bits;
 use log::{log, Level};
 use std::collections::HashMap;
-use winit::keyboard::KeyCode;
 
 pub struct IORegisters {
     // IO registers
@@ -17,14 +17,15 @@ pub struct IORegisters {
     FF07_TAC_timer_control: u8,
     pub(crate) FF0F_IF_interrupt_flag: u8,
     pub(crate) FF50_boot_rom_enabled: bool,
-    FFFF_IE_interrupt_enable: u8,
+    pub(crate) FFFF_IE_interrupt_enable: u8,
     // Internal state
     clock_counter: u16,
     TIMA_overflowed: bool,
     TIMA_counter: u8,
-    pub(crate) inputs: HashMap<KeyCode, bool>,
+    pub(crate) inputs: HashMap<Button, bool>,
     should_update_DIV_APU: bool,
     serial_timer: u16,
+    pub(crate) joypad_polled: bool,
 }
 
 impl IORegisters {
@@ -49,38 +50,40 @@ impl IORegisters {
             inputs: HashMap::new(),
             should_update_DIV_APU: false,
             serial_timer: 0,
+            joypad_polled: false,
         }
     }
 
     pub fn read(&mut self, address: u16) -> u8 {
         match address {
             0xFF00 => {
+                self.joypad_polled = true;
                 let mut value = self.FF00_JOYP | 0xF;
                 if self.FF00_JOYP & 0x10 == 0 {
                     // d-pad
-                    if *self.inputs.get(&KeyCode::ArrowDown).unwrap_or(&false) {
+                    if *self.inputs.get(&Button::Down).unwrap_or(&false) {
                         value &= 0b11110111;
-                    } else if *self.inputs.get(&KeyCode::ArrowUp).unwrap_or(&false) {
+                    } else if *self.inputs.get(&Button::Up).unwrap_or(&false) {
                         value &= 0b11111011;
                     }
-                    if *self.inputs.get(&KeyCode::ArrowLeft).unwrap_or(&false) {
+                    if *self.inputs.get(&Button::Left).unwrap_or(&false) {
                         value &= 0b11111101;
-                    } else if *self.inputs.get(&KeyCode::ArrowRight).unwrap_or(&false) {
+                    } else if *self.inputs.get(&Button::Right).unwrap_or(&false) {
                         value &= 0b11111110;
                     }
                 }
                 if self.FF00_JOYP & 0x20 == 0 {
                     // buttons
-                    if *self.inputs.get(&KeyCode::KeyF).unwrap_or(&false) {
+                    if *self.inputs.get(&Button::Start).unwrap_or(&false) {
                         value &= 0b11110111;
                     }
-                    if *self.inputs.get(&KeyCode::KeyD).unwrap_or(&false) {
+                    if *self.inputs.get(&Button::Select).unwrap_or(&false) {
                         value &= 0b11111011;
                     }
-                    if *self.inputs.get(&KeyCode::KeyS).unwrap_or(&false) {
+                    if *self.inputs.get(&Button::B).unwrap_or(&false) {
                         value &= 0b11111101;
                     }
-                    if *self.inputs.get(&KeyCode::KeyA).unwrap_or(&false) {
+                    if *self.inputs.get(&Button::A).unwrap_or(&false) {
                         value &= 0b11111110;
                     }
                 }
@@ -1,3 +1,4 @@
+use crate::gb::mbc::gbs::GBSMapper;
 use crate::gb::mbc::mbc1::MBC1;
 use crate::gb::mbc::mbc2::MBC2;
 use crate::gb::mbc::mbc3::MBC3;
@@ -8,6 +9,7 @@ use log::{log, Level};
 use std::any::Any;
 use std::path::Path;
 
+mod gbs;
 mod mbc1;
 mod mbc2;
 mod mbc3;
@@ -52,6 +54,18 @@ pub trait MBC {
     fn name(&self) -> String;
     fn save_ram(&self);
     fn load_ram(&mut self);
+    // Currently mapped banks, used to make cheats and debug views bank-aware
+    fn rom_bank(&self, address: u16) -> usize;
+    fn ram_bank(&self) -> usize;
+    // Raw ROM and RAM contents for all banks, without going through the memory bus
+    fn rom(&self) -> &[u8];
+    fn rom_mut(&mut self) -> &mut [u8];
+    fn ram(&self) -> &[u8];
+    fn ram_mut(&mut self) -> &mut [u8];
+}
+
+pub fn create_GBS_mapper(rom: Vec<u8>) -> Box<dyn MBC> {
+    Box::new(GBSMapper::new(rom))
 }
 
 pub fn create_MBC(rom: Vec<u8>) -> Box<dyn MBC> {
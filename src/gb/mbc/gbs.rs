@@ -0,0 +1,80 @@
+use crate::gb::mbc::MBC;
+
+// Minimal mapper for GBS playback, MBC1 style ROM banking and 8 KiB of RAM
+pub struct GBSMapper {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rom_bank: usize,
+}
+
+impl MBC for GBSMapper {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                let offset = self.rom_bank * 0x4000 + (address as usize - 0x4000);
+                self.rom.get(offset).copied().unwrap_or(0xFF)
+            }
+            0xA000..=0xBFFF => self.ram[(address - 0xA000) as usize],
+            _ => {
+                panic!("Tried to read from cartridge with invalid address!")
+            }
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x2000..=0x3FFF => {
+                self.rom_bank = (value as usize).max(1);
+            }
+            0xA000..=0xBFFF => self.ram[(address - 0xA000) as usize] = value,
+            _ => {}
+        }
+    }
+
+    fn name(&self) -> String {
+        String::from("GBS")
+    }
+
+    fn save_ram(&self) {}
+
+    fn load_ram(&mut self) {}
+
+    fn rom_bank(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        0
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn rom_mut(&mut self) -> &mut [u8] {
+        &mut self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+impl GBSMapper {
+    pub fn new(rom: Vec<u8>) -> Self {
+        GBSMapper {
+            rom,
+            ram: vec![0; 0x2000],
+            rom_bank: 1,
+        }
+    }
+}
@@ -27,33 +27,7 @@ pub struct MBC1 {
 impl MBC for MBC1 {
     fn read(&self, address: u16) -> u8 {
         match address {
-            0x0000..=0x3FFF => {
-                let mut mapped_address = if self.reg_banking_mode {
-                    if self.is_MBC1M {
-                        ((self.reg_ram_bank_number.bits(0..2) as usize) << 18)
-                            | (address.bits(0..14) as usize)
-                    } else {
-                        ((self.reg_ram_bank_number as usize) << 19) | (address.bits(0..14) as usize)
-                    }
-                } else {
-                    address.bits(0..14) as usize
-                };
-                mapped_address &= (1 << (self.rom_banks.ilog2() + 14)) - 1;
-                self.rom[mapped_address]
-            }
-            0x4000..=0x7FFF => {
-                let mut mapped_address = if self.is_MBC1M {
-                    ((self.reg_ram_bank_number.bits(0..2) as usize) << 18)
-                        | ((self.reg_rom_bank_number.bits(0..4) as usize) << 14)
-                        | (address.bits(0..14) as usize)
-                } else {
-                    ((self.reg_ram_bank_number as usize) << 19)
-                        | ((self.reg_rom_bank_number.bits(0..5) as usize) << 14)
-                        | (address.bits(0..14) as usize)
-                };
-                mapped_address &= (1 << (self.rom_banks.ilog2() + 14)) - 1;
-                self.rom[mapped_address]
-            }
+            0x0000..=0x7FFF => self.rom[self.map_rom_address(address)],
             0xA000..=0xBFFF => {
                 if self.has_ram && self.reg_ram_enabled && self.ram_size > 0 {
                     let mut mapped_address = if self.reg_banking_mode {
@@ -153,9 +127,66 @@ impl MBC for MBC1 {
             }
         }
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.map_rom_address(address) >> 14
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.reg_banking_mode {
+            self.reg_ram_bank_number as usize
+        } else {
+            0
+        }
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn rom_mut(&mut self) -> &mut [u8] {
+        &mut self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
 }
 
 impl MBC1 {
+    fn map_rom_address(&self, address: u16) -> usize {
+        let mapped_address = match address {
+            0x0000..=0x3FFF => {
+                if self.reg_banking_mode {
+                    if self.is_MBC1M {
+                        ((self.reg_ram_bank_number.bits(0..2) as usize) << 18)
+                            | (address.bits(0..14) as usize)
+                    } else {
+                        ((self.reg_ram_bank_number as usize) << 19) | (address.bits(0..14) as usize)
+                    }
+                } else {
+                    address.bits(0..14) as usize
+                }
+            }
+            _ => {
+                if self.is_MBC1M {
+                    ((self.reg_ram_bank_number.bits(0..2) as usize) << 18)
+                        | ((self.reg_rom_bank_number.bits(0..4) as usize) << 14)
+                        | (address.bits(0..14) as usize)
+                } else {
+                    ((self.reg_ram_bank_number as usize) << 19)
+                        | ((self.reg_rom_bank_number.bits(0..5) as usize) << 14)
+                        | (address.bits(0..14) as usize)
+                }
+            }
+        };
+        mapped_address & ((1 << (self.rom_banks.ilog2() + 14)) - 1)
+    }
+
     pub(crate) fn new(
         name: String,
         rom: &[u8],
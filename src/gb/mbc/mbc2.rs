@@ -102,6 +102,33 @@ impl MBC for MBC2 {
             file.read_exact(&mut self.ram).unwrap();
         }
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => (self.reg_rom_bank_number.bits(0..4) as usize) & (self.rom_banks - 1),
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        0
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn rom_mut(&mut self) -> &mut [u8] {
+        &mut self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
 }
 
 impl MBC2 {
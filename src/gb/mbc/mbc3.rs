@@ -126,6 +126,33 @@ impl MBC for MBC3 {
             }
         }
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.reg_rom_bank_number as usize,
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        self.reg_ram_bank_number as usize
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn rom_mut(&mut self) -> &mut [u8] {
+        &mut self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
 }
 
 impl MBC3 {
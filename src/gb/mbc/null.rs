@@ -14,6 +14,24 @@ impl MBC for NullMBC {
     }
     fn save_ram(&self) {}
     fn load_ram(&mut self) {}
+    fn rom_bank(&self, address: u16) -> usize {
+        0
+    }
+    fn ram_bank(&self) -> usize {
+        0
+    }
+    fn rom(&self) -> &[u8] {
+        &[]
+    }
+    fn rom_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+    fn ram(&self) -> &[u8] {
+        &[]
+    }
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
 }
 
 impl NullMBC {
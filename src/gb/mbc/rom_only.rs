@@ -51,6 +51,30 @@ impl MBC for ROMOnly {
     fn save_ram(&self) {}
 
     fn load_ram(&mut self) {}
+
+    fn rom_bank(&self, address: u16) -> usize {
+        (address >> 14) as usize
+    }
+
+    fn ram_bank(&self) -> usize {
+        0
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn rom_mut(&mut self) -> &mut [u8] {
+        &mut self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
 }
 
 impl ROMOnly {
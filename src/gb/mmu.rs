@@ -1,11 +1,22 @@
-use crate::audio::AudioPlayer;
+use crate::audio::AudioSink;
 use crate::gb::apu::APU;
+use crate::gb::bus::Bus;
+use crate::gb::cheats::Cheats;
+use crate::gb::events::{is_tracked_register, EventKind, EventLog};
 use crate::gb::io_registers::IORegisters;
 use crate::gb::mbc::{create_MBC, MBC};
 use crate::gb::ppu::PPU;
 use intbits::Bits;
 use rand::Rng;
+use std::collections::HashSet;
 use std::fs;
+use std::time::Duration;
+
+pub(crate) struct MemoryAccess {
+    pub(crate) address: u16,
+    pub(crate) value: u8,
+    pub(crate) write: bool,
+}
 
 pub struct MMU {
     // 256 bytes: 0x0000 -> 0x00FF
@@ -32,10 +43,22 @@ pub struct MMU {
     source_address: u16,
     transfer_active: bool,
     reg_FF46_DMA: u8,
+    // Script hooks
+    // Accesses to hooked addresses are recorded here and handled after the instruction completes
+    pub(crate) read_hooks: HashSet<u16>,
+    pub(crate) write_hooks: HashSet<u16>,
+    pub(crate) hooked_accesses: Vec<MemoryAccess>,
+    // Active Game Genie / GameShark cheats
+    pub(crate) cheats: Cheats,
+    // Event viewer
+    pub(crate) events: EventLog,
+    // Profiling
+    pub(crate) time_ppu: Duration,
+    pub(crate) time_io: Duration,
 }
 
 impl MMU {
-    pub fn new(audio_player: AudioPlayer) -> Self {
+    pub fn new(audio_sink: Box<dyn AudioSink>) -> Self {
         let mut rng = rand::rng();
         MMU {
             boot_rom: *include_bytes!("../roms/bootix_dmg.bin"),
@@ -44,12 +67,20 @@ impl MMU {
             mbc: create_MBC(Vec::new()),
             io_registers: IORegisters::new(),
             ppu: PPU::new(),
-            apu: APU::new(audio_player),
+            apu: APU::new(audio_sink),
             // DMA
             dot_counter: 0,
             source_address: 0xFF00,
             transfer_active: false,
             reg_FF46_DMA: 0xFF,
+            // Script hooks
+            read_hooks: HashSet::new(),
+            write_hooks: HashSet::new(),
+            hooked_accesses: Vec::new(),
+            cheats: Cheats::default(),
+            events: EventLog::default(),
+            time_ppu: Duration::new(0, 0),
+            time_io: Duration::new(0, 0),
         }
     }
 
@@ -88,6 +119,8 @@ impl MMU {
             return self.boot_rom[address as usize];
         }
 
+        let requested_address = address;
+
         if self.transfer_active
             && self.dot_counter >= 4
             && !((0xFF80..=0xFFFE).contains(&address) || address == 0xFF46)
@@ -116,8 +149,60 @@ impl MMU {
             }
         }
 
+        let value = self.read_bus(address);
+
+        if !self.read_hooks.is_empty() && self.read_hooks.contains(&requested_address) {
+            self.hooked_accesses.push(MemoryAccess {
+                address: requested_address,
+                value,
+                write: false,
+            });
+        }
+
+        value
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        // OAM DMA conflict
+        if self.transfer_active
+            && self.dot_counter >= 4
+            && !((0xFF80..=0xFFFE).contains(&address) || address == 0xFF46)
+        {
+            return;
+        }
+
+        self.write_bus(address, value);
+
+        if self.events.enabled {
+            if address == 0xFF46 {
+                self.log_event(EventKind::OamDma {
+                    source: self.source_address,
+                });
+            } else if is_tracked_register(address) {
+                self.log_event(EventKind::RegisterWrite { address, value });
+            }
+        }
+
+        if !self.write_hooks.is_empty() && self.write_hooks.contains(&address) {
+            self.hooked_accesses.push(MemoryAccess {
+                address,
+                value,
+                write: true,
+            });
+        }
+    }
+
+    fn read_bus(&mut self, address: u16) -> u8 {
         match address {
-            0x0000..=0x7FFF => self.mbc.read(address),
+            0x0000..=0x7FFF => {
+                let value = self.mbc.read(address);
+                if self.cheats.is_empty() {
+                    value
+                } else {
+                    self.cheats
+                        .patch_rom(address, value, self.mbc.rom_bank(address))
+                }
+            }
             0x8000..=0x9FFF => self.ppu.read(address),
             0xA000..=0xBFFF => self.mbc.read(address),
             0xC000..=0xCFFF => self.internal_ram[(address - 0xC000) as usize],
@@ -152,15 +237,7 @@ impl MMU {
         }
     }
 
-    pub fn write(&mut self, address: u16, value: u8) {
-        // OAM DMA conflict
-        if self.transfer_active
-            && self.dot_counter >= 4
-            && !((0xFF80..=0xFFFE).contains(&address) || address == 0xFF46)
-        {
-            return;
-        }
-
+    fn write_bus(&mut self, address: u16, value: u8) {
         match address {
             0x0000..=0x7FFF => self.mbc.write(address, value),
             0x8000..=0x9FFF => self.ppu.write(address, value),
@@ -205,11 +282,82 @@ impl MMU {
         }
     }
 
+    // Debugger access, bypasses DMA conflicts, PPU access restrictions, MBC RAM enables and
+    // script hooks
+    pub(crate) fn peek(&mut self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x00FF if self.io_registers.FF50_boot_rom_enabled => {
+                self.boot_rom[address as usize]
+            }
+            0x0000..=0x7FFF => {
+                let index = (self.mbc.rom_bank(address) << 14) | (address as usize & 0x3FFF);
+                *self.mbc.rom().get(index).unwrap_or(&0xFF)
+            }
+            0x8000..=0x97FF => self.ppu.tile_data[(address - 0x8000) as usize],
+            0x9800..=0x9BFF => self.ppu.background_map_1[(address - 0x9800) as usize],
+            0x9C00..=0x9FFF => self.ppu.background_map_2[(address - 0x9C00) as usize],
+            0xA000..=0xBFFF => {
+                let index = (self.mbc.ram_bank() << 13) | (address as usize & 0x1FFF);
+                *self.mbc.ram().get(index).unwrap_or(&0xFF)
+            }
+            0xFE00..=0xFE9F => self.ppu.object_attribute_memory[(address - 0xFE00) as usize],
+            0xFF00 => {
+                let joypad_polled = self.io_registers.joypad_polled;
+                let value = self.io_registers.read(address);
+                self.io_registers.joypad_polled = joypad_polled;
+                value
+            }
+            _ => self.read_bus(address),
+        }
+    }
+
+    pub(crate) fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7FFF => {
+                let index = (self.mbc.rom_bank(address) << 14) | (address as usize & 0x3FFF);
+                if let Some(byte) = self.mbc.rom_mut().get_mut(index) {
+                    *byte = value;
+                }
+            }
+            0x8000..=0x97FF => self.ppu.tile_data[(address - 0x8000) as usize] = value,
+            0x9800..=0x9BFF => self.ppu.background_map_1[(address - 0x9800) as usize] = value,
+            0x9C00..=0x9FFF => self.ppu.background_map_2[(address - 0x9C00) as usize] = value,
+            0xA000..=0xBFFF => {
+                let index = (self.mbc.ram_bank() << 13) | (address as usize & 0x1FFF);
+                if let Some(byte) = self.mbc.ram_mut().get_mut(index) {
+                    *byte = value;
+                }
+            }
+            0xFE00..=0xFE9F => {
+                self.ppu.object_attribute_memory[(address - 0xFE00) as usize] = value
+            }
+            _ => self.write_bus(address, value),
+        }
+    }
+
     pub(crate) fn handle_ppu_interrupts(&mut self) {
         if self.ppu.int_vblank {
             self.ppu.int_vblank = false;
             let value = self.io_registers.read(0xFF0F) | 1;
             self.io_registers.write(0xFF0F, value);
+            self.apply_cheats();
+            // Frames in the event log are split at the start of VBlank
+            if self.events.enabled {
+                self.events.end_frame();
+            }
+        }
+
+        // Without VBlank while the LCD is off, events would pile up in the current frame
+        if self.ppu.blank_frame {
+            self.ppu.blank_frame = false;
+            if self.events.enabled {
+                self.events.end_frame();
+            }
+        }
+
+        if self.ppu.recording_audio_stopped {
+            self.ppu.recording_audio_stopped = false;
+            self.apu.stop_recording();
         }
 
         if self.ppu.int_stat {
@@ -218,4 +366,73 @@ impl MMU {
             self.write(0xFF0F, value);
         }
     }
+
+    pub(crate) fn log_event(&mut self, kind: EventKind) {
+        self.events
+            .push(self.ppu.reg_LY, self.ppu.dot_counter(), kind);
+    }
+
+    // GameShark codes are applied once per frame at the start of VBlank
+    fn apply_cheats(&mut self) {
+        if self.cheats.is_empty() {
+            return;
+        }
+
+        let writes: Vec<(u16, u8)> = self.cheats.ram_writes(self.mbc.ram_bank()).collect();
+        for (address, value) in writes {
+            self.write(address, value);
+        }
+    }
+}
+
+impl Bus for MMU {
+    fn read(&mut self, address: u16) -> u8 {
+        MMU::read(self, address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        MMU::write(self, address, value)
+    }
+
+    fn tick_dots(&mut self, dots: u32) {
+        let mut start = fastant::Instant::now();
+        for _ in 0..dots {
+            self.ppu.tick();
+        }
+        self.time_ppu += start.elapsed();
+
+        start = fastant::Instant::now();
+        for _ in 0..dots {
+            let div_apu = self.io_registers.update_timers();
+            self.tick();
+            self.handle_ppu_interrupts();
+            self.apu.tick(div_apu);
+        }
+        self.time_io += start.elapsed();
+    }
+
+    // The registers are used directly, like peek, so read hooks, cheats and the event log don't
+    // see accesses the CPU never puts on the bus
+    fn pending_interrupts(&mut self) -> u8 {
+        self.io_registers.FFFF_IE_interrupt_enable & self.io_registers.FF0F_IF_interrupt_flag & 0x1F
+    }
+
+    fn interrupt_flags(&mut self) -> u8 {
+        self.io_registers.FF0F_IF_interrupt_flag | 0xE0
+    }
+
+    fn set_interrupt_flags(&mut self, value: u8) {
+        self.io_registers.FF0F_IF_interrupt_flag = value;
+    }
+
+    fn begin_instruction(&mut self, pc: u16) {
+        self.events.pc = pc;
+    }
+
+    fn interrupt_dispatched(&mut self, pc: u16, vector: u16) {
+        if self.events.enabled {
+            self.events.pc = pc;
+            self.log_event(EventKind::Interrupt { vector });
+        }
+    }
 }
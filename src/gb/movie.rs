@@ -0,0 +1,108 @@
+use crate::gb::joypad::Button;
+use std::fs;
+use std::path::Path;
+
+// Button presses per frame. The text format has one `<frame> <buttons>` line per change, where
+// buttons is a comma separated list or `-` for none, e.g. `120 start` and `125 -`. The buttons
+// stay held until the next line, `#` starts a comment.
+#[derive(Clone, Default, Debug)]
+pub struct InputMovie {
+    // Sorted by frame
+    changes: Vec<(u64, Vec<Button>)>,
+}
+
+impl InputMovie {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut changes: Vec<(u64, Vec<Button>)> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (frame, buttons) = line.split_once(char::is_whitespace).unwrap_or((line, "-"));
+            let frame = frame
+                .parse::<u64>()
+                .map_err(|_| format!("Line {}: invalid frame number '{}'", index + 1, frame))?;
+            let buttons = match buttons.trim() {
+                "-" => Vec::new(),
+                buttons => buttons
+                    .split(',')
+                    .map(|name| {
+                        Button::from_name(name.trim()).ok_or(format!(
+                            "Line {}: unknown button '{}'",
+                            index + 1,
+                            name.trim()
+                        ))
+                    })
+                    .collect::<Result<Vec<Button>, String>>()?,
+            };
+
+            if changes.last().is_some_and(|(last, _)| *last >= frame) {
+                return Err(format!("Line {}: frames must be increasing", index + 1));
+            }
+            changes.push((frame, buttons));
+        }
+        Ok(InputMovie { changes })
+    }
+
+    // Buttons held during the given frame
+    pub fn buttons(&self, frame: u64) -> &[Button] {
+        let index = self.changes.partition_point(|(start, _)| *start <= frame);
+        match index {
+            0 => &[],
+            index => &self.changes[index - 1].1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_changes() {
+        let movie = InputMovie::parse(
+            "# Skip the title screen\n\
+             \n\
+             60 start\n\
+             62 -  # release\n\
+             100 A, right\n\
+             110\n\
+             120 select,B\n",
+        )
+        .unwrap();
+
+        assert!(movie.buttons(0).is_empty());
+        assert!(movie.buttons(59).is_empty());
+        assert_eq!(movie.buttons(60), [Button::Start]);
+        assert_eq!(movie.buttons(61), [Button::Start]);
+        assert!(movie.buttons(62).is_empty());
+        assert_eq!(movie.buttons(105), [Button::A, Button::Right]);
+        // A bare frame number releases everything
+        assert!(movie.buttons(110).is_empty());
+        assert_eq!(movie.buttons(1000), [Button::Select, Button::B]);
+    }
+
+    #[test]
+    fn parse_empty() {
+        let movie = InputMovie::parse("# nothing\n\n").unwrap();
+        assert!(movie.buttons(0).is_empty());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |text: &str| InputMovie::parse(text).unwrap_err();
+        assert_eq!(error("start 60"), "Line 1: invalid frame number 'start'");
+        assert_eq!(error("-1 a"), "Line 1: invalid frame number '-1'");
+        assert_eq!(error("10 a\n20 turbo"), "Line 2: unknown button 'turbo'");
+        assert_eq!(error("10 a,"), "Line 1: unknown button ''");
+        assert_eq!(error("10 a\n10 b"), "Line 2: frames must be increasing");
+        assert_eq!(error("10 a\n# b\n5 b"), "Line 3: frames must be increasing");
+    }
+}
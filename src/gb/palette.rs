@@ -0,0 +1,350 @@
+pub use crate::gb::ppu::DmgPalette;
+use serde::{Deserialize, Serialize};
+
+pub type Rgb = [u8; 3];
+
+// Colors for the four shades of each DMG palette register
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ColorPalette {
+    pub bg: [Rgb; 4],
+    pub obj0: [Rgb; 4],
+    pub obj1: [Rgb; 4],
+}
+
+impl ColorPalette {
+    pub const fn uniform(colors: [Rgb; 4]) -> Self {
+        ColorPalette {
+            bg: colors,
+            obj0: colors,
+            obj1: colors,
+        }
+    }
+
+    pub fn color(&self, palette: DmgPalette, shade: u8) -> Rgb {
+        let colors = match palette {
+            DmgPalette::BGP => &self.bg,
+            DmgPalette::OBP0 => &self.obj0,
+            DmgPalette::OBP1 => &self.obj1,
+        };
+        colors[shade as usize & 0b11]
+    }
+
+    // Converts a frame buffer of shades to RGBA
+    pub fn apply(&self, frame_buffer: &[u8], palettes: &[DmgPalette]) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(frame_buffer.len() * 4);
+        for (index, shade) in frame_buffer.iter().enumerate() {
+            let palette = palettes.get(index).copied().unwrap_or(DmgPalette::BGP);
+            rgba.extend(self.color(palette, *shade));
+            rgba.push(0xFF);
+        }
+        rgba
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum PalettePreset {
+    Grayscale,
+    DmgGreen,
+    Pocket,
+    Light,
+    HighContrast,
+    ColorblindSafe,
+}
+
+impl PalettePreset {
+    pub const ALL: [PalettePreset; 6] = [
+        PalettePreset::Grayscale,
+        PalettePreset::DmgGreen,
+        PalettePreset::Pocket,
+        PalettePreset::Light,
+        PalettePreset::HighContrast,
+        PalettePreset::ColorblindSafe,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PalettePreset::Grayscale => "Grayscale",
+            PalettePreset::DmgGreen => "DMG green",
+            PalettePreset::Pocket => "Pocket",
+            PalettePreset::Light => "Light",
+            PalettePreset::HighContrast => "High contrast",
+            PalettePreset::ColorblindSafe => "Colorblind safe",
+        }
+    }
+
+    pub fn palette(&self) -> ColorPalette {
+        let colors = match self {
+            PalettePreset::Grayscale => [
+                [0xFF, 0xFF, 0xFF],
+                [0xAA, 0xAA, 0xAA],
+                [0x55, 0x55, 0x55],
+                [0x00, 0x00, 0x00],
+            ],
+            PalettePreset::DmgGreen => [
+                [0x9B, 0xBC, 0x0F],
+                [0x8B, 0xAC, 0x0F],
+                [0x30, 0x62, 0x30],
+                [0x0F, 0x38, 0x0F],
+            ],
+            PalettePreset::Pocket => [
+                [0xC4, 0xCF, 0xA1],
+                [0x8B, 0x95, 0x6D],
+                [0x4D, 0x53, 0x3C],
+                [0x1F, 0x1F, 0x1F],
+            ],
+            PalettePreset::Light => [
+                [0x00, 0xB5, 0x81],
+                [0x00, 0x9A, 0x71],
+                [0x00, 0x69, 0x4A],
+                [0x00, 0x4F, 0x3B],
+            ],
+            PalettePreset::HighContrast => [
+                [0xFF, 0xFF, 0xFF],
+                [0xC0, 0xC0, 0xC0],
+                [0x40, 0x40, 0x40],
+                [0x00, 0x00, 0x00],
+            ],
+            // Blue to orange ramp that stays distinguishable with common color vision deficiencies
+            PalettePreset::ColorblindSafe => [
+                [0xFF, 0xF4, 0xE0],
+                [0xF4, 0xA5, 0x3A],
+                [0x1F, 0x6F, 0xB4],
+                [0x0A, 0x1A, 0x33],
+            ],
+        };
+        ColorPalette::uniform(colors)
+    }
+}
+
+// Palettes the CGB boot ROM assigns to DMG games, stored as RGB555 and indexed per color
+const CGB_COLORS: [u16; 120] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000, // 0
+    0x639F, 0x4279, 0x15B0, 0x04CB, // 1
+    0x7FFF, 0x6E31, 0x454A, 0x0000, // 2
+    0x7FFF, 0x1BEF, 0x0200, 0x0000, // 3
+    0x7FFF, 0x421F, 0x1CF2, 0x0000, // 4
+    0x7FFF, 0x5294, 0x294A, 0x0000, // 5
+    0x7FFF, 0x03FF, 0x012F, 0x0000, // 6
+    0x7FFF, 0x03EF, 0x01D6, 0x0000, // 7
+    0x7FFF, 0x42B5, 0x3DC8, 0x0000, // 8
+    0x7E74, 0x03FF, 0x0180, 0x0000, // 9
+    0x67FF, 0x77AC, 0x1A13, 0x2D6B, // 10
+    0x7ED6, 0x4BFF, 0x2175, 0x0000, // 11
+    0x53FF, 0x4A5F, 0x7E52, 0x0000, // 12
+    0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0, // 13
+    0x03ED, 0x7FFF, 0x255F, 0x0000, // 14
+    0x036A, 0x021F, 0x03FF, 0x7FFF, // 15
+    0x7FFF, 0x01DF, 0x0112, 0x0000, // 16
+    0x231F, 0x035F, 0x00F2, 0x0009, // 17
+    0x7FFF, 0x03EA, 0x011F, 0x0000, // 18
+    0x299F, 0x001A, 0x000C, 0x0000, // 19
+    0x7FFF, 0x027F, 0x001F, 0x0000, // 20
+    0x7FFF, 0x03E0, 0x0206, 0x0120, // 21
+    0x7FFF, 0x7EEB, 0x001F, 0x7C00, // 22
+    0x7FFF, 0x3FFF, 0x7E00, 0x001F, // 23
+    0x7FFF, 0x03FF, 0x001F, 0x0000, // 24
+    0x03FF, 0x001F, 0x000C, 0x0000, // 25
+    0x7FFF, 0x033F, 0x0193, 0x0000, // 26
+    0x0000, 0x4200, 0x037F, 0x7FFF, // 27
+    0x7FFF, 0x7E8C, 0x7C00, 0x0000, // 28
+    0x7FFF, 0x1BEF, 0x6180, 0x0000, // 29
+];
+
+// OBJ0, OBJ1 and BG offsets into CGB_COLORS, a few combinations start mid palette
+const CGB_COMBINATIONS: [(usize, usize, usize); 51] = [
+    (16, 16, 116),
+    (72, 72, 72),
+    (80, 80, 80),
+    (96, 96, 96),
+    (36, 36, 36),
+    (0, 0, 0),
+    (108, 108, 108),
+    (20, 20, 20),
+    (48, 48, 48),
+    (104, 104, 104),
+    (64, 32, 32), // 10
+    (16, 112, 112),
+    (16, 8, 8),
+    (12, 16, 16),
+    (16, 116, 116),
+    (112, 16, 112),
+    (8, 68, 8),
+    (64, 64, 32),
+    (16, 16, 28),
+    (16, 16, 72),
+    (16, 16, 80), // 20
+    (76, 76, 36),
+    (15, 15, 44),
+    (68, 68, 8),
+    (16, 16, 8),
+    (16, 16, 12),
+    (112, 112, 0),
+    (12, 12, 0),
+    (0, 0, 4),
+    (72, 88, 72),
+    (80, 88, 80), // 30
+    (96, 88, 96),
+    (64, 88, 32),
+    (68, 16, 52),
+    (111, 0, 56),
+    (111, 16, 60),
+    (76, 88, 36),
+    (64, 112, 40),
+    (16, 92, 112),
+    (68, 88, 8),
+    (16, 0, 8), // 40
+    (16, 112, 12),
+    (112, 12, 0),
+    (12, 112, 16),
+    (84, 112, 16),
+    (12, 112, 0),
+    (100, 12, 112),
+    (0, 112, 32),
+    (16, 12, 112),
+    (112, 12, 24),
+    (16, 112, 116), // 50
+];
+
+// Title checksums of licensed games, the last entries share a checksum and are told apart by
+// the fourth letter of the title
+const TITLE_CHECKSUMS: [u8; 94] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B, 0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+    0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+];
+const FIRST_DUPLICATE_CHECKSUM: usize = 65;
+const DUPLICATE_ROW_LENGTH: usize = 14;
+const DUPLICATE_FOURTH_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+// Palette combination for each entry of TITLE_CHECKSUMS
+const CHECKSUM_COMBINATIONS: [u8; 94] = [
+    0, 4, 5, 35, 34, 3, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44, 21, 32, 31, 20, 5, 33, 13, 14, 5, 29,
+    5, 18, 9, 3, 2, 26, 25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34, 5, 42, 6,
+    5, 33, 25, 42, 42, 40, 2, 16, 25, 42, 42, 5, 0, 39, 36, 22, 25, 6, 32, 12, 36, 11, 39, 18, 39,
+    24, 31, 50, 17, 46, 6, 27, 0, 47, 41, 41, 0, 0, 19, 34, 23, 18, 29,
+];
+
+// Reproduces the palette the CGB boot ROM picks for a DMG cartridge
+pub fn colorize(rom: &[u8]) -> ColorPalette {
+    cgb_palette(cgb_combination(rom))
+}
+
+fn cgb_combination(rom: &[u8]) -> usize {
+    if rom.len() < 0x150 {
+        return 0;
+    }
+
+    // Only games licensed by Nintendo get a specific palette
+    let nintendo = match rom[0x014B] {
+        0x01 => true,
+        0x33 => &rom[0x0144..0x0146] == b"01",
+        _ => false,
+    };
+    if !nintendo {
+        return 0;
+    }
+
+    let checksum = rom[0x0134..=0x0143]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    let Some(mut index) = TITLE_CHECKSUMS
+        .iter()
+        .position(|title_checksum| *title_checksum == checksum)
+    else {
+        return 0;
+    };
+
+    if index >= FIRST_DUPLICATE_CHECKSUM {
+        let fourth_letter = rom[0x0137];
+        loop {
+            if DUPLICATE_FOURTH_LETTERS[index - FIRST_DUPLICATE_CHECKSUM] == fourth_letter {
+                break;
+            }
+            index += DUPLICATE_ROW_LENGTH;
+            if index >= TITLE_CHECKSUMS.len() {
+                return 0;
+            }
+        }
+    }
+
+    CHECKSUM_COMBINATIONS[index] as usize
+}
+
+fn cgb_palette(combination: usize) -> ColorPalette {
+    let (obj0, obj1, bg) = CGB_COMBINATIONS[combination];
+    let colors = |offset: usize| std::array::from_fn(|i| rgb555(CGB_COLORS[offset + i]));
+    ColorPalette {
+        bg: colors(bg),
+        obj0: colors(obj0),
+        obj1: colors(obj1),
+    }
+}
+
+fn rgb555(color: u16) -> Rgb {
+    let expand = |value: u16| {
+        let value = (value & 0x1F) as u8;
+        (value << 3) | (value >> 2)
+    };
+    [expand(color), expand(color >> 5), expand(color >> 10)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(title: &[u8], licensee: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+        rom[0x014B] = licensee;
+        rom
+    }
+
+    #[test]
+    fn unique_checksums() {
+        let checksums = &TITLE_CHECKSUMS[..FIRST_DUPLICATE_CHECKSUM];
+        for (index, checksum) in checksums.iter().enumerate() {
+            assert!(
+                !checksums[..index].contains(checksum),
+                "Checksum {:#04X} at {} is not unique",
+                checksum,
+                index
+            );
+        }
+    }
+
+    #[test]
+    fn unique_title() {
+        assert_eq!(cgb_combination(&rom(b"TETRIS", 0x01)), 3);
+        assert_eq!(cgb_combination(&rom(b"DONKEYKONGLAND 3", 0x01)), 39);
+        // New licensee code 01 is Nintendo as well
+        let mut new_licensee = rom(b"TETRIS", 0x33);
+        new_licensee[0x0144..0x0146].copy_from_slice(b"01");
+        assert_eq!(cgb_combination(&new_licensee), 3);
+    }
+
+    #[test]
+    fn fourth_letter_duplicate() {
+        // Checksum 0xB3 is shared by three titles
+        assert_eq!(cgb_combination(&rom(&[0, 0, 0, b'B', 0x71], 0x01)), 36);
+        assert_eq!(cgb_combination(&rom(&[0, 0, 0, b'U', 0x5E], 0x01)), 17);
+        assert_eq!(cgb_combination(&rom(&[0, 0, 0, b'R', 0x61], 0x01)), 29);
+        assert_eq!(cgb_combination(&rom(&[0, 0, 0, b'Z', 0x59], 0x01)), 0);
+    }
+
+    #[test]
+    fn not_licensed_by_nintendo() {
+        assert_eq!(cgb_combination(&rom(b"TETRIS", 0x02)), 0);
+        let mut new_licensee = rom(b"TETRIS", 0x33);
+        new_licensee[0x0144..0x0146].copy_from_slice(b"08");
+        assert_eq!(cgb_combination(&new_licensee), 0);
+    }
+
+    #[test]
+    fn short_rom() {
+        assert_eq!(cgb_combination(&rom(b"TETRIS", 0x01)[..0x014F]), 0);
+        assert_eq!(cgb_combination(&[]), 0);
+    }
+}
@@ -1,13 +1,17 @@
-mod registers;
+pub(crate) mod registers;
 
+use crate::gb::video::VideoRecorder;
 use arbitrary_int::{u2, u3};
 use bitbybit::bitfield;
-use egui::ahash::HashSetExt;
 use intbits::Bits;
+use log::{log, Level};
 use registers::*;
 use std::cmp::PartialEq;
 use std::collections::VecDeque;
 
+// Dots in a frame, including VBlank
+const DOTS_PER_FRAME: u32 = 70224;
+
 #[derive(PartialEq)]
 pub(crate) enum PPUMode {
     HorizontalBlank = 0,
@@ -58,7 +62,7 @@ struct Sprite {
 
 #[bitfield(u8)]
 #[derive(Eq, Hash, PartialEq)]
-struct OAMAttributes {
+pub(crate) struct OAMAttributes {
     #[bit(7, rw)]
     priority: bool,
 
@@ -95,6 +99,86 @@ struct PixelInfo {
     palette: u8,
     sprite_priority: bool,
     background_priority: bool,
+    layer: Layer,
+    tile_index: u8,
+    tile_address: u16,
+}
+
+impl PixelInfo {
+    fn dmg_palette(&self) -> DmgPalette {
+        match self.layer {
+            Layer::Sprite { .. } if self.palette == 0 => DmgPalette::OBP0,
+            Layer::Sprite { .. } => DmgPalette::OBP1,
+            _ => DmgPalette::BGP,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Layer {
+    // Neither the background nor a sprite was drawn, e.g. with LCDC bit 0 cleared
+    Blank,
+    Background,
+    Window,
+    Sprite { oam_index: u8 },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DmgPalette {
+    BGP,
+    OBP0,
+    OBP1,
+}
+
+// Debug toggles applied when mixing the final pixel, the emulated state is not affected
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct LayerVisibility {
+    pub(crate) background: bool,
+    pub(crate) window: bool,
+    pub(crate) sprites: bool,
+    pub(crate) hidden_sprites: [bool; 40],
+}
+
+impl Default for LayerVisibility {
+    fn default() -> Self {
+        LayerVisibility {
+            background: true,
+            window: true,
+            sprites: true,
+            hidden_sprites: [false; 40],
+        }
+    }
+}
+
+impl LayerVisibility {
+    fn is_visible(&self, layer: Layer) -> bool {
+        match layer {
+            Layer::Blank => true,
+            Layer::Background => self.background,
+            Layer::Window => self.window,
+            Layer::Sprite { oam_index } => self.sprites && !self.hidden_sprites[oam_index as usize],
+        }
+    }
+}
+
+// Describes where a single pixel of the frame buffer came from
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PixelSource {
+    pub(crate) layer: Layer,
+    pub(crate) tile_index: u8,
+    pub(crate) tile_address: u16,
+    pub(crate) palette: DmgPalette,
+    pub(crate) color_id: u8,
+}
+
+impl PixelSource {
+    const BLANK: PixelSource = PixelSource {
+        layer: Layer::Blank,
+        tile_index: 0,
+        tile_address: 0,
+        palette: DmgPalette::BGP,
+        color_id: 0,
+    };
 }
 
 pub(crate) struct PPU {
@@ -108,6 +192,16 @@ pub(crate) struct PPU {
     current_fetcher: ActiveFetcher,
     frame_buffer: [u8; 160 * 144],
     pub(crate) frame_buffer_vblanked: Vec<u8>,
+    // Palette register each pixel was drawn with, used for colorization
+    palette_buffer: [DmgPalette; 160 * 144],
+    pub(crate) palette_buffer_vblanked: Vec<DmgPalette>,
+    pub(crate) frame_count: u64,
+    recorder: Option<VideoRecorder>,
+    // Per-pixel provenance, only recorded while a debugger is inspecting the screen
+    pub(crate) record_provenance: bool,
+    provenance: Vec<PixelSource>,
+    pub(crate) provenance_vblanked: Vec<PixelSource>,
+    pub(crate) layer_visibility: LayerVisibility,
     window_y: u8,
     // Memory
     pub(crate) tile_data: [u8; 6144],
@@ -134,6 +228,12 @@ pub(crate) struct PPU {
     new_line: bool,
     stat_delay: u8,
     first_frame: bool,
+    // Dots since the LCD was turned off or the last blank frame passed
+    lcd_off_dots: u32,
+    // Set every frame's worth of dots while the LCD is off, in place of VBlank
+    pub(crate) blank_frame: bool,
+    // Set when the encoder failed on a recording with audio, the WAV file has to be closed too
+    pub(crate) recording_audio_stopped: bool,
 }
 
 impl PPU {
@@ -149,6 +249,14 @@ impl PPU {
             current_fetcher: ActiveFetcher::Background,
             frame_buffer: [0; 160 * 144],
             frame_buffer_vblanked: vec![0; 160 * 144],
+            palette_buffer: [DmgPalette::BGP; 160 * 144],
+            palette_buffer_vblanked: vec![DmgPalette::BGP; 160 * 144],
+            frame_count: 0,
+            recorder: None,
+            record_provenance: false,
+            provenance: Vec::new(),
+            provenance_vblanked: Vec::new(),
+            layer_visibility: LayerVisibility::default(),
             window_y: 0,
             // Memory
             tile_data: [0; 6144],
@@ -175,12 +283,25 @@ impl PPU {
             new_line: false,
             stat_delay: 0,
             first_frame: false,
+            lcd_off_dots: 0,
+            blank_frame: false,
+            recording_audio_stopped: false,
         }
     }
 
     pub(crate) fn tick(&mut self) {
         self.test_counter += 1;
         if !self.reg_LCDC.lcd_ppu_enable() {
+            // No frames are produced, keep a recording in step with its audio and let the event
+            // log split frames
+            self.lcd_off_dots += 1;
+            if self.lcd_off_dots == DOTS_PER_FRAME {
+                self.lcd_off_dots = 0;
+                self.blank_frame = true;
+                if self.recorder.is_some() {
+                    self.record_blank_frame();
+                }
+            }
             return;
         }
 
@@ -201,6 +322,12 @@ impl PPU {
                         self.update_reg_STAT();
                         self.int_vblank = true;
                         self.frame_buffer_vblanked = self.frame_buffer.to_vec();
+                        self.palette_buffer_vblanked = self.palette_buffer.to_vec();
+                        if self.record_provenance {
+                            self.provenance_vblanked = self.provenance.clone();
+                        }
+                        self.frame_count += 1;
+                        self.record_frame();
                     } else {
                         self.ppu_mode = PPUMode::OAMScan;
                         self.stat_delay = 3;
@@ -250,15 +377,17 @@ impl PPU {
                 if self.dot_counter % 2 == 0 {
                     // Check if sprite should be added to buffer
                     let sprite_idx = self.dot_counter / 2;
-                    let mut sprite =
-                        Sprite::new(&self.object_attribute_memory, (sprite_idx * 4) as usize, 0);
+                    let sprite = Sprite::new(
+                        &self.object_attribute_memory,
+                        (sprite_idx * 4) as usize,
+                        sprite_idx as u8,
+                    );
 
                     if self.reg_LY + 16 >= sprite.y
                         && self.reg_LY + 16
                             < sprite.y + if self.reg_LCDC.obj_size() { 16 } else { 8 }
                         && self.oam_buffer.len() < 10
                     {
-                        sprite.oam_index = self.oam_buffer.len() as u8;
                         self.oam_buffer.push(sprite);
                     }
                 }
@@ -430,27 +559,45 @@ impl PPU {
                             palette: 0,
                             sprite_priority: false,
                             background_priority: true,
+                            layer: Layer::Blank,
+                            tile_index: 0,
+                            tile_address: 0,
                         });
 
                         if (8..160 + 8).contains(&screen_x) {
-                            let color = if sprite_pixel.color != 0
+                            // Hidden layers are mixed as if they were transparent
+                            let sprite_visible = sprite_pixel.color != 0
+                                && self.layer_visibility.is_visible(sprite_pixel.layer);
+                            let bg_visible = self.layer_visibility.is_visible(bg_pixel.layer);
+                            let (color, source) = if sprite_visible
                                 && !(bg_pixel.color != 0
+                                    && bg_visible
                                     && self.reg_LCDC.bg_window_enable_priority()
                                     && sprite_pixel.background_priority)
                                 && !self.first_frame
                             {
                                 // Output sprite pixel
-                                self.get_sprite_color(sprite_pixel.palette, sprite_pixel.color)
-                            } else if self.reg_LCDC.bg_window_enable_priority() && !self.first_frame
+                                (
+                                    self.get_sprite_color(sprite_pixel.palette, sprite_pixel.color),
+                                    Some(&sprite_pixel),
+                                )
+                            } else if self.reg_LCDC.bg_window_enable_priority()
+                                && !self.first_frame
+                                && bg_visible
                             {
                                 // Output background / window pixel
-                                self.get_color(bg_pixel.color)
+                                (self.get_color(bg_pixel.color), Some(&bg_pixel))
                             } else {
-                                0
+                                (0, None)
                             };
 
-                            self.frame_buffer[self.reg_LY as usize * 160 + screen_x as usize - 8] =
-                                color;
+                            let index = self.reg_LY as usize * 160 + screen_x as usize - 8;
+                            self.frame_buffer[index] = color;
+                            self.palette_buffer[index] =
+                                source.map_or(DmgPalette::BGP, PixelInfo::dmg_palette);
+                            if self.record_provenance {
+                                self.record_pixel(index, source);
+                            }
                         }
                         screen_x = screen_x.wrapping_add(1);
 
@@ -511,6 +658,9 @@ impl PPU {
                 palette: 0,
                 sprite_priority: false,
                 background_priority: true,
+                layer: Layer::Blank,
+                tile_index: 0,
+                tile_address: 0,
             })
         }
 
@@ -528,6 +678,11 @@ impl PPU {
                     palette: u8::from(sprite.attributes.dmg_palette()),
                     sprite_priority: false,
                     background_priority: sprite.attributes.priority(),
+                    layer: Layer::Sprite {
+                        oam_index: sprite.oam_index,
+                    },
+                    tile_index: tile_number,
+                    tile_address: tile_address & !0xF,
                 };
             }
         }
@@ -573,6 +728,9 @@ impl PPU {
                 palette: 0,
                 sprite_priority: false,
                 background_priority: false,
+                layer: Layer::Background,
+                tile_index: tile_id,
+                tile_address: tile_address & !0xF,
             })
         }
     }
@@ -620,10 +778,92 @@ impl PPU {
                 palette: 0,
                 sprite_priority: false,
                 background_priority: false,
+                layer: Layer::Window,
+                tile_index: tile_id,
+                tile_address: tile_address & !0xF,
             })
         }
     }
 
+    pub(crate) fn dot_counter(&self) -> u16 {
+        self.dot_counter
+    }
+
+    pub(crate) fn start_recording(&mut self, recorder: VideoRecorder) {
+        self.stop_recording();
+        self.recorder = Some(recorder);
+    }
+
+    // Returns whether the recording included audio
+    pub(crate) fn stop_recording(&mut self) -> bool {
+        match self.recorder.take() {
+            Some(recorder) => {
+                let with_audio = recorder.with_audio;
+                if let Err(err) = recorder.finish() {
+                    log!(Level::Error, "Failed to finish video recording: {}", err);
+                }
+                with_audio
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    fn record_frame(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            if recorder
+                .write_frame(&self.frame_buffer_vblanked, &self.palette_buffer_vblanked)
+                .is_err()
+            {
+                self.stop_recording_after_error();
+            }
+        }
+    }
+
+    // The LCD shows white while it is off
+    fn record_blank_frame(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            if recorder
+                .write_frame(&[0; 160 * 144], &[DmgPalette::BGP; 160 * 144])
+                .is_err()
+            {
+                self.stop_recording_after_error();
+            }
+        }
+    }
+
+    // Finishing the recording reports why the encoder stopped
+    fn stop_recording_after_error(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            self.recording_audio_stopped = recorder.with_audio;
+            if let Err(err) = recorder.finish() {
+                log!(Level::Error, "Failed to write video recording: {}", err);
+            }
+        }
+    }
+
+    fn record_pixel(&mut self, index: usize, pixel: Option<&PixelInfo>) {
+        if self.provenance.len() != 160 * 144 {
+            self.provenance = vec![PixelSource::BLANK; 160 * 144];
+        }
+
+        let Some(pixel) = pixel else {
+            self.provenance[index] = PixelSource::BLANK;
+            return;
+        };
+
+        self.provenance[index] = PixelSource {
+            layer: pixel.layer,
+            tile_index: pixel.tile_index,
+            tile_address: pixel.tile_address,
+            palette: pixel.dmg_palette(),
+            color_id: pixel.color,
+        };
+    }
+
     fn get_color(&mut self, color_id: u8) -> u8 {
         match color_id {
             0 => self.reg_BGP & 0b11,
@@ -861,6 +1101,7 @@ impl PPU {
             0xFF40 => {
                 self.reg_LCDC = LCDC::new_with_raw_value(value);
                 if !self.reg_LCDC.lcd_ppu_enable() {
+                    self.lcd_off_dots = 0;
                     self.reg_LY = 0;
                     self.dot_counter = 4;
                     self.ppu_mode = PPUMode::OAMScan;
@@ -0,0 +1,379 @@
+// Snapshots are laid out as WRAM, followed by HRAM, followed by all cartridge RAM banks
+const WRAM_SIZE: usize = 0x2000;
+const HRAM_SIZE: usize = 0x7F;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ValueSize {
+    Byte,
+    Word,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SearchFormat {
+    pub size: ValueSize,
+    pub endianness: Endianness,
+    pub bcd: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    LessThan,
+    GreaterThan,
+    IncreasedBy(u32),
+    DecreasedBy(u32),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SearchTarget {
+    Previous,
+    Value(u32),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MemoryLocation {
+    pub bank: Option<usize>,
+    pub address: u16,
+}
+
+#[derive(Clone)]
+pub struct RamSearchResult {
+    pub index: usize,
+    pub location: MemoryLocation,
+    pub value: u32,
+    pub previous: u32,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Watch {
+    pub index: usize,
+    pub format: SearchFormat,
+}
+
+pub struct RamSearch {
+    previous: Vec<u8>,
+    candidates: Vec<usize>,
+}
+
+impl Default for SearchFormat {
+    fn default() -> Self {
+        SearchFormat {
+            size: ValueSize::Byte,
+            endianness: Endianness::Little,
+            bcd: false,
+        }
+    }
+}
+
+impl SearchFormat {
+    pub fn width(&self) -> usize {
+        match self.size {
+            ValueSize::Byte => 1,
+            ValueSize::Word => 2,
+        }
+    }
+
+    // Returns None for values that do not fit the format, like invalid BCD digits or words
+    // crossing a memory region
+    pub fn read(&self, snapshot: &[u8], index: usize) -> Option<u32> {
+        if index + self.width() > snapshot.len()
+            || region(index) != region(index + self.width() - 1)
+        {
+            return None;
+        }
+
+        let bytes = &snapshot[index..index + self.width()];
+        let mut value = 0u32;
+        for offset in 0..self.width() {
+            let byte = match self.endianness {
+                Endianness::Little => bytes[self.width() - 1 - offset],
+                Endianness::Big => bytes[offset],
+            };
+            value = if self.bcd {
+                if byte >> 4 > 9 || byte & 0x0F > 9 {
+                    return None;
+                }
+                value * 100 + (byte >> 4) as u32 * 10 + (byte & 0x0F) as u32
+            } else {
+                (value << 8) | byte as u32
+            };
+        }
+        Some(value)
+    }
+
+    // Inverse of read, returns the bytes in memory order
+    pub fn encode(&self, value: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut remaining = value;
+        for _ in 0..self.width() {
+            if self.bcd {
+                let digits = remaining % 100;
+                bytes.push((((digits / 10) << 4) | (digits % 10)) as u8);
+                remaining /= 100;
+            } else {
+                bytes.push(remaining as u8);
+                remaining >>= 8;
+            }
+        }
+        if self.endianness == Endianness::Big {
+            bytes.reverse();
+        }
+        bytes
+    }
+}
+
+fn region(index: usize) -> usize {
+    if index < WRAM_SIZE {
+        0
+    } else if index < WRAM_SIZE + HRAM_SIZE {
+        1
+    } else {
+        2 + (index - WRAM_SIZE - HRAM_SIZE) / 0x2000
+    }
+}
+
+pub fn location(index: usize) -> MemoryLocation {
+    if index < WRAM_SIZE {
+        MemoryLocation {
+            bank: None,
+            address: 0xC000 + index as u16,
+        }
+    } else if index < WRAM_SIZE + HRAM_SIZE {
+        MemoryLocation {
+            bank: None,
+            address: 0xFF80 + (index - WRAM_SIZE) as u16,
+        }
+    } else {
+        let offset = index - WRAM_SIZE - HRAM_SIZE;
+        MemoryLocation {
+            bank: Some(offset / 0x2000),
+            address: 0xA000 + (offset % 0x2000) as u16,
+        }
+    }
+}
+
+impl Comparison {
+    fn matches(&self, value: u32, previous: u32, target: SearchTarget) -> bool {
+        let target = match target {
+            SearchTarget::Previous => previous,
+            SearchTarget::Value(target) => target,
+        };
+
+        match self {
+            Comparison::Equal => value == target,
+            Comparison::NotEqual => value != target,
+            Comparison::LessThan => value < target,
+            Comparison::GreaterThan => value > target,
+            Comparison::IncreasedBy(amount) => value == previous.wrapping_add(*amount),
+            Comparison::DecreasedBy(amount) => value == previous.wrapping_sub(*amount),
+        }
+    }
+}
+
+impl Default for RamSearch {
+    fn default() -> Self {
+        RamSearch::new()
+    }
+}
+
+impl RamSearch {
+    pub fn new() -> Self {
+        RamSearch {
+            previous: Vec::new(),
+            candidates: Vec::new(),
+        }
+    }
+
+    pub fn reset(&mut self, snapshot: Vec<u8>) {
+        self.candidates = (0..snapshot.len()).collect();
+        self.previous = snapshot;
+    }
+
+    pub fn filter(
+        &mut self,
+        snapshot: Vec<u8>,
+        format: SearchFormat,
+        comparison: Comparison,
+        target: SearchTarget,
+    ) {
+        // Cartridge RAM can change size when a new ROM is loaded
+        if snapshot.len() != self.previous.len() {
+            self.reset(snapshot);
+            return;
+        }
+
+        let previous = &self.previous;
+        self.candidates.retain(|&index| {
+            match (format.read(&snapshot, index), format.read(previous, index)) {
+                (Some(value), Some(previous)) => comparison.matches(value, previous, target),
+                _ => false,
+            }
+        });
+        self.previous = snapshot;
+    }
+
+    pub fn candidate_count(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn results(
+        &self,
+        snapshot: &[u8],
+        format: SearchFormat,
+        limit: usize,
+    ) -> Vec<RamSearchResult> {
+        self.candidates
+            .iter()
+            .filter_map(|&index| {
+                Some(RamSearchResult {
+                    index,
+                    location: location(index),
+                    value: format.read(snapshot, index)?,
+                    previous: format.read(&self.previous, index)?,
+                })
+            })
+            .take(limit)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // WRAM and HRAM followed by one cartridge RAM bank
+    const SNAPSHOT_SIZE: usize = WRAM_SIZE + HRAM_SIZE + 0x2000;
+
+    fn format(size: ValueSize, endianness: Endianness, bcd: bool) -> SearchFormat {
+        SearchFormat {
+            size,
+            endianness,
+            bcd,
+        }
+    }
+
+    #[test]
+    fn read_and_encode() {
+        let snapshot = [0x34, 0x12, 0x99, 0x9A];
+        let cases = [
+            (format(ValueSize::Byte, Endianness::Little, false), 0, 0x34),
+            (
+                format(ValueSize::Word, Endianness::Little, false),
+                0,
+                0x1234,
+            ),
+            (format(ValueSize::Word, Endianness::Big, false), 0, 0x3412),
+            (format(ValueSize::Byte, Endianness::Little, true), 0, 34),
+            (format(ValueSize::Word, Endianness::Little, true), 0, 1234),
+            (format(ValueSize::Word, Endianness::Big, true), 0, 3412),
+            (format(ValueSize::Word, Endianness::Little, true), 1, 9912),
+        ];
+        for (format, index, value) in cases {
+            assert_eq!(format.read(&snapshot, index), Some(value), "{:?}", format);
+            assert_eq!(
+                format.encode(value),
+                snapshot[index..index + format.width()],
+                "{:?}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn read_invalid() {
+        let snapshot = [0x9A, 0xA0, 0x12];
+        let bcd = format(ValueSize::Byte, Endianness::Little, true);
+        assert_eq!(bcd.read(&snapshot, 0), None);
+        assert_eq!(bcd.read(&snapshot, 1), None);
+        // Past the end of the snapshot
+        let word = format(ValueSize::Word, Endianness::Little, false);
+        assert_eq!(word.read(&snapshot, 2), None);
+    }
+
+    #[test]
+    fn read_region_boundaries() {
+        let snapshot = vec![0x11; SNAPSHOT_SIZE];
+        let word = format(ValueSize::Word, Endianness::Little, false);
+        assert_eq!(word.read(&snapshot, WRAM_SIZE - 2), Some(0x1111));
+        // WRAM to HRAM and HRAM to cartridge RAM
+        assert_eq!(word.read(&snapshot, WRAM_SIZE - 1), None);
+        assert_eq!(word.read(&snapshot, WRAM_SIZE + HRAM_SIZE - 1), None);
+        assert_eq!(word.read(&snapshot, WRAM_SIZE + HRAM_SIZE), Some(0x1111));
+
+        assert_eq!(
+            location(WRAM_SIZE - 1),
+            MemoryLocation {
+                bank: None,
+                address: 0xDFFF,
+            }
+        );
+        assert_eq!(
+            location(WRAM_SIZE),
+            MemoryLocation {
+                bank: None,
+                address: 0xFF80,
+            }
+        );
+        assert_eq!(
+            location(WRAM_SIZE + HRAM_SIZE + 0x2001),
+            MemoryLocation {
+                bank: Some(1),
+                address: 0xA001,
+            }
+        );
+    }
+
+    #[test]
+    fn narrow_between_snapshots() {
+        let byte = SearchFormat::default();
+        let mut snapshot = vec![0; SNAPSHOT_SIZE];
+        snapshot[0x10] = 5;
+        snapshot[0x20] = 5;
+        snapshot[0x30] = 7;
+
+        let mut search = RamSearch::default();
+        search.reset(snapshot.clone());
+        assert_eq!(search.candidate_count(), SNAPSHOT_SIZE);
+
+        search.filter(
+            snapshot.clone(),
+            byte,
+            Comparison::Equal,
+            SearchTarget::Value(5),
+        );
+        assert_eq!(search.candidate_count(), 2);
+
+        snapshot[0x10] = 6;
+        search.filter(
+            snapshot.clone(),
+            byte,
+            Comparison::IncreasedBy(1),
+            SearchTarget::Previous,
+        );
+        let results = search.results(&snapshot, byte, 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].index, 0x10);
+        assert_eq!(results[0].location.address, 0xC010);
+        assert_eq!(results[0].value, 6);
+        assert_eq!(results[0].previous, 6);
+
+        search.filter(
+            snapshot.clone(),
+            byte,
+            Comparison::NotEqual,
+            SearchTarget::Previous,
+        );
+        assert_eq!(search.candidate_count(), 0);
+
+        // A snapshot of a different size starts over
+        search.filter(vec![0; 16], byte, Comparison::Equal, SearchTarget::Value(1));
+        assert_eq!(search.candidate_count(), 16);
+    }
+}
@@ -1,7 +1,11 @@
+use crate::config::THREAD_LOCAL_CONFIG;
 use crate::egui_renderer::CallbackContext;
 use crate::emulator::EmulatorState;
+use crate::gb::filters::{FilterChain, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::gb::software_renderer::SoftwareRenderer;
 use crate::vulkan_renderer::EmulatorRenderer;
-use egui::PaintCallbackInfo;
+use egui::{Context, PaintCallbackInfo, TextureId};
+use log::{log, Level};
 use std::sync::Arc;
 use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
 use vulkano::command_buffer::{
@@ -13,7 +17,7 @@ use vulkano::format::Format;
 use vulkano::image::sampler::{Sampler, SamplerCreateInfo};
 use vulkano::image::view::ImageView;
 use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
-use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
 use vulkano::pipeline::graphics::color_blend::{
     AttachmentBlend, ColorBlendAttachmentState, ColorBlendState,
 };
@@ -42,38 +46,76 @@ pub(crate) struct GameboyRenderer {
     texture_sampler: Arc<Sampler>,
     upload_buffer: Subbuffer<[u8]>,
     image: Arc<Image>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    // Size of the filtered frame, the texture is recreated when it changes
+    extent: [usize; 2],
+    filters: FilterChain,
+    // Takes over when the texture can't be recreated for a new frame size
+    fallback: Option<SoftwareRenderer>,
 }
 
 impl GameboyRenderer {
-    pub(crate) fn new(vulkano_context: &VulkanoContext, _: &VulkanoWindows) -> Self {
-        let image = Image::new(
-            vulkano_context.memory_allocator().clone(),
-            ImageCreateInfo {
-                image_type: ImageType::Dim2d,
-                format: Format::R8G8B8A8_SRGB,
-                extent: [160, 144, 1], // Size of Game Boy screen
-                usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
-                ..Default::default()
-            },
-            AllocationCreateInfo::default(),
-        )
-        .unwrap();
-
-        let texture = ImageView::new_default(image.clone()).unwrap();
+    // Fails when the device can't provide the resources, the caller falls back to SoftwareRenderer
+    pub(crate) fn new(
+        vulkano_context: &VulkanoContext,
+        _: &VulkanoWindows,
+    ) -> Result<Self, String> {
+        let memory_allocator = vulkano_context.memory_allocator().clone();
+        let (image, texture, upload_buffer) =
+            Self::create_texture(&memory_allocator, SCREEN_WIDTH, SCREEN_HEIGHT)?;
 
         let texture_sampler = Sampler::new(
             vulkano_context.device().clone(),
             SamplerCreateInfo::default(),
         )
-        .unwrap();
+        .map_err(|err| err.to_string())?;
+
+        // Make sure the shaders can be loaded before committing to this renderer
+        vs::load(vulkano_context.device().clone()).map_err(|err| err.to_string())?;
+        fs::load(vulkano_context.device().clone()).map_err(|err| err.to_string())?;
 
         let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
             vulkano_context.device().clone(),
             Default::default(),
         ));
 
+        Ok(GameboyRenderer {
+            pipeline: None,
+            descriptor_set: None,
+            descriptor_set_allocator,
+            texture,
+            texture_sampler,
+            upload_buffer,
+            image,
+            memory_allocator,
+            extent: [SCREEN_WIDTH, SCREEN_HEIGHT],
+            filters: FilterChain::new(),
+            fallback: None,
+        })
+    }
+
+    fn create_texture(
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        width: usize,
+        height: usize,
+    ) -> Result<(Arc<Image>, Arc<ImageView>, Subbuffer<[u8]>), String> {
+        let image = Image::new(
+            memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: Format::R8G8B8A8_SRGB,
+                extent: [width as u32, height as u32, 1],
+                usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .map_err(|err| err.to_string())?;
+
+        let texture = ImageView::new_default(image.clone()).map_err(|err| err.to_string())?;
+
         let upload_buffer: Subbuffer<[u8]> = Buffer::new_slice(
-            vulkano_context.memory_allocator().clone(),
+            memory_allocator.clone(),
             BufferCreateInfo {
                 usage: BufferUsage::TRANSFER_SRC,
                 ..Default::default()
@@ -83,36 +125,39 @@ impl GameboyRenderer {
                     | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
                 ..Default::default()
             },
-            (160 * 144 * 4) as DeviceSize, // Size of Game Boy screen
+            (width * height * 4) as DeviceSize,
         )
-        .unwrap();
+        .map_err(|err| err.to_string())?;
 
-        GameboyRenderer {
-            pipeline: None,
-            descriptor_set: None,
-            descriptor_set_allocator,
-            texture,
-            texture_sampler,
-            upload_buffer,
-            image,
+        Ok((image, texture, upload_buffer))
+    }
+
+    fn resize_texture(&mut self, width: usize, height: usize) -> Result<(), String> {
+        let (image, texture, upload_buffer) =
+            Self::create_texture(&self.memory_allocator, width, height)?;
+        self.image = image;
+        self.texture = texture;
+        self.upload_buffer = upload_buffer;
+        self.extent = [width, height];
+
+        if let Some(pipeline) = &self.pipeline {
+            self.descriptor_set = Some(self.create_descriptor_set(pipeline));
         }
+        Ok(())
     }
-}
 
-impl EmulatorRenderer for GameboyRenderer {
-    fn create_pipeline(
-        &mut self,
+    fn build_pipeline(
         vulkano_context: &VulkanoContext,
         vulkano_windows: &VulkanoWindows,
-    ) {
+    ) -> Result<Arc<GraphicsPipeline>, String> {
         let vs = vs::load(vulkano_context.device().clone())
-            .unwrap()
+            .map_err(|err| err.to_string())?
             .entry_point("main")
-            .unwrap();
+            .ok_or("Vertex shader has no main entry point")?;
         let fs = fs::load(vulkano_context.device().clone())
-            .unwrap()
+            .map_err(|err| err.to_string())?
             .entry_point("main")
-            .unwrap();
+            .ok_or("Fragment shader has no main entry point")?;
 
         let stages = [
             PipelineShaderStageCreateInfo::new(vs),
@@ -123,21 +168,21 @@ impl EmulatorRenderer for GameboyRenderer {
             vulkano_context.device().clone(),
             PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
                 .into_pipeline_layout_create_info(vulkano_context.device().clone())
-                .unwrap(),
+                .map_err(|err| err.to_string())?,
         )
-        .unwrap();
+        .map_err(|err| err.to_string())?;
 
         let subpass = PipelineRenderingCreateInfo {
             color_attachment_formats: vec![Some(
                 vulkano_windows
                     .get_primary_renderer()
-                    .unwrap()
+                    .ok_or("No window to render to")?
                     .swapchain_format(),
             )],
             ..Default::default()
         };
 
-        let pipeline = GraphicsPipeline::new(
+        GraphicsPipeline::new(
             vulkano_context.device().clone(),
             None,
             GraphicsPipelineCreateInfo {
@@ -163,12 +208,14 @@ impl EmulatorRenderer for GameboyRenderer {
                 )),
                 dynamic_state: [DynamicState::Viewport].into_iter().collect(),
                 subpass: Some(subpass.into()),
-                ..GraphicsPipelineCreateInfo::layout(layout.clone())
+                ..GraphicsPipelineCreateInfo::layout(layout)
             },
         )
-        .unwrap();
+        .map_err(|err| err.to_string())
+    }
 
-        let descriptor_set = DescriptorSet::new(
+    fn create_descriptor_set(&self, pipeline: &Arc<GraphicsPipeline>) -> Arc<DescriptorSet> {
+        DescriptorSet::new(
             self.descriptor_set_allocator.clone(),
             pipeline.layout().set_layouts()[0].clone(),
             [
@@ -177,32 +224,73 @@ impl EmulatorRenderer for GameboyRenderer {
             ],
             [],
         )
-        .unwrap();
+        .unwrap()
+    }
+}
 
-        self.descriptor_set = Some(descriptor_set);
-        self.pipeline = Some(pipeline);
+impl EmulatorRenderer for GameboyRenderer {
+    fn create_pipeline(
+        &mut self,
+        vulkano_context: &VulkanoContext,
+        vulkano_windows: &VulkanoWindows,
+    ) {
+        match Self::build_pipeline(vulkano_context, vulkano_windows) {
+            Ok(pipeline) => {
+                self.descriptor_set = Some(self.create_descriptor_set(&pipeline));
+                self.pipeline = Some(pipeline);
+            }
+            Err(err) => {
+                log!(
+                    Level::Error,
+                    "Failed to create emulator pipeline, falling back to software: {}",
+                    err
+                );
+                self.pipeline = None;
+                self.descriptor_set = None;
+                self.fallback = Some(SoftwareRenderer::new());
+            }
+        }
     }
 
     fn sync_render_world(&mut self, emulator_state: &EmulatorState) {
+        if let Some(fallback) = &mut self.fallback {
+            fallback.sync_render_world(emulator_state);
+            return;
+        }
+
         if let EmulatorState::GameBoy(gameboy_state) = emulator_state {
-            let mut writer = self.upload_buffer.write().unwrap();
-            for idx in 0..(160 * 144) {
-                let color: u8 = match gameboy_state.frame_buffer[idx] {
-                    0 => 0xFF,
-                    1 => 0xAA,
-                    2 => 0x55,
-                    3 => 0x00,
-                    _ => panic!("Received invalid color code"),
-                };
-                writer[idx * 4] = color;
-                writer[idx * 4 + 1] = color;
-                writer[idx * 4 + 2] = color;
-                writer[idx * 4 + 3] = 0xFF;
+            let rgba = gameboy_state
+                .color_palette
+                .apply(&gameboy_state.frame_buffer, &gameboy_state.palette_buffer);
+            let settings = THREAD_LOCAL_CONFIG.with(|c| c.borrow_mut().load().filter_config);
+            let image = self
+                .filters
+                .process_frame(&settings, &rgba, gameboy_state.frame_count);
+
+            if [image.width, image.height] != self.extent {
+                if let Err(err) = self.resize_texture(image.width, image.height) {
+                    log!(
+                        Level::Error,
+                        "Failed to resize emulator texture, falling back to software: {}",
+                        err
+                    );
+                    let mut fallback = SoftwareRenderer::new();
+                    fallback.sync_render_world(emulator_state);
+                    self.fallback = Some(fallback);
+                    return;
+                }
             }
+            self.upload_buffer
+                .write()
+                .unwrap()
+                .copy_from_slice(&image.pixels);
         }
     }
 
     fn gpu_upload(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        if self.fallback.is_some() {
+            return;
+        }
         builder
             .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
                 self.upload_buffer.clone(),
@@ -212,18 +300,9 @@ impl EmulatorRenderer for GameboyRenderer {
     }
 
     fn render(&self, callback_info: PaintCallbackInfo, callback_context: &mut CallbackContext) {
-        let pipeline = match &self.pipeline {
-            None => {
-                panic!()
-            }
-            Some(pipeline) => pipeline,
-        };
-
-        let descriptor_set = match &self.descriptor_set {
-            None => {
-                panic!()
-            }
-            Some(descriptor_set) => descriptor_set,
+        // Nothing to draw with after falling back to software
+        let (Some(pipeline), Some(descriptor_set)) = (&self.pipeline, &self.descriptor_set) else {
+            return;
         };
 
         // Set gb screen ratio
@@ -264,6 +343,12 @@ impl EmulatorRenderer for GameboyRenderer {
 
         unsafe { callback_context.builder.draw(3, 1, 0, 0) }.unwrap();
     }
+
+    fn texture(&mut self, egui_context: &Context) -> Option<TextureId> {
+        self.fallback
+            .as_mut()
+            .and_then(|fallback| fallback.texture(egui_context))
+    }
 }
 
 mod vs {
@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+// Frame and cycle counts are relative to the start of the run
+#[derive(Clone, PartialEq, Debug)]
+pub enum StopCondition {
+    Frames(u64),
+    // M-cycles
+    Cycles(u64),
+    Pc(u16),
+    SerialContains(String),
+    MemoryEquals(u16, u8),
+    // LD B,B, used by test ROMs to signal they are done
+    SoftwareBreakpoint,
+    // Wall-clock time, for ROMs that may never meet any of the other conditions
+    Timeout(Duration),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct StopReason {
+    // First condition of the list that was met
+    pub condition: StopCondition,
+    pub frames: u64,
+    pub cycles: u64,
+}
+
+// Wall-clock time is only checked every this many instructions
+pub(crate) const TIMEOUT_CHECK_INTERVAL: u64 = 4096;
+
+// M-cycles in a frame, used to keep time while the LCD is off and no frames are produced
+pub const CYCLES_PER_FRAME: u64 = 70224 / 4;
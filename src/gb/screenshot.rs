@@ -0,0 +1,144 @@
+use crate::gb::filters::{FilterChain, FilterSettings, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::gb::palette::{ColorPalette, DmgPalette};
+use directories::ProjectDirs;
+use image::{ColorType, ImageReader};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum ScreenshotMode {
+    // Color IDs 0-3 stored as-is in a grayscale image, independent of palette and filters so
+    // they can be used as test fixtures
+    Raw,
+    #[default]
+    Palette,
+    Filtered,
+}
+
+impl ScreenshotMode {
+    pub const ALL: [ScreenshotMode; 3] = [
+        ScreenshotMode::Raw,
+        ScreenshotMode::Palette,
+        ScreenshotMode::Filtered,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScreenshotMode::Raw => "Raw color IDs",
+            ScreenshotMode::Palette => "Palette",
+            ScreenshotMode::Filtered => "Palette and filters",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Screenshot {
+    pub width: usize,
+    pub height: usize,
+    // One byte per pixel in raw mode, RGBA otherwise
+    pub raw: bool,
+    pub pixels: Vec<u8>,
+}
+
+impl Screenshot {
+    pub(crate) fn raw(frame_buffer: &[u8]) -> Self {
+        Screenshot {
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+            raw: true,
+            pixels: frame_buffer.to_vec(),
+        }
+    }
+
+    pub(crate) fn with_palette(
+        frame_buffer: &[u8],
+        palette_buffer: &[DmgPalette],
+        palette: &ColorPalette,
+    ) -> Self {
+        Screenshot {
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+            raw: false,
+            pixels: palette.apply(frame_buffer, palette_buffer),
+        }
+    }
+
+    // A fresh filter chain is used, so frame blending has no previous frame to blend with
+    pub(crate) fn with_filters(
+        frame_buffer: &[u8],
+        palette_buffer: &[DmgPalette],
+        palette: &ColorPalette,
+        filters: &FilterSettings,
+    ) -> Self {
+        let rgba = palette.apply(frame_buffer, palette_buffer);
+        let image = FilterChain::new().process(filters, &rgba);
+        Screenshot {
+            width: image.width,
+            height: image.height,
+            raw: false,
+            pixels: image.pixels,
+        }
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let color_type = if self.raw {
+            ColorType::L8
+        } else {
+            ColorType::Rgba8
+        };
+        image::save_buffer(
+            path,
+            &self.pixels,
+            self.width as u32,
+            self.height as u32,
+            color_type,
+        )
+        .map_err(|err| err.to_string())
+    }
+
+    // Loads a PNG as RGBA, or as raw color IDs when `raw` is set
+    pub fn load_png(path: impl AsRef<Path>, raw: bool) -> Result<Self, String> {
+        let image = ImageReader::open(path)
+            .map_err(|err| err.to_string())?
+            .decode()
+            .map_err(|err| err.to_string())?;
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let pixels = if raw {
+            image.into_luma8().into_raw()
+        } else {
+            image.into_rgba8().into_raw()
+        };
+        Ok(Screenshot {
+            width,
+            height,
+            raw,
+            pixels,
+        })
+    }
+}
+
+// Next free file in the screenshot folder of the ROM, e.g. `screenshots/tetris/tetris_003.png`
+pub(crate) fn screenshot_path(rom_path: Option<&str>) -> Result<PathBuf, String> {
+    let rom_name = rom_path
+        .and_then(|path| Path::new(path).file_stem())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or("unknown".to_string());
+
+    let project_dirs =
+        ProjectDirs::from("", "", "Mnemosyne").ok_or("No data directory available")?;
+    let mut folder = PathBuf::new();
+    folder.push(project_dirs.data_dir());
+    folder.push("screenshots");
+    folder.push(&rom_name);
+    fs::create_dir_all(&folder).map_err(|err| err.to_string())?;
+
+    let mut index = 1;
+    loop {
+        let path = folder.join(format!("{}_{:03}.png", rom_name, index));
+        if !path.exists() {
+            return Ok(path);
+        }
+        index += 1;
+    }
+}
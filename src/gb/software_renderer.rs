@@ -0,0 +1,69 @@
+use crate::config::THREAD_LOCAL_CONFIG;
+use crate::egui_renderer::CallbackContext;
+use crate::emulator::EmulatorState;
+use crate::gb::filters::FilterChain;
+use crate::vulkan_renderer::EmulatorRenderer;
+use egui::{ColorImage, Context, PaintCallbackInfo, TextureHandle, TextureId, TextureOptions};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano_util::context::VulkanoContext;
+use vulkano_util::window::VulkanoWindows;
+
+// Fallback for when the Vulkan pipeline of GameboyRenderer is unavailable. Frames are prepared on
+// the CPU and handed to egui as a regular texture, so no custom pipeline or shaders are needed.
+pub(crate) struct SoftwareRenderer {
+    filters: FilterChain,
+    // Latest frame that hasn't been uploaded to the texture yet
+    frame: Option<ColorImage>,
+    texture: Option<TextureHandle>,
+}
+
+impl SoftwareRenderer {
+    pub(crate) fn new() -> Self {
+        SoftwareRenderer {
+            filters: FilterChain::new(),
+            frame: None,
+            texture: None,
+        }
+    }
+}
+
+impl EmulatorRenderer for SoftwareRenderer {
+    fn sync_render_world(&mut self, emulator_state: &EmulatorState) {
+        if let EmulatorState::GameBoy(gameboy_state) = emulator_state {
+            let rgba = gameboy_state
+                .color_palette
+                .apply(&gameboy_state.frame_buffer, &gameboy_state.palette_buffer);
+            let settings = THREAD_LOCAL_CONFIG.with(|c| c.borrow_mut().load().filter_config);
+            let image = self
+                .filters
+                .process_frame(&settings, &rgba, gameboy_state.frame_count);
+
+            self.frame = Some(ColorImage::from_rgba_unmultiplied(
+                [image.width, image.height],
+                &image.pixels,
+            ));
+        }
+    }
+
+    fn gpu_upload(&self, _: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {}
+
+    fn render(&self, _: PaintCallbackInfo, _: &mut CallbackContext) {}
+
+    fn create_pipeline(&mut self, _: &VulkanoContext, _: &VulkanoWindows) {}
+
+    fn texture(&mut self, egui_context: &Context) -> Option<TextureId> {
+        if let Some(frame) = self.frame.take() {
+            match &mut self.texture {
+                Some(texture) => texture.set(frame, TextureOptions::NEAREST),
+                None => {
+                    self.texture = Some(egui_context.load_texture(
+                        "gameboy_screen",
+                        frame,
+                        TextureOptions::NEAREST,
+                    ))
+                }
+            }
+        }
+        self.texture.as_ref().map(|texture| texture.id())
+    }
+}
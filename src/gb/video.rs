@@ -0,0 +1,511 @@
+use crate::gb::filters::{FilterChain, FilterSettings, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::gb::palette::{ColorPalette, DmgPalette};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{Delay, ExtendedColorType, Frame, ImageEncoder, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+const CLOCK_RATE: u64 = 4194304;
+const CYCLES_PER_FRAME: u64 = 70224;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum VideoFormat {
+    // Uncompressed YUV 4:4:4
+    Y4m,
+    Gif,
+    Apng,
+}
+
+impl VideoFormat {
+    pub const ALL: [VideoFormat; 3] = [VideoFormat::Y4m, VideoFormat::Gif, VideoFormat::Apng];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VideoFormat::Y4m => "Y4M",
+            VideoFormat::Gif => "GIF",
+            VideoFormat::Apng => "APNG",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VideoFormat::Y4m => "y4m",
+            VideoFormat::Gif => "gif",
+            VideoFormat::Apng => "png",
+        }
+    }
+}
+
+// Frames waiting for the encoder thread. When encoding falls behind the emulator waits for it,
+// so no frames are skipped
+const FRAME_QUEUE_LENGTH: usize = 120;
+
+// Captures every completed frame, called by the PPU at vblank so that a slow renderer can't
+// cause frames to be skipped. Colorization, filters and encoding run on a separate thread
+pub(crate) struct VideoRecorder {
+    frames: SyncSender<(Vec<u8>, Vec<DmgPalette>)>,
+    encoder_thread: JoinHandle<Result<(), String>>,
+    // Audio is written by the APU to a WAV file next to the video
+    pub(crate) with_audio: bool,
+}
+
+enum Encoder {
+    Y4m(Y4mWriter),
+    Gif(GifWriter),
+    Apng(ApngWriter),
+}
+
+impl VideoRecorder {
+    pub(crate) fn new(
+        path: &Path,
+        format: VideoFormat,
+        palette: ColorPalette,
+        filters: Option<FilterSettings>,
+        with_audio: bool,
+    ) -> Result<Self, String> {
+        let file = BufWriter::new(File::create(path).map_err(|err| err.to_string())?);
+        let encoder = match format {
+            VideoFormat::Y4m => Encoder::Y4m(Y4mWriter::new(file)),
+            VideoFormat::Gif => Encoder::Gif(GifWriter::new(file)?),
+            VideoFormat::Apng => Encoder::Apng(ApngWriter::new(file)),
+        };
+
+        let (frames, receiver) = mpsc::sync_channel(FRAME_QUEUE_LENGTH);
+        let encoder_thread = thread::Builder::new()
+            .name("Video encoder".to_owned())
+            .spawn(move || encode_frames(encoder, palette, filters, receiver))
+            .map_err(|err| err.to_string())?;
+
+        Ok(VideoRecorder {
+            frames,
+            encoder_thread,
+            with_audio,
+        })
+    }
+
+    // Fails when the encoder thread has stopped, finish returns the reason
+    pub(crate) fn write_frame(
+        &mut self,
+        frame_buffer: &[u8],
+        palette_buffer: &[DmgPalette],
+    ) -> Result<(), String> {
+        self.frames
+            .send((frame_buffer.to_vec(), palette_buffer.to_vec()))
+            .map_err(|_| "Video encoder stopped".to_string())
+    }
+
+    // Waits for the queued frames to be encoded
+    pub(crate) fn finish(self) -> Result<(), String> {
+        drop(self.frames);
+        self.encoder_thread
+            .join()
+            .map_err(|_| "Video encoder thread panicked".to_string())?
+    }
+}
+
+fn encode_frames(
+    mut encoder: Encoder,
+    palette: ColorPalette,
+    filters: Option<FilterSettings>,
+    frames: Receiver<(Vec<u8>, Vec<DmgPalette>)>,
+) -> Result<(), String> {
+    let mut filter_chain = FilterChain::new();
+    for (frame_buffer, palette_buffer) in frames {
+        let rgba = palette.apply(&frame_buffer, &palette_buffer);
+        let (width, height, pixels) = match &filters {
+            Some(filters) => {
+                let image = filter_chain.process(filters, &rgba);
+                (image.width, image.height, image.pixels)
+            }
+            None => (SCREEN_WIDTH, SCREEN_HEIGHT, rgba),
+        };
+
+        encoder.write_frame(width, height, pixels)?;
+    }
+    encoder.finish()
+}
+
+impl Encoder {
+    fn write_frame(&mut self, width: usize, height: usize, pixels: Vec<u8>) -> Result<(), String> {
+        match self {
+            Encoder::Y4m(writer) => writer.write_frame(width, height, &pixels),
+            Encoder::Gif(writer) => writer.write_frame(width, height, pixels),
+            Encoder::Apng(writer) => writer.write_frame(width, height, &pixels),
+        }
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self {
+            Encoder::Y4m(writer) => writer.finish(),
+            Encoder::Gif(writer) => writer.finish(),
+            Encoder::Apng(writer) => writer.finish(),
+        }
+    }
+}
+
+struct Y4mWriter {
+    out: BufWriter<File>,
+    header_written: bool,
+}
+
+impl Y4mWriter {
+    fn new(out: BufWriter<File>) -> Self {
+        Y4mWriter {
+            out,
+            header_written: false,
+        }
+    }
+
+    fn write_frame(&mut self, width: usize, height: usize, rgba: &[u8]) -> Result<(), String> {
+        if !self.header_written {
+            writeln!(
+                self.out,
+                "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444 XCOLORRANGE=FULL",
+                width, height, CLOCK_RATE, CYCLES_PER_FRAME
+            )
+            .map_err(|err| err.to_string())?;
+            self.header_written = true;
+        }
+
+        // Full range BT.601, written as separate Y, U and V planes
+        let mut planes = vec![0u8; width * height * 3];
+        let (y_plane, chroma) = planes.split_at_mut(width * height);
+        let (u_plane, v_plane) = chroma.split_at_mut(width * height);
+        for (i, pixel) in rgba.chunks_exact(4).enumerate() {
+            let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+            y_plane[i] = (0.299 * r + 0.587 * g + 0.114 * b).round() as u8;
+            u_plane[i] = (128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b).round() as u8;
+            v_plane[i] = (128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b).round() as u8;
+        }
+
+        self.out
+            .write_all(b"FRAME\n")
+            .and_then(|_| self.out.write_all(&planes))
+            .map_err(|err| err.to_string())
+    }
+
+    fn finish(mut self) -> Result<(), String> {
+        self.out.flush().map_err(|err| err.to_string())
+    }
+}
+
+// GIF delays are in centiseconds and viewers slow down anything below 2cs, so frames that would
+// be shown shorter than that are replaced by the next one
+struct GifWriter {
+    encoder: GifEncoder<BufWriter<File>>,
+    pending: Option<RgbaImage>,
+    frames: u64,
+    written_cs: u64,
+}
+
+impl GifWriter {
+    fn new(out: BufWriter<File>) -> Result<Self, String> {
+        let mut encoder = GifEncoder::new(out);
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|err| err.to_string())?;
+        Ok(GifWriter {
+            encoder,
+            pending: None,
+            frames: 0,
+            written_cs: 0,
+        })
+    }
+
+    fn write_frame(&mut self, width: usize, height: usize, rgba: Vec<u8>) -> Result<(), String> {
+        let image = RgbaImage::from_raw(width as u32, height as u32, rgba)
+            .ok_or("Frame does not match its dimensions")?;
+
+        let now_cs = Self::timestamp_cs(self.frames);
+        self.frames += 1;
+        if let Some(pending) = self.pending.take() {
+            let delay = now_cs - self.written_cs;
+            if delay >= 2 {
+                self.encode(pending, delay)?;
+            }
+        }
+        self.pending = Some(image);
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), String> {
+        if let Some(pending) = self.pending.take() {
+            let delay = (Self::timestamp_cs(self.frames) - self.written_cs).max(2);
+            self.encode(pending, delay)?;
+        }
+        Ok(())
+    }
+
+    fn encode(&mut self, image: RgbaImage, delay_cs: u64) -> Result<(), String> {
+        let delay = Delay::from_numer_denom_ms(delay_cs as u32 * 10, 1);
+        self.encoder
+            .encode_frame(Frame::from_parts(image, 0, 0, delay))
+            .map_err(|err| err.to_string())?;
+        self.written_cs += delay_cs;
+        Ok(())
+    }
+
+    fn timestamp_cs(frame: u64) -> u64 {
+        frame * CYCLES_PER_FRAME * 100 / CLOCK_RATE
+    }
+}
+
+// The PNG signature and IHDR chunk come before acTL
+const ACTL_OFFSET: u64 = 8 + 25;
+// Closest u16 fraction to the 59.73 Hz frame time
+const APNG_DELAY: (u16, u16) = (1000, 59727);
+
+// APNG is written chunk by chunk, with the image data of each frame taken from a regular PNG
+// encode. The frame count in acTL isn't known up front, so it is patched in when finishing.
+struct ApngWriter {
+    out: BufWriter<File>,
+    frames: u32,
+    sequence: u32,
+}
+
+impl ApngWriter {
+    fn new(out: BufWriter<File>) -> Self {
+        ApngWriter {
+            out,
+            frames: 0,
+            sequence: 0,
+        }
+    }
+
+    fn write_frame(&mut self, width: usize, height: usize, rgba: &[u8]) -> Result<(), String> {
+        let mut png = Vec::new();
+        PngEncoder::new_with_quality(&mut png, CompressionType::Fast, FilterType::Adaptive)
+            .write_image(rgba, width as u32, height as u32, ExtendedColorType::Rgba8)
+            .map_err(|err| err.to_string())?;
+        let (ihdr, image_data) = split_png(&png)?;
+
+        if self.frames == 0 {
+            self.out
+                .write_all(&png[..8])
+                .map_err(|err| err.to_string())?;
+            self.write_chunk(b"IHDR", &ihdr)?;
+            // Frame count placeholder, loop forever
+            self.write_chunk(b"acTL", &[0; 8])?;
+        }
+
+        let mut fctl = Vec::with_capacity(26);
+        fctl.extend(self.sequence.to_be_bytes());
+        fctl.extend((width as u32).to_be_bytes());
+        fctl.extend((height as u32).to_be_bytes());
+        fctl.extend(0u32.to_be_bytes()); // x offset
+        fctl.extend(0u32.to_be_bytes()); // y offset
+        fctl.extend(APNG_DELAY.0.to_be_bytes());
+        fctl.extend(APNG_DELAY.1.to_be_bytes());
+        fctl.extend([0, 0]); // dispose and blend op
+        self.sequence += 1;
+        self.write_chunk(b"fcTL", &fctl)?;
+
+        // The first frame doubles as the default image
+        if self.frames == 0 {
+            self.write_chunk(b"IDAT", &image_data)?;
+        } else {
+            let mut fdat = Vec::with_capacity(image_data.len() + 4);
+            fdat.extend(self.sequence.to_be_bytes());
+            fdat.extend(&image_data);
+            self.sequence += 1;
+            self.write_chunk(b"fdAT", &fdat)?;
+        }
+
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), String> {
+        if self.frames == 0 {
+            return Ok(());
+        }
+        self.write_chunk(b"IEND", &[])?;
+
+        let mut actl = Vec::with_capacity(12);
+        actl.extend(b"acTL");
+        actl.extend(self.frames.to_be_bytes());
+        actl.extend(0u32.to_be_bytes());
+        let mut file = self.out.into_inner().map_err(|err| err.to_string())?;
+        file.seek(SeekFrom::Start(ACTL_OFFSET + 8))
+            .and_then(|_| file.write_all(&actl[4..]))
+            .and_then(|_| file.write_all(&crc32fast::hash(&actl).to_be_bytes()))
+            .map_err(|err| err.to_string())
+    }
+
+    fn write_chunk(&mut self, kind: &[u8; 4], data: &[u8]) -> Result<(), String> {
+        let mut crc_data = Vec::with_capacity(data.len() + 4);
+        crc_data.extend(kind);
+        crc_data.extend(data);
+
+        self.out
+            .write_all(&(data.len() as u32).to_be_bytes())
+            .and_then(|_| self.out.write_all(&crc_data))
+            .and_then(|_| {
+                self.out
+                    .write_all(&crc32fast::hash(&crc_data).to_be_bytes())
+            })
+            .map_err(|err| err.to_string())
+    }
+}
+
+// Returns the IHDR contents and the concatenated IDAT contents of a PNG file
+fn split_png(png: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let mut ihdr = Vec::new();
+    let mut image_data = Vec::new();
+    let mut offset = 8;
+    while offset + 8 <= png.len() {
+        let length = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+        let kind = &png[offset + 4..offset + 8];
+        let data = png
+            .get(offset + 8..offset + 8 + length)
+            .ok_or("Truncated PNG chunk")?;
+        match kind {
+            b"IHDR" => ihdr = data.to_vec(),
+            b"IDAT" => image_data.extend(data),
+            _ => {}
+        }
+        offset += 12 + length;
+    }
+    Ok((ihdr, image_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifDecoder;
+    use image::AnimationDecoder;
+    use std::fs;
+    use std::io::BufReader;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("mnemosyne_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory.join(name)
+    }
+
+    fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .write_image(rgba, width, height, ExtendedColorType::Rgba8)
+            .unwrap();
+        png
+    }
+
+    // Kind, data and whether the CRC matched for every chunk after the signature
+    fn read_chunks(file: &[u8]) -> Vec<([u8; 4], Vec<u8>, bool)> {
+        let mut chunks = Vec::new();
+        let mut offset = 8;
+        while offset < file.len() {
+            let length = u32::from_be_bytes(file[offset..offset + 4].try_into().unwrap()) as usize;
+            let kind_and_data = &file[offset + 4..offset + 8 + length];
+            let crc = u32::from_be_bytes(
+                file[offset + 8 + length..offset + 12 + length]
+                    .try_into()
+                    .unwrap(),
+            );
+            chunks.push((
+                kind_and_data[..4].try_into().unwrap(),
+                kind_and_data[4..].to_vec(),
+                crc32fast::hash(kind_and_data) == crc,
+            ));
+            offset += 12 + length;
+        }
+        chunks
+    }
+
+    #[test]
+    fn chunk_crc() {
+        let path = temp_path("chunk_crc.png");
+        let mut writer = ApngWriter::new(BufWriter::new(File::create(&path).unwrap()));
+        writer.write_chunk(b"IEND", &[]).unwrap();
+        writer.out.flush().unwrap();
+
+        let file = fs::read(&path).unwrap();
+        assert_eq!(
+            file,
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+        );
+    }
+
+    #[test]
+    fn split_png_chunks() {
+        let png = encode_png(2, 1, &[0xFF, 0, 0, 0xFF, 0, 0xFF, 0, 0xFF]);
+        let (ihdr, image_data) = split_png(&png).unwrap();
+        assert_eq!(ihdr.len(), 13);
+        assert_eq!(ihdr[..8], [0, 0, 0, 2, 0, 0, 0, 1]);
+        assert!(!image_data.is_empty());
+
+        assert!(split_png(&png[..png.len() - 20]).is_err());
+    }
+
+    #[test]
+    fn apng_layout() {
+        let path = temp_path("layout.png");
+        let mut writer = ApngWriter::new(BufWriter::new(File::create(&path).unwrap()));
+        for value in [0x00, 0x80, 0xFF] {
+            writer.write_frame(2, 2, &[value; 2 * 2 * 4]).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let file = fs::read(&path).unwrap();
+        assert_eq!(file[..8], encode_png(1, 1, &[0; 4])[..8]);
+        let chunks = read_chunks(&file);
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _, _)| kind).collect();
+        assert_eq!(
+            kinds,
+            [b"IHDR", b"acTL", b"fcTL", b"IDAT", b"fcTL", b"fdAT", b"fcTL", b"fdAT", b"IEND"]
+        );
+        assert!(chunks.iter().all(|(_, _, crc_matches)| *crc_matches));
+
+        // The frame count is patched in when finishing, plays forever
+        assert_eq!(chunks[1].1, [0, 0, 0, 3, 0, 0, 0, 0]);
+        assert_eq!(ACTL_OFFSET, 8 + 12 + chunks[0].1.len() as u64);
+
+        // fcTL and fdAT share one sequence
+        let sequence = |index: usize| u32::from_be_bytes(chunks[index].1[..4].try_into().unwrap());
+        assert_eq!(
+            [2, 4, 5, 6, 7].map(sequence),
+            [0, 1, 2, 3, 4],
+            "sequence numbers"
+        );
+        assert_eq!(chunks[2].1[4..12], [0, 0, 0, 2, 0, 0, 0, 2]);
+    }
+
+    #[test]
+    fn apng_without_frames_is_empty() {
+        let path = temp_path("empty.png");
+        let writer = ApngWriter::new(BufWriter::new(File::create(&path).unwrap()));
+        writer.finish().unwrap();
+        assert!(fs::read(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn gif_merges_short_frames() {
+        let path = temp_path("delays.gif");
+        let mut writer = GifWriter::new(BufWriter::new(File::create(&path).unwrap())).unwrap();
+        // Frames start at 0, 1, 3, 5, 6 and 8cs, the ones shown for less than 2cs are dropped
+        for frame in 0..6u8 {
+            writer
+                .write_frame(1, 1, vec![frame * 40, frame * 40, frame * 40, 0xFF])
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let decoder = GifDecoder::new(BufReader::new(File::open(&path).unwrap())).unwrap();
+        let frames: Vec<(u8, u32)> = decoder
+            .into_frames()
+            .map(|frame| {
+                let frame = frame.unwrap();
+                let (numer, denom) = frame.delay().numer_denom_ms();
+                (frame.buffer().get_pixel(0, 0)[0], numer / denom)
+            })
+            .collect();
+        assert_eq!(frames, [(40, 30), (80, 20), (160, 30), (200, 20)]);
+    }
+}
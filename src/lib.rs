@@ -1,9 +1,18 @@
 #![feature(adt_const_params)]
 pub mod audio;
+#[cfg(feature = "frontend")]
 mod cached_ehttp_loader;
 pub mod config;
+#[cfg(feature = "frontend")]
 pub mod egui_renderer;
+#[cfg(feature = "frontend")]
 pub mod emulator;
 pub mod gb;
+#[cfg(feature = "frontend")]
+pub mod scripting;
+#[cfg(feature = "frontend")]
+pub mod software_presenter;
+#[cfg(feature = "frontend")]
 pub mod ui;
+#[cfg(feature = "frontend")]
 pub mod vulkan_renderer;
@@ -5,13 +5,21 @@ mod config;
 mod egui_renderer;
 mod emulator;
 mod gb;
+mod scripting;
+mod software_presenter;
 mod ui;
 mod vulkan_renderer;
 
+use crate::config::{RendererBackend, THREAD_LOCAL_CONFIG};
 use crate::egui_renderer::EguiRenderer;
 use crate::emulator::{Emulator, EmulatorControlMessage, SyncMessage};
-use crate::vulkan_renderer::VulkanRenderer;
+use crate::gb::renderer::GameboyRenderer;
+use crate::gb::software_renderer::SoftwareRenderer;
+use crate::software_presenter::SoftwarePresenter;
+use crate::ui::UIState;
+use crate::vulkan_renderer::{EmulatorRenderer, VulkanRenderer};
 use flexi_logger::{Age, Cleanup, Criterion, FileSpec, LoggerHandle, Naming};
+use log::{log, Level};
 use std::any::Any;
 use std::error::Error;
 use std::path::PathBuf;
@@ -25,8 +33,7 @@ use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::WindowId;
 
 struct App {
-    renderer: VulkanRenderer,
-    egui_renderer: EguiRenderer,
+    presenter: Presenter,
     join_handle: Option<JoinHandle<()>>,
     rx_sync: Receiver<SyncMessage>,
     tx_sync: SyncSender<SyncMessage>,
@@ -34,6 +41,14 @@ struct App {
     logger_handle: LoggerHandle,
 }
 
+enum Presenter {
+    Vulkan {
+        renderer: VulkanRenderer,
+        egui_renderer: EguiRenderer,
+    },
+    Software(SoftwarePresenter),
+}
+
 fn main() -> Result<(), impl Error> {
     // Setup logging
     let egui_logger = Box::new(egui_logger::builder().build());
@@ -63,18 +78,52 @@ impl App {
         let (tx_controls, rx_controls) = mpsc::channel::<KeyEvent>();
         let (tx_ui, rx_ui) = mpsc::channel::<EmulatorControlMessage>();
 
-        let mut renderer = VulkanRenderer::new(event_loop);
-        let egui_renderer = EguiRenderer::new(
-            &renderer.context,
-            renderer.command_buffer_allocator.clone(),
-            tx_ui,
-        );
+        let backend = THREAD_LOCAL_CONFIG.with(|c| c.borrow_mut().load().renderer);
+        let (presenter, emulator_renderer) = match VulkanRenderer::new(event_loop) {
+            Ok(mut renderer) => {
+                let egui_renderer = EguiRenderer::new(
+                    &renderer.context,
+                    renderer.command_buffer_allocator.clone(),
+                    tx_ui,
+                );
 
-        let emulator_renderer = Arc::new(Mutex::new(crate::gb::renderer::GameboyRenderer::new(
-            &renderer.context,
-            &renderer.windows,
-        )));
-        renderer.set_emulator_renderer(emulator_renderer.clone());
+                let emulator_renderer: Arc<Mutex<dyn EmulatorRenderer>> = match backend {
+                    RendererBackend::Vulkan => {
+                        match GameboyRenderer::new(&renderer.context, &renderer.windows) {
+                            Ok(gameboy_renderer) => Arc::new(Mutex::new(gameboy_renderer)),
+                            Err(err) => {
+                                log!(
+                                    Level::Warn,
+                                    "Failed to create Vulkan renderer, falling back to software: {}",
+                                    err
+                                );
+                                Arc::new(Mutex::new(SoftwareRenderer::new()))
+                            }
+                        }
+                    }
+                    RendererBackend::Software => Arc::new(Mutex::new(SoftwareRenderer::new())),
+                };
+                renderer.set_emulator_renderer(emulator_renderer.clone());
+
+                let presenter = Presenter::Vulkan {
+                    renderer,
+                    egui_renderer,
+                };
+                (presenter, emulator_renderer)
+            }
+            Err(err) => {
+                log!(
+                    Level::Warn,
+                    "Failed to initialize Vulkan, falling back to software presentation: {}",
+                    err
+                );
+                let emulator_renderer: Arc<Mutex<dyn EmulatorRenderer>> =
+                    Arc::new(Mutex::new(SoftwareRenderer::new()));
+                let presenter =
+                    Presenter::Software(SoftwarePresenter::new(tx_ui, emulator_renderer.clone()));
+                (presenter, emulator_renderer)
+            }
+        };
 
         let emulator = Emulator::new(
             rx_emulator,
@@ -86,8 +135,7 @@ impl App {
         let join_handle = Emulator::start(emulator);
 
         App {
-            renderer,
-            egui_renderer,
+            presenter,
             join_handle: Some(join_handle),
             rx_sync: rx_main,
             tx_sync: tx_main,
@@ -97,11 +145,32 @@ impl App {
     }
 }
 
+impl Presenter {
+    fn ui_state(&self) -> &UIState {
+        match self {
+            Presenter::Vulkan { egui_renderer, .. } => &egui_renderer.ui_state,
+            Presenter::Software(presenter) => &presenter.ui_state,
+        }
+    }
+}
+
 impl ApplicationHandler for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        self.renderer.create_render_context(event_loop);
-        self.egui_renderer
-            .create_render_context(&self.renderer.context, &self.renderer.windows);
+        match &mut self.presenter {
+            Presenter::Vulkan {
+                renderer,
+                egui_renderer,
+            } => {
+                renderer.create_render_context(event_loop);
+                egui_renderer.create_render_context(&renderer.context, &renderer.windows);
+            }
+            Presenter::Software(presenter) => {
+                if let Err(err) = presenter.create_render_context(event_loop) {
+                    log!(Level::Error, "Failed to create window: {}", err);
+                    event_loop.exit();
+                }
+            }
+        }
     }
 
     fn window_event(
@@ -110,8 +179,13 @@ impl ApplicationHandler for App {
         _window_id: WindowId,
         event: WindowEvent,
     ) {
-        self.egui_renderer
-            .handle_window_event(&self.renderer.windows, &event);
+        match &mut self.presenter {
+            Presenter::Vulkan {
+                renderer,
+                egui_renderer,
+            } => egui_renderer.handle_window_event(&renderer.windows, &event),
+            Presenter::Software(presenter) => presenter.handle_window_event(&event),
+        }
         match event {
             WindowEvent::CloseRequested => {
                 self.tx_sync
@@ -126,19 +200,27 @@ impl ApplicationHandler for App {
                 event_loop.exit();
             }
             WindowEvent::Resized(_) => {
-                self.renderer.resize();
+                if let Presenter::Vulkan { renderer, .. } = &mut self.presenter {
+                    renderer.resize();
+                }
             }
             WindowEvent::RedrawRequested => {
                 puffin::GlobalProfiler::lock().new_frame();
                 self.tx_sync
-                    .send(SyncMessage::FrameStart(self.egui_renderer.ui_state.clone()))
+                    .send(SyncMessage::FrameStart(self.presenter.ui_state().clone()))
                     .ok();
                 let emu_state = match self.rx_sync.recv().ok().unwrap_or(SyncMessage::Exit) {
                     SyncMessage::StateSynchronized(emu_state) => emu_state,
                     SyncMessage::Exit => return,
                     _ => panic!("Unexpected message received on main thread"),
                 };
-                self.renderer.redraw(&mut self.egui_renderer, emu_state);
+                match &mut self.presenter {
+                    Presenter::Vulkan {
+                        renderer,
+                        egui_renderer,
+                    } => renderer.redraw(egui_renderer, emu_state),
+                    Presenter::Software(presenter) => presenter.redraw(emu_state),
+                }
             }
             WindowEvent::KeyboardInput {
                 event: key_event, ..
@@ -164,6 +246,9 @@ impl ApplicationHandler for App {
     }
 
     fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
-        self.renderer.request_redraw();
+        match &mut self.presenter {
+            Presenter::Vulkan { renderer, .. } => renderer.request_redraw(),
+            Presenter::Software(presenter) => presenter.request_redraw(),
+        }
     }
 }
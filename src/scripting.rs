@@ -0,0 +1,567 @@
+use crate::gb::joypad::Button;
+use crate::gb::GameBoy;
+use log::{log, Level};
+use mlua::{Function, Lua, RegistryKey, Variadic};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+// Shapes drawn by scripts on top of the game screen, in Game Boy screen coordinates
+#[derive(Clone)]
+pub(crate) enum OverlayShape {
+    Text {
+        x: f32,
+        y: f32,
+        text: String,
+        color: [u8; 4],
+    },
+    Rect {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        color: [u8; 4],
+        filled: bool,
+    },
+    Line {
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        color: [u8; 4],
+    },
+}
+
+#[derive(Default)]
+struct ScriptHooks {
+    frame: Vec<RegistryKey>,
+    input: Vec<RegistryKey>,
+    pc: HashMap<u16, Vec<RegistryKey>>,
+    read: HashMap<u16, Vec<RegistryKey>>,
+    write: HashMap<u16, Vec<RegistryKey>>,
+}
+
+pub(crate) struct ScriptEngine {
+    lua: Lua,
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    hooks: RefCell<ScriptHooks>,
+    overlay: RefCell<Vec<OverlayShape>>,
+    last_frame: u64,
+    last_input_frame: u64,
+}
+
+impl ScriptEngine {
+    pub(crate) fn new() -> Self {
+        ScriptEngine {
+            lua: Lua::new(),
+            path: None,
+            modified: None,
+            hooks: RefCell::new(ScriptHooks::default()),
+            overlay: RefCell::new(Vec::new()),
+            last_frame: 0,
+            last_input_frame: 0,
+        }
+    }
+
+    pub(crate) fn load(&mut self, path: &str, gameboy: &mut GameBoy) {
+        self.path = Some(PathBuf::from(path));
+        self.reload(gameboy);
+    }
+
+    pub(crate) fn unload(&mut self, gameboy: &mut GameBoy) {
+        self.clear_hooks(gameboy);
+        self.path = None;
+        self.modified = None;
+        self.lua = Lua::new();
+    }
+
+    // Re-runs the script against a (possibly new) Game Boy, hooks are registered on the new MMU
+    pub(crate) fn attach(&mut self, gameboy: &mut GameBoy) {
+        if self.path.is_some() {
+            self.reload(gameboy);
+        }
+    }
+
+    pub(crate) fn check_for_changes(&mut self, gameboy: &mut GameBoy) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if modified.is_some() && modified != self.modified {
+            log!(Level::Info, "Script changed, reloading");
+            self.reload(gameboy);
+        }
+    }
+
+    pub(crate) fn overlay(&self) -> Vec<OverlayShape> {
+        self.overlay.borrow().clone()
+    }
+
+    fn reload(&mut self, gameboy: &mut GameBoy) {
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+
+        self.clear_hooks(gameboy);
+        self.lua = Lua::new();
+        self.last_frame = gameboy.frame_count();
+        self.last_input_frame = gameboy.frame_count();
+        self.modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                log!(Level::Error, "Failed to read script {:?}: {}", path, err);
+                return;
+            }
+        };
+
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let result = self.with_api(gameboy, |lua| {
+            lua.globals().set(
+                "print",
+                lua.create_function(|_, values: Variadic<String>| {
+                    log!(Level::Info, "[script] {}", values.join("\t"));
+                    Ok(())
+                })?,
+            )?;
+            lua.load(&source).set_name(name.as_str()).exec()
+        });
+
+        match result {
+            Ok(()) => log!(Level::Info, "Loaded script: {:?}", path),
+            Err(err) => {
+                log!(Level::Error, "Script error: {}", err);
+                self.clear_hooks(gameboy);
+            }
+        }
+    }
+
+    fn clear_hooks(&mut self, gameboy: &mut GameBoy) {
+        *self.hooks.borrow_mut() = ScriptHooks::default();
+        self.overlay.borrow_mut().clear();
+        gameboy.cpu.mmu.read_hooks.clear();
+        gameboy.cpu.mmu.write_hooks.clear();
+        gameboy.cpu.mmu.hooked_accesses.clear();
+    }
+
+    // Dispatches all hooks triggered by the last instruction
+    pub(crate) fn after_tick(&mut self, gameboy: &mut GameBoy) {
+        if self.path.is_none() {
+            return;
+        }
+
+        let pc = gameboy.cpu.registers.PC;
+        let frame = gameboy.frame_count();
+        let accesses = std::mem::take(&mut gameboy.cpu.mmu.hooked_accesses);
+        let joypad_polled = std::mem::take(&mut gameboy.cpu.mmu.io_registers.joypad_polled);
+
+        let new_frame = frame != self.last_frame;
+        let poll_input = joypad_polled && frame != self.last_input_frame;
+        let hit_pc = self.hooks.borrow().pc.contains_key(&pc);
+        if accesses.is_empty() && !new_frame && !poll_input && !hit_pc {
+            return;
+        }
+
+        if new_frame {
+            self.last_frame = frame;
+            self.overlay.borrow_mut().clear();
+        }
+        if poll_input {
+            self.last_input_frame = frame;
+        }
+
+        let result = self.with_api(gameboy, |lua| {
+            for access in &accesses {
+                let callbacks = if access.write {
+                    Self::callbacks(lua, self.hooks.borrow().write.get(&access.address))?
+                } else {
+                    Self::callbacks(lua, self.hooks.borrow().read.get(&access.address))?
+                };
+                for callback in callbacks {
+                    callback.call::<_, ()>((access.address, access.value))?;
+                }
+            }
+
+            if hit_pc {
+                for callback in Self::callbacks(lua, self.hooks.borrow().pc.get(&pc))? {
+                    callback.call::<_, ()>(pc)?;
+                }
+            }
+
+            if poll_input {
+                for callback in Self::callbacks(lua, Some(&self.hooks.borrow().input))? {
+                    callback.call::<_, ()>(frame)?;
+                }
+            }
+
+            if new_frame {
+                for callback in Self::callbacks(lua, Some(&self.hooks.borrow().frame))? {
+                    callback.call::<_, ()>(frame)?;
+                }
+            }
+
+            Ok(())
+        });
+
+        if let Err(err) = result {
+            // Stop the script until it is changed on disk, instead of logging the error every tick
+            log!(Level::Error, "Script error: {}", err);
+            self.clear_hooks(gameboy);
+        }
+    }
+
+    // Resolves the registered callbacks up front, so callbacks are free to register new hooks
+    fn callbacks<'lua>(
+        lua: &'lua Lua,
+        keys: Option<&Vec<RegistryKey>>,
+    ) -> mlua::Result<Vec<Function<'lua>>> {
+        keys.map(|keys| keys.iter().map(|key| lua.registry_value(key)).collect())
+            .unwrap_or(Ok(Vec::new()))
+    }
+
+    // Exposes the `emu` table to the script, borrowing the Game Boy for the duration of `f`
+    fn with_api<R>(
+        &self,
+        gameboy: &mut GameBoy,
+        f: impl FnOnce(&Lua) -> mlua::Result<R>,
+    ) -> mlua::Result<R> {
+        let gameboy = RefCell::new(gameboy);
+        let gameboy = &gameboy;
+        let hooks = &self.hooks;
+        let overlay = &self.overlay;
+
+        self.lua.scope(|scope| {
+            let emu = self.lua.create_table()?;
+
+            // Memory, reads go through peek so they don't poll the joypad, trigger read hooks or
+            // see DMA conflicts and cheats
+            emu.set(
+                "read",
+                scope.create_function(|_, address: u16| {
+                    Ok(gameboy.borrow_mut().cpu.mmu.peek(address))
+                })?,
+            )?;
+            emu.set(
+                "read16",
+                scope.create_function(|_, address: u16| {
+                    let mmu = &mut gameboy.borrow_mut().cpu.mmu;
+                    let lo = mmu.peek(address) as u16;
+                    let hi = mmu.peek(address.wrapping_add(1)) as u16;
+                    Ok((hi << 8) | lo)
+                })?,
+            )?;
+            emu.set(
+                "write",
+                scope.create_function(|_, (address, value): (u16, u8)| {
+                    gameboy.borrow_mut().cpu.mmu.write(address, value);
+                    Ok(())
+                })?,
+            )?;
+
+            // CPU
+            emu.set(
+                "registers",
+                scope.create_function(|lua, ()| {
+                    let registers = &gameboy.borrow().cpu.registers;
+                    let table = lua.create_table()?;
+                    table.set("a", registers.A)?;
+                    table.set("b", registers.B)?;
+                    table.set("c", registers.C)?;
+                    table.set("d", registers.D)?;
+                    table.set("e", registers.E)?;
+                    table.set("f", registers.F)?;
+                    table.set("h", registers.H)?;
+                    table.set("l", registers.L)?;
+                    table.set("sp", registers.SP)?;
+                    table.set("pc", registers.PC)?;
+                    table.set("ime", registers.IME)?;
+                    Ok(table)
+                })?,
+            )?;
+            emu.set(
+                "frame",
+                scope.create_function(|_, ()| Ok(gameboy.borrow().frame_count()))?,
+            )?;
+
+            // Input
+            emu.set(
+                "set_input",
+                scope.create_function(|_, (button, pressed): (String, bool)| {
+                    let button = script_button(&button)?;
+                    gameboy.borrow_mut().set_button(button, pressed);
+                    Ok(())
+                })?,
+            )?;
+            emu.set(
+                "get_input",
+                scope.create_function(|_, button: String| {
+                    let button = script_button(&button)?;
+                    Ok(gameboy.borrow().button_pressed(button))
+                })?,
+            )?;
+
+            // Hooks
+            emu.set(
+                "on_frame",
+                scope.create_function(|lua, callback: Function| {
+                    let key = lua.create_registry_value(callback)?;
+                    hooks.borrow_mut().frame.push(key);
+                    Ok(())
+                })?,
+            )?;
+            emu.set(
+                "on_input",
+                scope.create_function(|lua, callback: Function| {
+                    let key = lua.create_registry_value(callback)?;
+                    hooks.borrow_mut().input.push(key);
+                    Ok(())
+                })?,
+            )?;
+            emu.set(
+                "on_pc",
+                scope.create_function(|lua, (address, callback): (u16, Function)| {
+                    let key = lua.create_registry_value(callback)?;
+                    hooks.borrow_mut().pc.entry(address).or_default().push(key);
+                    Ok(())
+                })?,
+            )?;
+            emu.set(
+                "on_read",
+                scope.create_function(|lua, (address, callback): (u16, Function)| {
+                    let key = lua.create_registry_value(callback)?;
+                    hooks
+                        .borrow_mut()
+                        .read
+                        .entry(address)
+                        .or_default()
+                        .push(key);
+                    gameboy.borrow_mut().cpu.mmu.read_hooks.insert(address);
+                    Ok(())
+                })?,
+            )?;
+            emu.set(
+                "on_write",
+                scope.create_function(|lua, (address, callback): (u16, Function)| {
+                    let key = lua.create_registry_value(callback)?;
+                    hooks
+                        .borrow_mut()
+                        .write
+                        .entry(address)
+                        .or_default()
+                        .push(key);
+                    gameboy.borrow_mut().cpu.mmu.write_hooks.insert(address);
+                    Ok(())
+                })?,
+            )?;
+
+            // Overlay
+            emu.set(
+                "draw_text",
+                scope.create_function(
+                    |_, (x, y, text, color): (f32, f32, String, Option<u32>)| {
+                        overlay.borrow_mut().push(OverlayShape::Text {
+                            x,
+                            y,
+                            text,
+                            color: unpack_color(color),
+                        });
+                        Ok(())
+                    },
+                )?,
+            )?;
+            emu.set(
+                "draw_rect",
+                scope.create_function(
+                    |_,
+                     (x, y, width, height, color, filled): (
+                        f32,
+                        f32,
+                        f32,
+                        f32,
+                        Option<u32>,
+                        Option<bool>,
+                    )| {
+                        overlay.borrow_mut().push(OverlayShape::Rect {
+                            x,
+                            y,
+                            width,
+                            height,
+                            color: unpack_color(color),
+                            filled: filled.unwrap_or(false),
+                        });
+                        Ok(())
+                    },
+                )?,
+            )?;
+            emu.set(
+                "draw_line",
+                scope.create_function(
+                    |_, (x1, y1, x2, y2, color): (f32, f32, f32, f32, Option<u32>)| {
+                        overlay.borrow_mut().push(OverlayShape::Line {
+                            x1,
+                            y1,
+                            x2,
+                            y2,
+                            color: unpack_color(color),
+                        });
+                        Ok(())
+                    },
+                )?,
+            )?;
+
+            self.lua.globals().set("emu", emu)?;
+            f(&self.lua)
+        })
+    }
+}
+
+fn script_button(button: &str) -> mlua::Result<Button> {
+    Button::from_name(button)
+        .ok_or_else(|| mlua::Error::RuntimeError(format!("Unknown button: {}", button)))
+}
+
+// Colors are passed from scripts as 0xRRGGBBAA, defaulting to opaque white
+fn unpack_color(color: Option<u32>) -> [u8; 4] {
+    color.unwrap_or(0xFFFFFFFF).to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::NullSink;
+    use crate::gb::run_until::CYCLES_PER_FRAME;
+    use std::path::Path;
+    use std::time::Duration;
+
+    // Runs `JR -2` from WRAM with the LCD on, so frames advance without a ROM
+    fn setup() -> GameBoy {
+        let mut gameboy = GameBoy::with_audio_sink(Box::new(NullSink::new(48000)));
+        gameboy.skip_boot_rom();
+        gameboy.cpu.mmu.write(0xC000, 0x18);
+        gameboy.cpu.mmu.write(0xC001, 0xFE);
+        gameboy.cpu.registers.PC = 0xC000;
+        gameboy
+    }
+
+    fn script_path(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("mnemosyne_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory.join(name)
+    }
+
+    fn global<T: for<'lua> mlua::FromLua<'lua>>(engine: &ScriptEngine, name: &str) -> T {
+        engine.lua.globals().get(name).unwrap()
+    }
+
+    fn load(path: &Path, source: &str, gameboy: &mut GameBoy) -> ScriptEngine {
+        fs::write(path, source).unwrap();
+        let mut engine = ScriptEngine::new();
+        engine.load(path.to_str().unwrap(), gameboy);
+        engine
+    }
+
+    #[test]
+    fn read_and_write() {
+        let mut gameboy = setup();
+        let path = script_path("read_and_write.lua");
+        let engine = load(
+            &path,
+            "emu.write(0xC100, 0x42)
+             emu.write(0xC101, 0x13)
+             value = emu.read(0xC100)
+             value16 = emu.read16(0xC100)
+             emu.on_read(0xFF00, function() end)
+             joypad = emu.read(0xFF00)",
+            &mut gameboy,
+        );
+
+        assert_eq!(global::<u8>(&engine, "value"), 0x42);
+        assert_eq!(global::<u16>(&engine, "value16"), 0x1342);
+        assert_eq!(gameboy.cpu.mmu.peek(0xC101), 0x13);
+        // Reading from a script is not a program access
+        assert!(!gameboy.cpu.mmu.io_registers.joypad_polled);
+        assert!(gameboy.cpu.mmu.hooked_accesses.is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn write_hook() {
+        let mut gameboy = setup();
+        let path = script_path("write_hook.lua");
+        let mut engine = load(
+            &path,
+            "written = 0
+             emu.on_write(0xC200, function(address, value) written = value end)",
+            &mut gameboy,
+        );
+
+        gameboy.cpu.mmu.write(0xC200, 0x99);
+        engine.after_tick(&mut gameboy);
+        assert_eq!(global::<u8>(&engine, "written"), 0x99);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn frame_callback() {
+        let mut gameboy = setup();
+        let path = script_path("frame_callback.lua");
+        let mut engine = load(
+            &path,
+            "frames = 0
+             emu.on_frame(function(frame) frames = frames + 1 end)",
+            &mut gameboy,
+        );
+
+        let start = gameboy.frame_count();
+        let mut cycles = 0;
+        while cycles < 3 * CYCLES_PER_FRAME {
+            cycles += gameboy.tick().1 as u64;
+            engine.after_tick(&mut gameboy);
+        }
+
+        let frames = gameboy.frame_count() - start;
+        assert!(frames >= 2);
+        assert_eq!(global::<u64>(&engine, "frames"), frames);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn hot_reload() {
+        let mut gameboy = setup();
+        let path = script_path("hot_reload.lua");
+        let mut engine = load(
+            &path,
+            "version = 1
+             emu.on_frame(function() end)",
+            &mut gameboy,
+        );
+        assert_eq!(global::<u8>(&engine, "version"), 1);
+        assert_eq!(engine.hooks.borrow().frame.len(), 1);
+
+        fs::write(&path, "version = 2").unwrap();
+        // The modification time may not change within the file system's resolution
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        engine.check_for_changes(&mut gameboy);
+
+        assert_eq!(global::<u8>(&engine, "version"), 2);
+        assert!(engine.hooks.borrow().frame.is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+}
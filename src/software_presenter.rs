@@ -0,0 +1,452 @@
+use crate::egui_renderer::create_egui_context;
+use crate::emulator::{EmulatorControlMessage, EmulatorState};
+use crate::ui::{create_ui, UIContext, UIState};
+use crate::vulkan_renderer::EmulatorRenderer;
+use egui::ahash::AHashMap;
+use egui::epaint::{ImageData, ImageDelta, Mesh, Primitive, Vertex};
+use egui::{ClippedPrimitive, Color32, Context, Pos2, Rect, TextureId};
+use egui_winit::State;
+use log::{log, Level};
+use softbuffer::{SoftBufferError, Surface};
+use std::num::NonZeroU32;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use winit::dpi::LogicalSize;
+use winit::event::WindowEvent;
+use winit::event_loop::ActiveEventLoop;
+use winit::window::Window;
+
+// Presentation path for systems without a usable Vulkan device. The UI is tessellated by egui as
+// usual and rasterized on the CPU, the result is blitted to the window with softbuffer. The game
+// screen comes from SoftwareRenderer, which hands it to egui as a regular texture.
+pub(crate) struct SoftwarePresenter {
+    pub(crate) ui_state: UIState,
+    ui_context: UIContext,
+    emulator_renderer: Arc<Mutex<dyn EmulatorRenderer>>,
+    textures: AHashMap<TextureId, Texture>,
+    rcx: Option<RenderContext>,
+}
+
+struct RenderContext {
+    window: Arc<Window>,
+    surface: Surface<Arc<Window>, Arc<Window>>,
+    egui_context: Context,
+    egui_winit_state: State,
+}
+
+struct Texture {
+    size: [usize; 2],
+    pixels: Vec<Color32>,
+}
+
+impl SoftwarePresenter {
+    pub(crate) fn new(
+        tx_ui: Sender<EmulatorControlMessage>,
+        emulator_renderer: Arc<Mutex<dyn EmulatorRenderer>>,
+    ) -> Self {
+        SoftwarePresenter {
+            ui_state: UIState::new(tx_ui),
+            ui_context: UIContext::new(),
+            emulator_renderer,
+            textures: AHashMap::new(),
+            rcx: None,
+        }
+    }
+
+    pub(crate) fn create_render_context(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+    ) -> Result<(), String> {
+        let window = Arc::new(
+            event_loop
+                .create_window(
+                    Window::default_attributes()
+                        .with_title("Mnemosyne")
+                        .with_inner_size(LogicalSize::new(1920.0, 1080.0)),
+                )
+                .map_err(|err| err.to_string())?,
+        );
+        let context = softbuffer::Context::new(window.clone()).map_err(|err| err.to_string())?;
+        let surface = Surface::new(&context, window.clone()).map_err(|err| err.to_string())?;
+
+        let egui_context = create_egui_context();
+        let egui_winit_state = State::new(
+            egui_context.clone(),
+            egui::viewport::ViewportId::ROOT,
+            &*window,
+            Some(window.scale_factor() as f32),
+            None,
+            Some(2 * 1024), // default dimension is 2048
+        );
+
+        self.rcx = Some(RenderContext {
+            window,
+            surface,
+            egui_context,
+            egui_winit_state,
+        });
+        Ok(())
+    }
+
+    pub(crate) fn handle_window_event(&mut self, event: &WindowEvent) {
+        if let Some(rcx) = self.rcx.as_mut() {
+            let _ = rcx.egui_winit_state.on_window_event(&rcx.window, event);
+        }
+    }
+
+    pub(crate) fn request_redraw(&mut self) {
+        if let Some(rcx) = &self.rcx {
+            rcx.window.request_redraw();
+        }
+    }
+
+    pub(crate) fn redraw(&mut self, emu_state: EmulatorState) {
+        puffin::profile_scope!("software renderer");
+        let Some(rcx) = self.rcx.as_mut() else {
+            return;
+        };
+        let window_size = rcx.window.inner_size();
+        let (Some(width), Some(height)) = (
+            NonZeroU32::new(window_size.width),
+            NonZeroU32::new(window_size.height),
+        ) else {
+            return;
+        };
+
+        let raw_input = rcx.egui_winit_state.take_egui_input(&rcx.window);
+        rcx.egui_context.begin_pass(raw_input);
+        create_ui(
+            &rcx.egui_context,
+            window_size,
+            &mut self.ui_state,
+            emu_state,
+            &mut self.ui_context,
+            self.emulator_renderer.clone(),
+        );
+        let full_output = rcx.egui_context.end_pass();
+        rcx.egui_winit_state
+            .handle_platform_output(&rcx.window, full_output.platform_output);
+
+        let pixels_per_point = egui_winit::pixels_per_point(&rcx.egui_context, &rcx.window);
+        let clipped_meshes = {
+            puffin::profile_scope!("Render UI - Tesselate");
+            rcx.egui_context
+                .tessellate(full_output.shapes, pixels_per_point)
+        };
+
+        for (id, delta) in &full_output.textures_delta.set {
+            update_texture(&mut self.textures, *id, delta);
+        }
+
+        let size = [window_size.width as usize, window_size.height as usize];
+        if let Err(err) = rcx.surface.resize(width, height).and_then(|_| {
+            present(
+                &mut rcx.surface,
+                size,
+                &clipped_meshes,
+                &self.textures,
+                pixels_per_point,
+            )
+        }) {
+            log!(Level::Error, "Failed to present frame: {}", err);
+        }
+
+        for id in &full_output.textures_delta.free {
+            self.textures.remove(id);
+        }
+    }
+}
+
+fn update_texture(textures: &mut AHashMap<TextureId, Texture>, id: TextureId, delta: &ImageDelta) {
+    let size = delta.image.size();
+    let pixels: Vec<Color32> = match &delta.image {
+        ImageData::Color(image) => image.pixels.clone(),
+        ImageData::Font(image) => image.srgba_pixels(None).collect(),
+    };
+
+    match (delta.pos, textures.get_mut(&id)) {
+        (Some([x, y]), Some(texture)) => {
+            for (row, line) in pixels.chunks_exact(size[0]).enumerate() {
+                let start = (y + row) * texture.size[0] + x;
+                texture.pixels[start..start + size[0]].copy_from_slice(line);
+            }
+        }
+        _ => {
+            textures.insert(id, Texture { size, pixels });
+        }
+    }
+}
+
+fn present(
+    surface: &mut Surface<Arc<Window>, Arc<Window>>,
+    size: [usize; 2],
+    clipped_meshes: &[ClippedPrimitive],
+    textures: &AHashMap<TextureId, Texture>,
+    pixels_per_point: f32,
+) -> Result<(), SoftBufferError> {
+    puffin::profile_scope!("Render UI - Rasterize");
+    let mut buffer = surface.buffer_mut()?;
+    buffer.fill(0x00FFFFFF);
+    for ClippedPrimitive {
+        clip_rect,
+        primitive,
+    } in clipped_meshes
+    {
+        // Paint callbacks need Vulkan, SoftwareRenderer never emits them
+        let Primitive::Mesh(mesh) = primitive else {
+            continue;
+        };
+        let Some(texture) = textures.get(&mesh.texture_id) else {
+            continue;
+        };
+        let clip_rect = Rect::from_min_max(
+            (clip_rect.min.to_vec2() * pixels_per_point).to_pos2(),
+            (clip_rect.max.to_vec2() * pixels_per_point).to_pos2(),
+        );
+        draw_mesh(
+            &mut buffer,
+            size,
+            clip_rect,
+            mesh,
+            texture,
+            pixels_per_point,
+        );
+    }
+    buffer.present()
+}
+
+// Fills every triangle of the mesh, sampling the texture with nearest filtering and blending
+// premultiplied colors the same way the egui pipeline does
+fn draw_mesh(
+    target: &mut [u32],
+    size: [usize; 2],
+    clip_rect: Rect,
+    mesh: &Mesh,
+    texture: &Texture,
+    pixels_per_point: f32,
+) {
+    let clip_min_x = clip_rect.min.x.round().clamp(0.0, size[0] as f32) as usize;
+    let clip_min_y = clip_rect.min.y.round().clamp(0.0, size[1] as f32) as usize;
+    let clip_max_x = clip_rect.max.x.round().clamp(0.0, size[0] as f32) as usize;
+    let clip_max_y = clip_rect.max.y.round().clamp(0.0, size[1] as f32) as usize;
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let mut vertices = [triangle[0], triangle[1], triangle[2]].map(|index| {
+            let vertex = mesh.vertices[index as usize];
+            Vertex {
+                pos: (vertex.pos.to_vec2() * pixels_per_point).to_pos2(),
+                ..vertex
+            }
+        });
+        let mut area = edge(vertices[0].pos, vertices[1].pos, vertices[2].pos);
+        if area == 0.0 {
+            continue;
+        }
+        if area < 0.0 {
+            vertices.swap(1, 2);
+            area = -area;
+        }
+        let [v0, v1, v2] = vertices;
+
+        let min_x = v0.pos.x.min(v1.pos.x).min(v2.pos.x).floor().max(0.0) as usize;
+        let min_y = v0.pos.y.min(v1.pos.y).min(v2.pos.y).floor().max(0.0) as usize;
+        let max_x = v0.pos.x.max(v1.pos.x).max(v2.pos.x).ceil().max(0.0) as usize;
+        let max_y = v0.pos.y.max(v1.pos.y).max(v2.pos.y).ceil().max(0.0) as usize;
+
+        for y in min_y.max(clip_min_y)..max_y.min(clip_max_y) {
+            for x in min_x.max(clip_min_x)..max_x.min(clip_max_x) {
+                let point = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let w0 = edge(v1.pos, v2.pos, point);
+                let w1 = edge(v2.pos, v0.pos, point);
+                let w2 = edge(v0.pos, v1.pos, point);
+                if !covers(w0, v1.pos, v2.pos)
+                    || !covers(w1, v2.pos, v0.pos)
+                    || !covers(w2, v0.pos, v1.pos)
+                {
+                    continue;
+                }
+                let weights = [w0 / area, w1 / area, w2 / area];
+
+                let u = weights[0] * v0.uv.x + weights[1] * v1.uv.x + weights[2] * v2.uv.x;
+                let v = weights[0] * v0.uv.y + weights[1] * v1.uv.y + weights[2] * v2.uv.y;
+                let texel_x = ((u * texture.size[0] as f32) as usize).min(texture.size[0] - 1);
+                let texel_y = ((v * texture.size[1] as f32) as usize).min(texture.size[1] - 1);
+                let texel = texture.pixels[texel_y * texture.size[0] + texel_x];
+
+                let channel = |vertex_channel: fn(&Color32) -> u8, texel_channel: u8| {
+                    let color = weights[0] * vertex_channel(&v0.color) as f32
+                        + weights[1] * vertex_channel(&v1.color) as f32
+                        + weights[2] * vertex_channel(&v2.color) as f32;
+                    color * texel_channel as f32 / 255.0
+                };
+                let source = [
+                    channel(Color32::r, texel.r()),
+                    channel(Color32::g, texel.g()),
+                    channel(Color32::b, texel.b()),
+                    channel(Color32::a, texel.a()),
+                ];
+
+                let pixel = &mut target[y * size[0] + x];
+                *pixel = blend(*pixel, source);
+            }
+        }
+    }
+}
+
+fn edge(a: Pos2, b: Pos2, point: Pos2) -> f32 {
+    (b.x - a.x) * (point.y - a.y) - (b.y - a.y) * (point.x - a.x)
+}
+
+// Pixels exactly on an edge shared by two triangles only belong to one of them, otherwise
+// translucent shapes would be blended twice along the diagonal
+fn covers(weight: f32, a: Pos2, b: Pos2) -> bool {
+    weight > 0.0 || (weight == 0.0 && (b.y > a.y || (b.y == a.y && b.x < a.x)))
+}
+
+// softbuffer pixels are 0x00RRGGBB
+fn blend(destination: u32, source: [f32; 4]) -> u32 {
+    let inverse_alpha = 1.0 - source[3] / 255.0;
+    let mix = |shift: u32, source: f32| {
+        let destination = ((destination >> shift) & 0xFF) as f32;
+        ((source + destination * inverse_alpha).round().min(255.0) as u32) << shift
+    };
+    mix(16, source[0]) | mix(8, source[1]) | mix(0, source[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::{ColorImage, TextureOptions};
+
+    const WHITE: u32 = 0x00FFFFFF;
+
+    fn quad(size: f32, color: Color32) -> Mesh {
+        let mut mesh = Mesh::with_texture(TextureId::default());
+        mesh.add_rect_with_uv(
+            Rect::from_min_max(Pos2::ZERO, Pos2::new(size, size)),
+            Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+            color,
+        );
+        mesh
+    }
+
+    #[test]
+    fn blend_premultiplied() {
+        // Half transparent, the source color is already multiplied by its alpha
+        assert_eq!(blend(0x00204060, [100.0, 50.0, 0.0, 127.5]), 0x00745230);
+        assert_eq!(blend(0x00204060, [1.0, 2.0, 3.0, 255.0]), 0x00010203);
+        assert_eq!(blend(0x00204060, [0.0, 0.0, 0.0, 0.0]), 0x00204060);
+        // Additive colors saturate
+        assert_eq!(blend(WHITE, [200.0, 200.0, 200.0, 0.0]), WHITE);
+    }
+
+    #[test]
+    fn covers_shared_edges_once() {
+        let (a, b) = (Pos2::new(0.0, 0.0), Pos2::new(0.0, 4.0));
+        assert!(covers(1.0, a, b));
+        assert!(!covers(-1.0, a, b));
+
+        // On the edge only one of the two directions owns the pixel
+        assert!(covers(0.0, a, b));
+        assert!(!covers(0.0, b, a));
+        let (a, b) = (Pos2::new(4.0, 0.0), Pos2::new(0.0, 0.0));
+        assert!(covers(0.0, a, b));
+        assert!(!covers(0.0, b, a));
+    }
+
+    #[test]
+    fn update_texture_partial() {
+        let mut textures = AHashMap::new();
+        let id = TextureId::default();
+        update_texture(
+            &mut textures,
+            id,
+            &ImageDelta::full(
+                ColorImage::new([4, 3], Color32::BLACK),
+                TextureOptions::NEAREST,
+            ),
+        );
+        update_texture(
+            &mut textures,
+            id,
+            &ImageDelta::partial(
+                [1, 1],
+                ColorImage::new([2, 2], Color32::RED),
+                TextureOptions::NEAREST,
+            ),
+        );
+
+        let texture = &textures[&id];
+        assert_eq!(texture.size, [4, 3]);
+        for y in 0..3 {
+            for x in 0..4 {
+                let expected = if (1..3).contains(&x) && (1..3).contains(&y) {
+                    Color32::RED
+                } else {
+                    Color32::BLACK
+                };
+                assert_eq!(texture.pixels[y * 4 + x], expected, "texel {x},{y}");
+            }
+        }
+    }
+
+    #[test]
+    fn draw_mesh_samples_texture() {
+        let texture = Texture {
+            size: [2, 1],
+            pixels: vec![Color32::RED, Color32::BLUE],
+        };
+        let mut target = vec![WHITE; 4 * 4];
+        let clip_rect = Rect::from_min_max(Pos2::ZERO, Pos2::new(4.0, 4.0));
+        draw_mesh(
+            &mut target,
+            [4, 4],
+            clip_rect,
+            &quad(2.0, Color32::WHITE),
+            &texture,
+            2.0,
+        );
+        for (index, pixel) in target.iter().enumerate() {
+            let expected = if index % 4 < 2 {
+                0x00FF0000
+            } else {
+                0x000000FF
+            };
+            assert_eq!(*pixel, expected, "pixel {index}");
+        }
+
+        // Only the clipped area is drawn
+        let mut target = vec![WHITE; 4 * 4];
+        let clip_rect = Rect::from_min_max(Pos2::ZERO, Pos2::new(4.0, 2.0));
+        draw_mesh(
+            &mut target,
+            [4, 4],
+            clip_rect,
+            &quad(4.0, Color32::WHITE),
+            &texture,
+            1.0,
+        );
+        assert!(target[..8].iter().all(|pixel| *pixel != WHITE));
+        assert!(target[8..].iter().all(|pixel| *pixel == WHITE));
+    }
+
+    #[test]
+    fn draw_mesh_blends_diagonal_once() {
+        let texture = Texture {
+            size: [1, 1],
+            pixels: vec![Color32::WHITE],
+        };
+        let mut target = vec![WHITE; 4 * 4];
+        let clip_rect = Rect::from_min_max(Pos2::ZERO, Pos2::new(4.0, 4.0));
+        draw_mesh(
+            &mut target,
+            [4, 4],
+            clip_rect,
+            &quad(4.0, Color32::from_rgba_premultiplied(0, 0, 0, 128)),
+            &texture,
+            1.0,
+        );
+        // Pixel centers on the diagonal between the two triangles get the same color
+        assert!(target.iter().all(|pixel| *pixel == 0x007F7F7F));
+    }
+}
@@ -1,15 +1,26 @@
 mod components;
 mod views;
 
+use crate::config::{AudioConfig, PaletteConfig, RendererBackend, THREAD_LOCAL_CONFIG};
 use crate::egui_renderer::CallbackFn;
 use crate::emulator::{EmulatorControlMessage, EmulatorState};
 use crate::gb::breakpoints::Breakpoints;
 use crate::gb::disassembler::{Address, Disassembler};
+use crate::gb::events::Event;
+use crate::gb::filters::FilterSettings;
+use crate::gb::ppu::LayerVisibility;
+use crate::gb::ram_search::{Comparison, SearchFormat, Watch};
 use crate::gb::registers::Flag;
+use crate::gb::screenshot::ScreenshotMode;
+use crate::gb::video::VideoFormat;
+use crate::ui::components::vram_viewer::{TilePalette, VramTab};
 use crate::vulkan_renderer::EmulatorRenderer;
 use egui::text::{LayoutJob, LayoutSection};
-use egui::{menu, vec2, Align, Context, PaintCallback, Rgba, Sense, TextFormat, TextStyle};
+use egui::{
+    menu, vec2, Align, Context, PaintCallback, Rgba, Sense, TextFormat, TextStyle, TextureHandle,
+};
 use egui_extras::{Column, TableBuilder};
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::path::Path;
 use std::sync::mpsc::Sender;
@@ -30,6 +41,7 @@ enum Views {
     Fullscreen,
     Debugger,
     GameList,
+    Settings,
 }
 
 #[derive(Clone)]
@@ -38,33 +50,78 @@ pub struct UIState {
     pub(crate) emulator_should_step: bool,
     pub(crate) breakpoints: Breakpoints,
     pub(crate) selected_memory: Memories,
+    pub(crate) show_ram_search: bool,
+    pub(crate) ram_search_format: SearchFormat,
+    pub(crate) watch_list: Vec<Watch>,
+    pub(crate) show_vram_viewer: bool,
+    pub(crate) inspect_pixels: bool,
+    pub(crate) show_event_viewer: bool,
+    pub(crate) show_audio_debugger: bool,
+    show_gbs_player: bool,
+    channel_mute: [bool; 4],
+    channel_solo: [bool; 4],
+    record_stems: bool,
+    video_format: VideoFormat,
+    video_filters: bool,
+    video_audio: bool,
+    pub(crate) layer_visibility: LayerVisibility,
     bottom_panel: BottomPanels,
-    volume: f32,
+    pub(crate) volume: f32,
     current_view: Views,
     game_list: Vec<String>,
     selected_game: String,
     search_string: String,
+    show_cheats: bool,
+    show_io_registers: bool,
+    show_layers: bool,
+    selected_event: Option<Event>,
+    cheat_name: String,
+    cheat_code: String,
+    ram_search_comparison: Comparison,
+    ram_search_value: String,
+    ram_search_use_value: bool,
     tx_ui: Sender<EmulatorControlMessage>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub(crate) enum Memories {
-    ExternalRAM,
-    WRAM1,
-    WRAM2,
-    TileData,
-    BackgroundMaps,
-    OAM,
-    HRAM,
-    IO,
-}
-
 pub(crate) struct UIContext {
     ts: ThemeSet,
     ps: SyntaxSet,
     previous_pc: u16,
     disassembler: Disassembler,
     disassembly: Vec<(Option<Address>, String)>,
+    memory_view: MemoryViewState,
+    vram_view: VramViewState,
+    settings: SettingsState,
+}
+
+// Memory viewer state that stays on the UI thread
+pub(crate) struct MemoryViewState {
+    memory: Memories,
+    previous: Vec<u8>,
+    changed: Vec<u8>,
+    goto: String,
+    search: String,
+    search_match: Option<(usize, usize)>,
+    scroll_to: Option<usize>,
+    editing: Option<(usize, String)>,
+}
+
+// VRAM viewer state, textures are reused between frames
+pub(crate) struct VramViewState {
+    tab: VramTab,
+    palette: TilePalette,
+    map: usize,
+    textures: HashMap<String, TextureHandle>,
+}
+
+// Settings are edited on a copy and only written to the config when applied
+pub(crate) struct SettingsState {
+    audio: AudioConfig,
+    audio_devices: Option<Vec<String>>,
+    palette: PaletteConfig,
+    filters: FilterSettings,
+    screenshot_mode: ScreenshotMode,
+    renderer: RendererBackend,
 }
 
 impl UIContext {
@@ -90,6 +147,31 @@ impl UIContext {
             previous_pc: 0x00FF,
             disassembler,
             disassembly: table,
+            memory_view: MemoryViewState {
+                memory: Memories::WRAM1,
+                previous: Vec::new(),
+                changed: Vec::new(),
+                goto: String::new(),
+                search: String::new(),
+                search_match: None,
+                scroll_to: None,
+                editing: None,
+            },
+            vram_view: VramViewState {
+                tab: VramTab::Tiles,
+                palette: TilePalette::BGP,
+                map: 0,
+                textures: HashMap::new(),
+            },
+            settings: SettingsState {
+                audio: THREAD_LOCAL_CONFIG.with(|c| c.borrow_mut().load().audio_config.clone()),
+                audio_devices: None,
+                palette: THREAD_LOCAL_CONFIG.with(|c| c.borrow_mut().load().palette_config.clone()),
+                filters: THREAD_LOCAL_CONFIG.with(|c| c.borrow_mut().load().filter_config),
+                screenshot_mode: THREAD_LOCAL_CONFIG
+                    .with(|c| c.borrow_mut().load().screenshot_mode),
+                renderer: THREAD_LOCAL_CONFIG.with(|c| c.borrow_mut().load().renderer),
+            },
         }
     }
 }
@@ -101,16 +183,49 @@ impl UIState {
             emulator_should_step: false,
             breakpoints: Breakpoints::new(),
             selected_memory: Memories::WRAM1,
+            show_ram_search: false,
+            ram_search_format: SearchFormat::default(),
+            watch_list: Vec::new(),
+            show_vram_viewer: false,
+            inspect_pixels: false,
+            show_event_viewer: false,
+            show_audio_debugger: false,
+            show_gbs_player: false,
+            channel_mute: [false; 4],
+            channel_solo: [false; 4],
+            record_stems: false,
+            video_format: VideoFormat::Apng,
+            video_filters: false,
+            video_audio: true,
+            layer_visibility: LayerVisibility::default(),
             bottom_panel: BottomPanels::Logger,
             volume: 50.0,
             current_view: Views::GameList,
             game_list: Vec::new(),
             selected_game: String::new(),
             search_string: "Search".to_string(),
+            show_cheats: false,
+            show_io_registers: false,
+            show_layers: false,
+            selected_event: None,
+            cheat_name: String::new(),
+            cheat_code: String::new(),
+            ram_search_comparison: Comparison::NotEqual,
+            ram_search_value: String::new(),
+            ram_search_use_value: false,
             tx_ui,
         }
     }
 
+    // Soloed channels take precedence over muted ones
+    pub(crate) fn audio_channels_enabled(&self) -> [bool; 4] {
+        if self.channel_solo.iter().any(|solo| *solo) {
+            self.channel_solo
+        } else {
+            self.channel_mute.map(|mute| !mute)
+        }
+    }
+
     fn toggle_row_selection(&mut self, address: Option<Address>, row_response: &egui::Response) {
         if address.is_some() && row_response.clicked() {
             if self
@@ -140,6 +255,14 @@ pub(crate) fn create_ui(
 ) {
     puffin::profile_scope!("Create UI");
 
+    // Hotkeys
+    if egui_context.input(|i| i.key_pressed(egui::Key::F12)) {
+        ui_state
+            .tx_ui
+            .send(EmulatorControlMessage::TakeScreenshot)
+            .expect("Failed to send control message to emulator thread");
+    }
+
     match ui_state.current_view {
         Views::Debugger => {
             views::debugger::render(
@@ -169,7 +292,75 @@ pub(crate) fn create_ui(
                 emulator_renderer,
             );
         }
+        Views::Settings => {
+            views::settings::render(egui_context, ui_state, &emu_state, ui_context);
+        }
     }
+
+    // Tool windows
+    let mut show_cheats = ui_state.show_cheats;
+    egui::Window::new("Cheats")
+        .open(&mut show_cheats)
+        .show(egui_context, |ui| {
+            components::cheats::render(ui, egui_context, ui_state, &emu_state);
+        });
+    ui_state.show_cheats = show_cheats;
+
+    let mut show_ram_search = ui_state.show_ram_search;
+    egui::Window::new("RAM search")
+        .open(&mut show_ram_search)
+        .show(egui_context, |ui| {
+            components::ram_search::render(ui, egui_context, ui_state, &emu_state);
+        });
+    ui_state.show_ram_search = show_ram_search;
+
+    let mut show_io_registers = ui_state.show_io_registers;
+    egui::Window::new("IO registers")
+        .open(&mut show_io_registers)
+        .show(egui_context, |ui| {
+            components::io_registers::render(ui, egui_context, ui_state, &emu_state);
+        });
+    ui_state.show_io_registers = show_io_registers;
+
+    let mut show_vram_viewer = ui_state.show_vram_viewer;
+    egui::Window::new("VRAM viewer")
+        .open(&mut show_vram_viewer)
+        .show(egui_context, |ui| {
+            components::vram_viewer::render(ui, ui_context, egui_context, &emu_state);
+        });
+    ui_state.show_vram_viewer = show_vram_viewer;
+
+    let mut show_layers = ui_state.show_layers;
+    egui::Window::new("Layers")
+        .open(&mut show_layers)
+        .show(egui_context, |ui| {
+            components::layers::render(ui, egui_context, ui_state, &emu_state);
+        });
+    ui_state.show_layers = show_layers;
+
+    let mut show_event_viewer = ui_state.show_event_viewer;
+    egui::Window::new("Event viewer")
+        .open(&mut show_event_viewer)
+        .show(egui_context, |ui| {
+            components::event_viewer::render(ui, ui_context, egui_context, ui_state, &emu_state);
+        });
+    ui_state.show_event_viewer = show_event_viewer;
+
+    let mut show_audio_debugger = ui_state.show_audio_debugger;
+    egui::Window::new("Audio debugger")
+        .open(&mut show_audio_debugger)
+        .show(egui_context, |ui| {
+            components::audio_debugger::render(ui, egui_context, ui_state, &emu_state);
+        });
+    ui_state.show_audio_debugger = show_audio_debugger;
+
+    let mut show_gbs_player = ui_state.show_gbs_player;
+    egui::Window::new("GBS player")
+        .open(&mut show_gbs_player)
+        .show(egui_context, |ui| {
+            components::gbs_player::render(ui, egui_context, ui_state, &emu_state);
+        });
+    ui_state.show_gbs_player = show_gbs_player;
 }
 
 fn as_byte_range(whole: &str, range: &str) -> std::ops::Range<usize> {
@@ -1,6 +1,14 @@
+pub(crate) mod audio_debugger;
 pub(crate) mod breakpoints;
+pub(crate) mod cheats;
 pub(crate) mod disassembly;
+pub(crate) mod event_viewer;
 pub(crate) mod game_screen;
+pub(crate) mod gbs_player;
+pub(crate) mod io_registers;
+pub(crate) mod layers;
 pub(crate) mod memory_viewer;
 pub(crate) mod menu_bar;
+pub(crate) mod ram_search;
 pub(crate) mod register_viewer;
+pub(crate) mod vram_viewer;
@@ -0,0 +1,145 @@
+use crate::emulator::EmulatorState;
+use crate::gb::apu::ChannelStatus;
+use crate::ui::UIState;
+use egui::{pos2, vec2, Color32, Context, Sense, Shape, Stroke, Ui};
+
+const CHANNEL_NAMES: [&str; 4] = ["Pulse 1", "Pulse 2", "Wave", "Noise"];
+const DUTY_CYCLES: [&str; 4] = ["12.5%", "25%", "50%", "75%"];
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+pub(crate) fn render(
+    ui: &mut Ui,
+    egui_context: &Context,
+    ui_state: &mut UIState,
+    emu_state: &EmulatorState,
+) {
+    puffin::profile_scope!("UI - Audio debugger");
+    match emu_state {
+        EmulatorState::GameBoy(emu_state) => {
+            let audio = &emu_state.audio;
+            egui::Grid::new("audio_channels")
+                .num_columns(3)
+                .spacing(vec2(12.0, 8.0))
+                .show(ui, |ui| {
+                    for (index, channel) in audio.channels.iter().enumerate() {
+                        ui.vertical(|ui| {
+                            ui.strong(CHANNEL_NAMES[index]);
+                            ui.horizontal(|ui| {
+                                ui.toggle_value(&mut ui_state.channel_mute[index], "M")
+                                    .on_hover_text("Mute");
+                                ui.toggle_value(&mut ui_state.channel_solo[index], "S")
+                                    .on_hover_text("Solo");
+                            });
+                        });
+                        oscilloscope(ui, &audio.scope[index], channel.on);
+                        channel_info(ui, index, channel);
+                        ui.end_row();
+                    }
+
+                    ui.strong("Mix");
+                    oscilloscope(ui, &audio.scope[4], true);
+                    ui.end_row();
+                });
+        }
+    }
+}
+
+fn channel_info(ui: &mut Ui, index: usize, channel: &ChannelStatus) {
+    ui.vertical(|ui| {
+        if !channel.on {
+            ui.weak("Off");
+            return;
+        }
+
+        // The noise channel has a clock rate instead of a pitch
+        if index == 3 {
+            ui.label(format!("{:.0} Hz", channel.frequency));
+        } else {
+            ui.label(format!(
+                "{:.1} Hz  {}",
+                channel.frequency,
+                note_name(channel.frequency)
+            ));
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Volume");
+            ui.add(
+                egui::ProgressBar::new(channel.volume as f32 / 15.0)
+                    .desired_width(80.0)
+                    .text(channel.volume.to_string()),
+            );
+        });
+
+        if index != 2 {
+            let envelope = if channel.envelope_pace == 0 {
+                "Envelope off".to_string()
+            } else {
+                format!(
+                    "Envelope {} every {}",
+                    if channel.envelope_increasing {
+                        "up"
+                    } else {
+                        "down"
+                    },
+                    channel.envelope_pace
+                )
+            };
+            ui.label(envelope);
+        }
+
+        if let Some(duty) = channel.duty {
+            ui.label(format!("Duty {}", DUTY_CYCLES[duty as usize]));
+        }
+    });
+}
+
+fn oscilloscope(ui: &mut Ui, samples: &[f32], active: bool) {
+    let (rect, _) = ui.allocate_exact_size(vec2(256.0, 64.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, Color32::from_gray(0x20));
+    painter.line_segment(
+        [rect.left_center(), rect.right_center()],
+        Stroke::new(1.0, Color32::from_gray(0x40)),
+    );
+
+    if samples.len() < 2 {
+        return;
+    }
+
+    let color = if active {
+        Color32::from_rgb(0x60, 0xE0, 0x90)
+    } else {
+        Color32::from_gray(0x80)
+    };
+    let points = samples
+        .iter()
+        .enumerate()
+        .map(|(index, sample)| {
+            pos2(
+                rect.left() + index as f32 / (samples.len() - 1) as f32 * rect.width(),
+                rect.center().y - sample.clamp(-1.0, 1.0) * rect.height() / 2.0,
+            )
+        })
+        .collect();
+    painter.add(Shape::line(points, Stroke::new(1.0, color)));
+}
+
+fn note_name(frequency: f32) -> String {
+    if frequency <= 0.0 {
+        return String::new();
+    }
+
+    let midi = 69.0 + 12.0 * (frequency / 440.0).log2();
+    let note = midi.round();
+    let cents = ((midi - note) * 100.0).round() as i32;
+    let note = note as i32;
+    format!(
+        "{}{} {:+}c",
+        NOTE_NAMES[note.rem_euclid(12) as usize],
+        note.div_euclid(12) - 1,
+        cents
+    )
+}
@@ -0,0 +1,96 @@
+use crate::emulator::{EmulatorControlMessage, EmulatorState};
+use crate::gb::cheats::{Cheat, CheatCode};
+use crate::ui::UIState;
+use egui::{Color32, Context, Ui};
+use egui_extras::{Column, TableBuilder};
+
+pub(crate) fn render(
+    ui: &mut Ui,
+    egui_context: &Context,
+    ui_state: &mut UIState,
+    emu_state: &EmulatorState,
+) {
+    puffin::profile_scope!("UI - Cheats");
+    match emu_state {
+        EmulatorState::GameBoy(emu_state) => {
+            let cheat_table = TableBuilder::new(ui)
+                .id_salt("cheats")
+                .striped(true)
+                .resizable(false)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Column::auto())
+                .column(Column::remainder())
+                .column(Column::auto())
+                .column(Column::auto());
+
+            cheat_table.body(|mut body| {
+                for (index, cheat) in emu_state.cheats.iter().enumerate() {
+                    body.row(20.0, |mut row| {
+                        row.col(|ui| {
+                            let mut enabled = cheat.enabled;
+                            if ui.checkbox(&mut enabled, "").changed() {
+                                ui_state
+                                    .tx_ui
+                                    .send(EmulatorControlMessage::ToggleCheat(index, enabled))
+                                    .expect("Failed to send control message to emulator thread");
+                            }
+                        });
+                        row.col(|ui| {
+                            ui.label(&cheat.name);
+                        });
+                        row.col(|ui| {
+                            ui.monospace(&cheat.code);
+                        });
+                        row.col(|ui| {
+                            if ui.button("Remove").clicked() {
+                                ui_state
+                                    .tx_ui
+                                    .send(EmulatorControlMessage::RemoveCheat(index))
+                                    .expect("Failed to send control message to emulator thread");
+                            }
+                        });
+                    });
+                }
+            });
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.label("Name");
+                ui.text_edit_singleline(&mut ui_state.cheat_name);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Code");
+                ui.text_edit_singleline(&mut ui_state.cheat_code);
+            });
+
+            // Game Genie, GameShark or raw address:value / bank:address:value
+            let decoded = CheatCode::decode(&ui_state.cheat_code);
+            match &decoded {
+                Ok(code) => {
+                    ui.label(format!("{:X?}", code));
+                }
+                Err(err) if !ui_state.cheat_code.is_empty() => {
+                    ui.colored_label(Color32::RED, err);
+                }
+                Err(_) => {}
+            }
+
+            if ui
+                .add_enabled(decoded.is_ok(), egui::Button::new("Add cheat"))
+                .clicked()
+            {
+                ui_state
+                    .tx_ui
+                    .send(EmulatorControlMessage::AddCheat(Cheat {
+                        name: ui_state.cheat_name.clone(),
+                        code: ui_state.cheat_code.trim().to_uppercase(),
+                        enabled: true,
+                    }))
+                    .expect("Failed to send control message to emulator thread");
+                ui_state.cheat_name.clear();
+                ui_state.cheat_code.clear();
+            }
+        }
+    }
+}
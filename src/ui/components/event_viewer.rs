@@ -0,0 +1,175 @@
+use crate::emulator::EmulatorState;
+use crate::gb::events::{Event, EventKind};
+use crate::ui::{UIContext, UIState};
+use egui::{pos2, vec2, Color32, Context, Rect, Sense, Stroke, StrokeKind, Ui};
+
+const DOTS: f32 = 456.0;
+const LINES: f32 = 154.0;
+const SCALE: f32 = 2.0;
+
+pub(crate) fn render(
+    ui: &mut Ui,
+    ui_context: &mut UIContext,
+    egui_context: &Context,
+    ui_state: &mut UIState,
+    emu_state: &EmulatorState,
+) {
+    puffin::profile_scope!("UI - Event viewer");
+    match emu_state {
+        EmulatorState::GameBoy(emu_state) => {
+            ui.horizontal(|ui| {
+                legend(ui, Color32::from_rgb(0x40, 0x90, 0xFF), "Register write");
+                legend(ui, Color32::from_rgb(0xFF, 0x50, 0x50), "Interrupt");
+                legend(ui, Color32::from_rgb(0x50, 0xD0, 0x50), "OAM DMA");
+                ui.label(format!("{} events", emu_state.events.len()));
+            });
+
+            let (rect, response) =
+                ui.allocate_exact_size(vec2(DOTS, LINES) * SCALE, Sense::click());
+            let painter = ui.painter_at(rect);
+            let to_screen = |dot: f32, line: f32| rect.min + vec2(dot, line) * SCALE;
+
+            // PPU modes: OAM scan, drawing (approximate, the length varies), HBlank and VBlank
+            painter.rect_filled(rect, 0.0, Color32::from_gray(0x30));
+            painter.rect_filled(
+                Rect::from_min_max(to_screen(0.0, 0.0), to_screen(80.0, 144.0)),
+                0.0,
+                Color32::from_rgb(0x40, 0x38, 0x28),
+            );
+            painter.rect_filled(
+                Rect::from_min_max(to_screen(80.0, 0.0), to_screen(252.0, 144.0)),
+                0.0,
+                Color32::from_rgb(0x28, 0x38, 0x28),
+            );
+            painter.rect_filled(
+                Rect::from_min_max(to_screen(0.0, 144.0), to_screen(DOTS, LINES)),
+                0.0,
+                Color32::from_rgb(0x28, 0x28, 0x40),
+            );
+
+            for event in &emu_state.events {
+                let center = to_screen(event.dot as f32 + 0.5, event.ly as f32 + 0.5);
+                painter.rect_filled(
+                    Rect::from_center_size(center, vec2(SCALE, SCALE)),
+                    0.0,
+                    event_color(event),
+                );
+            }
+
+            if let Some(selected) = &ui_state.selected_event {
+                let center = to_screen(selected.dot as f32 + 0.5, selected.ly as f32 + 0.5);
+                painter.rect_stroke(
+                    Rect::from_center_size(center, vec2(4.0, 4.0) * SCALE),
+                    0.0,
+                    Stroke::new(1.0, Color32::WHITE),
+                    StrokeKind::Outside,
+                );
+            }
+
+            if let Some(pointer) = response.hover_pos() {
+                let dot = ((pointer.x - rect.left()) / SCALE) as u16;
+                let line = ((pointer.y - rect.top()) / SCALE) as u8;
+                if response.clicked() {
+                    ui_state.selected_event = nearest_event(&emu_state.events, dot, line);
+                }
+                response.on_hover_text_at_pointer(format!("LY {}, dot {}", line, dot));
+            }
+
+            ui.separator();
+            match &ui_state.selected_event {
+                Some(event) => {
+                    let symbol = ui_context
+                        .disassembler
+                        .symbol_for(event.pc)
+                        .unwrap_or_else(|| "-".to_string());
+                    egui::Grid::new("selected_event").show(ui, |ui| {
+                        ui.label("Event");
+                        ui.label(describe(event));
+                        ui.end_row();
+                        ui.label("Position");
+                        ui.label(format!("LY {}, dot {}", event.ly, event.dot));
+                        ui.end_row();
+                        ui.label("PC");
+                        ui.label(format!("{:04X}", event.pc));
+                        ui.end_row();
+                        ui.label("Symbol");
+                        ui.label(symbol);
+                        ui.end_row();
+                    });
+                }
+                None => {
+                    ui.label("Click a marker to inspect it");
+                }
+            }
+        }
+    }
+}
+
+fn legend(ui: &mut Ui, color: Color32, text: &str) {
+    let (rect, _) = ui.allocate_exact_size(vec2(10.0, 10.0), Sense::hover());
+    ui.painter().rect_filled(rect, 0.0, color);
+    ui.label(text);
+}
+
+fn event_color(event: &Event) -> Color32 {
+    match event.kind {
+        EventKind::RegisterWrite { .. } => Color32::from_rgb(0x40, 0x90, 0xFF),
+        EventKind::Interrupt { .. } => Color32::from_rgb(0xFF, 0x50, 0x50),
+        EventKind::OamDma { .. } => Color32::from_rgb(0x50, 0xD0, 0x50),
+    }
+}
+
+// Picks the closest marker within a few dots of the click
+fn nearest_event(events: &[Event], dot: u16, line: u8) -> Option<Event> {
+    let position = pos2(dot as f32, line as f32);
+    events
+        .iter()
+        .map(|event| {
+            let distance = position.distance(pos2(event.dot as f32, event.ly as f32));
+            (distance, event)
+        })
+        .filter(|(distance, _)| *distance <= 4.0)
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, event)| *event)
+}
+
+fn describe(event: &Event) -> String {
+    match event.kind {
+        EventKind::RegisterWrite { address, value } => {
+            format!(
+                "{} ({:04X}) = {:02X}",
+                register_name(address),
+                address,
+                value
+            )
+        }
+        EventKind::Interrupt { vector } => {
+            let name = match vector {
+                0x40 => "VBlank",
+                0x48 => "STAT",
+                0x50 => "Timer",
+                0x58 => "Serial",
+                0x60 => "Joypad",
+                _ => "Cancelled",
+            };
+            format!("{} interrupt ({:04X})", name, vector)
+        }
+        EventKind::OamDma { source } => format!("OAM DMA from {:04X}", source),
+    }
+}
+
+fn register_name(address: u16) -> &'static str {
+    match address {
+        0xFF40 => "LCDC",
+        0xFF41 => "STAT",
+        0xFF42 => "SCY",
+        0xFF43 => "SCX",
+        0xFF45 => "LYC",
+        0xFF47 => "BGP",
+        0xFF48 => "OBP0",
+        0xFF49 => "OBP1",
+        0xFF4A => "WY",
+        0xFF4B => "WX",
+        _ => "IO",
+    }
+}
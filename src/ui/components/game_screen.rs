@@ -1,8 +1,13 @@
 use crate::egui_renderer::CallbackFn;
 use crate::emulator::EmulatorState;
+use crate::gb::ppu::{DmgPalette, Layer, PixelSource};
+use crate::scripting::OverlayShape;
 use crate::ui::UIState;
 use crate::vulkan_renderer::EmulatorRenderer;
-use egui::{vec2, Context, PaintCallback, Rgba, Sense, Ui};
+use egui::{
+    pos2, vec2, Align2, Color32, Context, FontId, PaintCallback, Pos2, Rect, Rgba, Sense, Stroke,
+    StrokeKind, Ui,
+};
 use std::sync::{Arc, Mutex};
 
 pub(crate) fn render(
@@ -17,22 +22,151 @@ pub(crate) fn render(
         .show(ui, |ui| {
             puffin::profile_scope!("UI - Emulator renderer");
             // Allocate all the space in the frame for the image
-            let (rect, _) = ui.allocate_exact_size(
+            let (rect, response) = ui.allocate_exact_size(
                 vec2(ui.available_width(), ui.available_height()),
-                Sense::empty(),
+                Sense::hover(),
             );
 
             // Render the scene in the allocated space
-            let paint_callback = PaintCallback {
-                rect,
-                callback: Arc::new(CallbackFn::new(move |info, context| {
-                    let emu_renderer = emulator_renderer
-                        .lock()
-                        .expect("Failed to lock emulator renderer");
-                    emu_renderer.render(info, context);
-                })),
-            };
-
-            ui.painter().add(paint_callback);
+            let texture = emulator_renderer
+                .lock()
+                .expect("Failed to lock emulator renderer")
+                .texture(egui_context);
+            match texture {
+                Some(texture) => {
+                    ui.painter().image(
+                        texture,
+                        screen_rect(rect),
+                        Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+                        Color32::WHITE,
+                    );
+                }
+                None => {
+                    let paint_callback = PaintCallback {
+                        rect,
+                        callback: Arc::new(CallbackFn::new(move |info, context| {
+                            let emu_renderer = emulator_renderer
+                                .lock()
+                                .expect("Failed to lock emulator renderer");
+                            emu_renderer.render(info, context);
+                        })),
+                    };
+
+                    ui.painter().add(paint_callback);
+                }
+            }
+
+            if let EmulatorState::GameBoy(emu_state) = emu_state {
+                draw_overlay(ui, screen_rect(rect), &emu_state.overlay);
+                if emu_state.pixel_provenance.len() == 160 * 144 {
+                    inspect_pixel(response, screen_rect(rect), &emu_state.pixel_provenance);
+                }
+            }
         });
 }
+
+// Matches the placement of the game screen done by the emulator renderer
+fn screen_rect(rect: Rect) -> Rect {
+    let mut width = rect.width();
+    let mut height = width * (9.0 / 10.0);
+
+    if height > rect.height() {
+        height = rect.height();
+        width = height * (10.0 / 9.0);
+    }
+
+    Rect::from_min_size(
+        pos2(rect.center().x - width / 2.0, rect.top()),
+        vec2(width, height),
+    )
+}
+
+fn inspect_pixel(response: egui::Response, screen: Rect, provenance: &[PixelSource]) {
+    let Some(pointer) = response.hover_pos() else {
+        return;
+    };
+    if !screen.contains(pointer) {
+        return;
+    }
+
+    let scale = screen.width() / 160.0;
+    let x = (((pointer.x - screen.left()) / scale) as usize).min(159);
+    let y = (((pointer.y - screen.top()) / scale) as usize).min(143);
+    let source = provenance[y * 160 + x];
+
+    let layer = match source.layer {
+        Layer::Blank => "Blank".to_string(),
+        Layer::Background => "Background".to_string(),
+        Layer::Window => "Window".to_string(),
+        Layer::Sprite { oam_index } => format!("Sprite (OAM #{})", oam_index),
+    };
+    let palette = match source.palette {
+        DmgPalette::BGP => "BGP",
+        DmgPalette::OBP0 => "OBP0",
+        DmgPalette::OBP1 => "OBP1",
+    };
+
+    response.on_hover_text_at_pointer(if source.layer == Layer::Blank {
+        format!("Pixel ({}, {})\n{}", x, y, layer)
+    } else {
+        format!(
+            "Pixel ({}, {})\n{}\nTile {:#04X} at {:#06X}\nPalette {}\nColor ID {}",
+            x, y, layer, source.tile_index, source.tile_address, palette, source.color_id
+        )
+    });
+}
+
+fn draw_overlay(ui: &mut Ui, screen: Rect, overlay: &[OverlayShape]) {
+    let painter = ui.painter_at(screen);
+    let scale = screen.width() / 160.0;
+    let to_screen = |x: f32, y: f32| -> Pos2 { screen.min + vec2(x, y) * scale };
+    let to_color = |color: &[u8; 4]| -> Color32 {
+        Color32::from_rgba_unmultiplied(color[0], color[1], color[2], color[3])
+    };
+
+    for shape in overlay {
+        match shape {
+            OverlayShape::Text { x, y, text, color } => {
+                painter.text(
+                    to_screen(*x, *y),
+                    Align2::LEFT_TOP,
+                    text,
+                    FontId::monospace(8.0 * scale),
+                    to_color(color),
+                );
+            }
+            OverlayShape::Rect {
+                x,
+                y,
+                width,
+                height,
+                color,
+                filled,
+            } => {
+                let rect = Rect::from_min_max(to_screen(*x, *y), to_screen(x + width, y + height));
+                if *filled {
+                    painter.rect_filled(rect, 0.0, to_color(color));
+                } else {
+                    painter.rect_stroke(
+                        rect,
+                        0.0,
+                        Stroke::new(scale, to_color(color)),
+                        StrokeKind::Inside,
+                    );
+                }
+            }
+            OverlayShape::Line {
+                x1,
+                y1,
+                x2,
+                y2,
+                color,
+            } => {
+                painter.line_segment(
+                    [to_screen(*x1, *y1), to_screen(*x2, *y2)],
+                    Stroke::new(scale, to_color(color)),
+                );
+            }
+        }
+    }
+}
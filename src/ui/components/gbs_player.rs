@@ -0,0 +1,70 @@
+use crate::emulator::{EmulatorControlMessage, EmulatorState};
+use crate::ui::UIState;
+use egui::{Context, RichText, Ui};
+
+pub(crate) fn render(
+    ui: &mut Ui,
+    egui_context: &Context,
+    ui_state: &mut UIState,
+    emu_state: &EmulatorState,
+) {
+    puffin::profile_scope!("UI - GBS player");
+    match emu_state {
+        EmulatorState::GameBoy(emu_state) => {
+            let Some(player) = &emu_state.gbs_player else {
+                ui.label("No GBS file loaded");
+                return;
+            };
+
+            ui.label(RichText::new(&player.title).strong().size(16.0));
+            ui.label(&player.author);
+            ui.weak(&player.copyright);
+            ui.separator();
+
+            let mut track = player.track;
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        player.track > 0,
+                        egui::Button::new(egui_material_icons::icons::ICON_SKIP_PREVIOUS),
+                    )
+                    .clicked()
+                {
+                    track = player.track - 1;
+                }
+
+                egui::ComboBox::from_id_salt("gbs_track")
+                    .selected_text(format!(
+                        "Track {} / {}",
+                        player.track + 1,
+                        player.song_count
+                    ))
+                    .show_ui(ui, |ui| {
+                        for index in 0..player.song_count {
+                            ui.selectable_value(&mut track, index, format!("Track {}", index + 1));
+                        }
+                    });
+
+                if ui
+                    .add_enabled(
+                        player.track + 1 < player.song_count,
+                        egui::Button::new(egui_material_icons::icons::ICON_SKIP_NEXT),
+                    )
+                    .clicked()
+                {
+                    track = player.track + 1;
+                }
+            });
+
+            let elapsed = player.elapsed as u64;
+            ui.label(format!("{:02}:{:02}", elapsed / 60, elapsed % 60));
+
+            if track != player.track {
+                ui_state
+                    .tx_ui
+                    .send(EmulatorControlMessage::PlayGbsTrack(track))
+                    .expect("Failed to send control message to emulator thread");
+            }
+        }
+    }
+}
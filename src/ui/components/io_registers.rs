@@ -0,0 +1,512 @@
+use crate::emulator::{EmulatorControlMessage, EmulatorState};
+use crate::gb::apu::registers::{
+    PeriodHighControl, PulseTimerDutyCycle, VolumeEnvelope, NR10, NR30, NR32, NR41, NR43, NR44,
+    NR50, NR51, NR52,
+};
+use crate::gb::ppu::registers::{LCDC, STAT};
+use crate::gb::Memories;
+use crate::ui::UIState;
+use arbitrary_int::{u2, u3, u4, u6};
+use egui::{vec2, Color32, Context, Sense, Ui};
+
+pub(crate) fn render(
+    ui: &mut Ui,
+    egui_context: &Context,
+    ui_state: &mut UIState,
+    emu_state: &EmulatorState,
+) {
+    puffin::profile_scope!("UI - IO registers");
+    match emu_state {
+        EmulatorState::GameBoy(emu_state) => {
+            if emu_state.io_registers.len() != 0x100 {
+                return;
+            }
+            let io = &emu_state.io_registers;
+            let mut writes: Vec<(u16, u8)> = Vec::new();
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::CollapsingHeader::new("PPU")
+                    .default_open(true)
+                    .show(ui, |ui| render_ppu(ui, io, &mut writes));
+                egui::CollapsingHeader::new("Timer")
+                    .default_open(true)
+                    .show(ui, |ui| render_timer(ui, io, &mut writes));
+                egui::CollapsingHeader::new("Interrupts")
+                    .default_open(true)
+                    .show(ui, |ui| render_interrupts(ui, io, &mut writes));
+                egui::CollapsingHeader::new("Joypad")
+                    .default_open(true)
+                    .show(ui, |ui| render_joypad(ui, io, &mut writes));
+                egui::CollapsingHeader::new("APU")
+                    .default_open(false)
+                    .show(ui, |ui| render_apu(ui, io, &mut writes));
+            });
+
+            for (address, value) in writes {
+                ui_state
+                    .tx_ui
+                    .send(EmulatorControlMessage::WriteMemory(
+                        Memories::Bus,
+                        address as usize,
+                        value,
+                    ))
+                    .expect("Failed to send control message to emulator thread");
+            }
+        }
+    }
+}
+
+fn render_ppu(ui: &mut Ui, io: &[u8], writes: &mut Vec<(u16, u8)>) {
+    let mut lcdc = LCDC::new_with_raw_value(io[0x40]);
+    ui.label(format!("LCDC ({:02X})", io[0x40]));
+    ui.indent("lcdc", |ui| {
+        lcdc = lcdc.with_lcd_ppu_enable(flag(ui, lcdc.lcd_ppu_enable(), "LCD & PPU enable"));
+        lcdc = lcdc.with_window_tile_map(flag(ui, lcdc.window_tile_map(), "Window tile map 9C00"));
+        lcdc = lcdc.with_window_enable(flag(ui, lcdc.window_enable(), "Window enable"));
+        lcdc = lcdc.with_tile_addressing_mode(flag(
+            ui,
+            lcdc.tile_addressing_mode(),
+            "BG & window tiles at 8000",
+        ));
+        lcdc = lcdc.with_bg_tile_map(flag(ui, lcdc.bg_tile_map(), "BG tile map 9C00"));
+        lcdc = lcdc.with_obj_size(flag(ui, lcdc.obj_size(), "8x16 objects"));
+        lcdc = lcdc.with_obj_enable(flag(ui, lcdc.obj_enable(), "Objects enable"));
+        lcdc = lcdc.with_bg_window_enable_priority(flag(
+            ui,
+            lcdc.bg_window_enable_priority(),
+            "BG & window enable",
+        ));
+    });
+    write_if_changed(writes, 0xFF40, io[0x40], lcdc.raw_value());
+
+    let mut stat = STAT::new_with_raw_value(io[0x41]);
+    ui.label(format!(
+        "STAT ({:02X}), mode {}: {}",
+        io[0x41],
+        stat.ppu_mode().value(),
+        match stat.ppu_mode().value() {
+            0 => "HBlank",
+            1 => "VBlank",
+            2 => "OAM scan",
+            _ => "Drawing pixels",
+        }
+    ));
+    ui.indent("stat", |ui| {
+        stat = stat.with_lyc_int_select(flag(ui, stat.lyc_int_select(), "LYC interrupt"));
+        stat = stat.with_mode_2_int_select(flag(ui, stat.mode_2_int_select(), "Mode 2 interrupt"));
+        stat = stat.with_mode_1_int_select(flag(ui, stat.mode_1_int_select(), "Mode 1 interrupt"));
+        stat = stat.with_mode_0_int_select(flag(ui, stat.mode_0_int_select(), "Mode 0 interrupt"));
+        ui.label(format!("LY == LYC: {}", stat.lyc_eq_lc()));
+    });
+    write_if_changed(writes, 0xFF41, io[0x41], stat.raw_value());
+
+    ui.label(format!("LY: {}", io[0x44]));
+    for (address, name) in [
+        (0xFF45, "LYC"),
+        (0xFF42, "SCY"),
+        (0xFF43, "SCX"),
+        (0xFF4A, "WY"),
+        (0xFF4B, "WX"),
+    ] {
+        let value = io[address & 0xFF];
+        write_if_changed(writes, address as u16, value, number(ui, value, 0xFF, name));
+    }
+
+    for (address, name) in [(0xFF47, "BGP"), (0xFF48, "OBP0"), (0xFF49, "OBP1")] {
+        let value = io[address & 0xFF];
+        write_if_changed(writes, address as u16, value, palette(ui, value, name));
+    }
+}
+
+fn render_timer(ui: &mut Ui, io: &[u8], writes: &mut Vec<(u16, u8)>) {
+    ui.label(format!("DIV: {:02X}", io[0x04]));
+    write_if_changed(writes, 0xFF05, io[0x05], number(ui, io[0x05], 0xFF, "TIMA"));
+    write_if_changed(writes, 0xFF06, io[0x06], number(ui, io[0x06], 0xFF, "TMA"));
+
+    let tac = io[0x07];
+    let mut enabled = tac & 0b100 != 0;
+    let mut clock_select = tac & 0b11;
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut enabled, "TAC enable");
+        egui::ComboBox::from_id_salt("tac_clock_select")
+            .selected_text(tac_frequency(clock_select))
+            .show_ui(ui, |ui| {
+                for select in 0..4 {
+                    ui.selectable_value(&mut clock_select, select, tac_frequency(select));
+                }
+            });
+    });
+    write_if_changed(
+        writes,
+        0xFF07,
+        tac,
+        (tac & 0xF8) | ((enabled as u8) << 2) | clock_select,
+    );
+}
+
+fn tac_frequency(clock_select: u8) -> &'static str {
+    match clock_select {
+        0b00 => "4096 Hz",
+        0b01 => "262144 Hz",
+        0b10 => "65536 Hz",
+        _ => "16384 Hz",
+    }
+}
+
+fn render_interrupts(ui: &mut Ui, io: &[u8], writes: &mut Vec<(u16, u8)>) {
+    let mut interrupt_flag = io[0x0F];
+    let mut interrupt_enable = io[0xFF];
+    egui::Grid::new("interrupts").show(ui, |ui| {
+        ui.label("");
+        ui.label("IF");
+        ui.label("IE");
+        ui.end_row();
+        for (bit, name) in ["VBlank", "STAT", "Timer", "Serial", "Joypad"]
+            .iter()
+            .enumerate()
+        {
+            ui.label(*name);
+            interrupt_flag = set_bit(
+                interrupt_flag,
+                bit,
+                flag(ui, interrupt_flag & (1 << bit) != 0, ""),
+            );
+            interrupt_enable = set_bit(
+                interrupt_enable,
+                bit,
+                flag(ui, interrupt_enable & (1 << bit) != 0, ""),
+            );
+            ui.end_row();
+        }
+    });
+    write_if_changed(writes, 0xFF0F, io[0x0F], interrupt_flag);
+    write_if_changed(writes, 0xFFFF, io[0xFF], interrupt_enable);
+}
+
+fn render_joypad(ui: &mut Ui, io: &[u8], writes: &mut Vec<(u16, u8)>) {
+    let joyp = io[0x00];
+    // Selection and button bits are active low
+    let mut select_buttons = joyp & 0x20 == 0;
+    let mut select_dpad = joyp & 0x10 == 0;
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut select_buttons, "Select buttons");
+        ui.checkbox(&mut select_dpad, "Select d-pad");
+    });
+    let names = match (select_buttons, select_dpad) {
+        (true, false) => ["A", "B", "Select", "Start"],
+        (false, true) => ["Right", "Left", "Up", "Down"],
+        _ => ["Bit 0", "Bit 1", "Bit 2", "Bit 3"],
+    };
+    ui.horizontal(|ui| {
+        for (bit, name) in names.iter().enumerate() {
+            ui.add_enabled(
+                false,
+                egui::Checkbox::new(&mut (joyp & (1 << bit) == 0), *name),
+            );
+        }
+    });
+    let mut new_joyp = set_bit(joyp, 5, !select_buttons);
+    new_joyp = set_bit(new_joyp, 4, !select_dpad);
+    write_if_changed(writes, 0xFF00, joyp, new_joyp);
+}
+
+fn render_apu(ui: &mut Ui, io: &[u8], writes: &mut Vec<(u16, u8)>) {
+    let mut nr52 = NR52::new_with_raw_value(io[0x26]);
+    nr52 = nr52.with_audio_on(flag(ui, nr52.audio_on(), "Audio on"));
+    ui.label(format!(
+        "Channels on: {} {} {} {}",
+        nr52.ch1_on() as u8,
+        nr52.ch2_on() as u8,
+        nr52.ch3_on() as u8,
+        nr52.ch4_on() as u8
+    ));
+    write_if_changed(writes, 0xFF26, io[0x26] & 0x80, nr52.raw_value() & 0x80);
+
+    let mut nr50 = NR50::new_with_raw_value(io[0x24]);
+    ui.horizontal(|ui| {
+        nr50 = nr50.with_left_volume(u3::new(number(
+            ui,
+            nr50.left_volume().value(),
+            7,
+            "Left volume",
+        )));
+        nr50 = nr50.with_right_volume(u3::new(number(
+            ui,
+            nr50.right_volume().value(),
+            7,
+            "Right volume",
+        )));
+    });
+    ui.horizontal(|ui| {
+        nr50 = nr50.with_vin_left(flag(ui, nr50.vin_left(), "VIN left"));
+        nr50 = nr50.with_vin_right(flag(ui, nr50.vin_right(), "VIN right"));
+    });
+    write_if_changed(writes, 0xFF24, io[0x24], nr50.raw_value());
+
+    let mut nr51 = NR51::new_with_raw_value(io[0x25]);
+    egui::Grid::new("nr51").show(ui, |ui| {
+        ui.label("Panning");
+        ui.label("CH1");
+        ui.label("CH2");
+        ui.label("CH3");
+        ui.label("CH4");
+        ui.end_row();
+        ui.label("Left");
+        nr51 = nr51.with_ch1_left(flag(ui, nr51.ch1_left(), ""));
+        nr51 = nr51.with_ch2_left(flag(ui, nr51.ch2_left(), ""));
+        nr51 = nr51.with_ch3_left(flag(ui, nr51.ch3_left(), ""));
+        let mut ch4_left = nr51.ch4_left();
+        ui.checkbox(&mut ch4_left, "");
+        ui.end_row();
+        ui.label("Right");
+        nr51 = nr51.with_ch1_right(flag(ui, nr51.ch1_right(), ""));
+        nr51 = nr51.with_ch2_right(flag(ui, nr51.ch2_right(), ""));
+        nr51 = nr51.with_ch3_right(flag(ui, nr51.ch3_right(), ""));
+        nr51 = nr51.with_ch4_right(flag(ui, nr51.ch4_right(), ""));
+        ui.end_row();
+        // CH4 left is read-only in the register definition
+        nr51 = NR51::new_with_raw_value(set_bit(nr51.raw_value(), 7, ch4_left));
+    });
+    write_if_changed(writes, 0xFF25, io[0x25], nr51.raw_value());
+
+    egui::CollapsingHeader::new("Channel 1 (pulse with sweep)").show(ui, |ui| {
+        let mut nr10 = NR10::new_with_raw_value(io[0x10]);
+        ui.horizontal(|ui| {
+            nr10 = nr10.with_pace(u3::new(number(ui, nr10.pace().value(), 7, "Sweep pace")));
+            nr10 = nr10.with_direction(flag(ui, nr10.direction(), "Decrease"));
+            nr10 = nr10.with_individual_step(u3::new(number(
+                ui,
+                nr10.individual_step().value(),
+                7,
+                "Step",
+            )));
+        });
+        write_if_changed(writes, 0xFF10, io[0x10] & 0x7F, nr10.raw_value() & 0x7F);
+        render_pulse(ui, io, writes, 0xFF11);
+    });
+
+    egui::CollapsingHeader::new("Channel 2 (pulse)").show(ui, |ui| {
+        render_pulse(ui, io, writes, 0xFF16);
+    });
+
+    egui::CollapsingHeader::new("Channel 3 (wave)").show(ui, |ui| {
+        let mut nr30 = NR30::new_with_raw_value(io[0x1A]);
+        nr30 = nr30.with_DAC_on(flag(ui, nr30.DAC_on(), "DAC on"));
+        write_if_changed(writes, 0xFF1A, io[0x1A] & 0x80, nr30.raw_value() & 0x80);
+        write_if_changed(
+            writes,
+            0xFF1B,
+            io[0x1B],
+            number(ui, io[0x1B], 0xFF, "Length"),
+        );
+
+        let mut nr32 = NR32::new_with_raw_value(io[0x1C]);
+        let mut output_level = nr32.output_level().value();
+        egui::ComboBox::from_id_salt("nr32_output_level")
+            .selected_text(wave_output_level(output_level))
+            .show_ui(ui, |ui| {
+                for level in 0..4 {
+                    ui.selectable_value(&mut output_level, level, wave_output_level(level));
+                }
+            });
+        nr32 = nr32.with_output_level(u2::new(output_level));
+        write_if_changed(writes, 0xFF1C, io[0x1C] & 0x60, nr32.raw_value() & 0x60);
+        render_period(ui, io, writes, 0xFF1D);
+
+        ui.label(format!(
+            "Wave RAM: {}",
+            io[0x30..0x40]
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<String>>()
+                .join(" ")
+        ));
+    });
+
+    egui::CollapsingHeader::new("Channel 4 (noise)").show(ui, |ui| {
+        let mut nr41 = NR41::new_with_raw_value(io[0x20]);
+        nr41 = nr41.with_initial_length_timer(u6::new(number(
+            ui,
+            nr41.initial_length_timer().value(),
+            63,
+            "Length",
+        )));
+        write_if_changed(writes, 0xFF20, io[0x20], nr41.raw_value());
+        render_envelope(ui, io, writes, 0xFF21);
+
+        let mut nr43 = NR43::new_with_raw_value(io[0x22]);
+        ui.horizontal(|ui| {
+            nr43 = nr43.with_clock_shift(u4::new(number(
+                ui,
+                nr43.clock_shift().value(),
+                15,
+                "Clock shift",
+            )));
+            nr43 = nr43.with_lsfr_width(flag(ui, nr43.lsfr_width(), "7-bit LFSR"));
+            nr43 = nr43.with_clock_divider(u3::new(number(
+                ui,
+                nr43.clock_divider().value(),
+                7,
+                "Divider",
+            )));
+        });
+        write_if_changed(writes, 0xFF22, io[0x22], nr43.raw_value());
+
+        // The trigger bit reads back as set, so it is cleared before decoding
+        let mut nr44 = NR44::new_with_raw_value(io[0x23] & 0x40);
+        ui.horizontal(|ui| {
+            nr44 = nr44.with_length_enable(flag(ui, nr44.length_enable(), "Length enable"));
+            if ui.button("Trigger").clicked() {
+                nr44 = nr44.with_trigger(true);
+            }
+        });
+        write_if_changed(writes, 0xFF23, io[0x23] & 0x40, nr44.raw_value() & 0xC0);
+    });
+}
+
+fn wave_output_level(output_level: u8) -> &'static str {
+    match output_level {
+        0 => "Mute",
+        1 => "100%",
+        2 => "50%",
+        _ => "25%",
+    }
+}
+
+// Shared by both pulse channels, base is the address of NRx1
+fn render_pulse(ui: &mut Ui, io: &[u8], writes: &mut Vec<(u16, u8)>, base: u16) {
+    let index = (base & 0xFF) as usize;
+    let mut duty_cycle = PulseTimerDutyCycle::new_with_raw_value(io[index]);
+    let mut wave_duty = duty_cycle.wave_duty().value();
+    egui::ComboBox::from_id_salt(("wave_duty", base))
+        .selected_text(pulse_duty(wave_duty))
+        .show_ui(ui, |ui| {
+            for duty in 0..4 {
+                ui.selectable_value(&mut wave_duty, duty, pulse_duty(duty));
+            }
+        });
+    duty_cycle = duty_cycle.with_wave_duty(u2::new(wave_duty));
+    let length = number(ui, io[index] & 0x3F, 63, "Length");
+    write_if_changed(
+        writes,
+        base,
+        io[index],
+        (duty_cycle.raw_value() & 0xC0) | length,
+    );
+
+    render_envelope(ui, io, writes, base + 1);
+    render_period(ui, io, writes, base + 2);
+}
+
+fn pulse_duty(wave_duty: u8) -> &'static str {
+    match wave_duty {
+        0 => "12.5%",
+        1 => "25%",
+        2 => "50%",
+        _ => "75%",
+    }
+}
+
+fn render_envelope(ui: &mut Ui, io: &[u8], writes: &mut Vec<(u16, u8)>, address: u16) {
+    let index = (address & 0xFF) as usize;
+    let mut envelope = VolumeEnvelope::new_with_raw_value(io[index]);
+    ui.horizontal(|ui| {
+        envelope = envelope.with_initial_volume(u4::new(number(
+            ui,
+            envelope.initial_volume().value(),
+            15,
+            "Volume",
+        )));
+        envelope = envelope.with_env_dir(flag(ui, envelope.env_dir(), "Increase"));
+        envelope = envelope.with_sweep_pace(u3::new(number(
+            ui,
+            envelope.sweep_pace().value(),
+            7,
+            "Pace",
+        )));
+    });
+    write_if_changed(writes, address, io[index], envelope.raw_value());
+}
+
+// Edits the period in NRx3 and NRx4 along with the controls of NRx4, address is the one of
+// NRx3. Every write keeps the bits of the register that were not changed
+fn render_period(ui: &mut Ui, io: &[u8], writes: &mut Vec<(u16, u8)>, address: u16) {
+    let index = (address & 0xFF) as usize;
+    let mut control = PeriodHighControl::new_with_raw_value(io[index + 1] & 0x47);
+    let mut period = ((control.period().value() as u16) << 8) | io[index] as u16;
+    ui.horizontal(|ui| {
+        ui.label("Period");
+        ui.add(egui::DragValue::new(&mut period).range(0..=0x7FF));
+    });
+    ui.horizontal(|ui| {
+        control = control.with_length_enable(flag(ui, control.length_enable(), "Length enable"));
+        if ui.button("Trigger").clicked() {
+            control = control.with_trigger(true);
+        }
+    });
+    control = control.with_period(u3::new((period >> 8) as u8));
+    write_if_changed(writes, address, io[index], period as u8);
+    write_if_changed(
+        writes,
+        address + 1,
+        io[index + 1] & 0x47,
+        control.raw_value() & 0xC7,
+    );
+}
+
+fn palette(ui: &mut Ui, value: u8, name: &str) -> u8 {
+    let mut value = value;
+    ui.horizontal(|ui| {
+        ui.label(format!("{} ({:02X})", name, value));
+        for index in 0..4 {
+            let shade = (value >> (index * 2)) & 0b11;
+            let color = match shade {
+                0 => 0xFF,
+                1 => 0xAA,
+                2 => 0x55,
+                _ => 0x00,
+            };
+            let (rect, response) = ui.allocate_exact_size(vec2(16.0, 16.0), Sense::click());
+            ui.painter()
+                .rect_filled(rect, 2.0, Color32::from_gray(color));
+            // Clicking a swatch cycles through the four shades
+            if response
+                .on_hover_text(format!("Color {}: shade {}", index, shade))
+                .clicked()
+            {
+                let shade = (shade + 1) & 0b11;
+                value = (value & !(0b11 << (index * 2))) | (shade << (index * 2));
+            }
+        }
+    });
+    value
+}
+
+fn flag(ui: &mut Ui, value: bool, label: &str) -> bool {
+    let mut value = value;
+    ui.checkbox(&mut value, label);
+    value
+}
+
+fn number(ui: &mut Ui, value: u8, max: u8, label: &str) -> u8 {
+    let mut value = value;
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(egui::DragValue::new(&mut value).range(0..=max));
+    });
+    value
+}
+
+fn set_bit(value: u8, bit: usize, set: bool) -> u8 {
+    if set {
+        value | (1 << bit)
+    } else {
+        value & !(1 << bit)
+    }
+}
+
+fn write_if_changed(writes: &mut Vec<(u16, u8)>, address: u16, old: u8, new: u8) {
+    if old != new {
+        writes.push((address, new));
+    }
+}
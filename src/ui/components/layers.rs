@@ -0,0 +1,42 @@
+use crate::emulator::EmulatorState;
+use crate::gb::ppu::LayerVisibility;
+use crate::ui::UIState;
+use egui::{Context, Ui};
+
+pub(crate) fn render(
+    ui: &mut Ui,
+    egui_context: &Context,
+    ui_state: &mut UIState,
+    emu_state: &EmulatorState,
+) {
+    puffin::profile_scope!("UI - Layers");
+    match emu_state {
+        EmulatorState::GameBoy(_) => {
+            let layers = &mut ui_state.layer_visibility;
+            ui.checkbox(&mut layers.background, "Background");
+            ui.checkbox(&mut layers.window, "Window");
+            ui.checkbox(&mut layers.sprites, "Sprites");
+
+            ui.separator();
+            ui.label("OAM entries");
+            ui.add_enabled_ui(layers.sprites, |ui| {
+                egui::Grid::new("layer_sprites").show(ui, |ui| {
+                    for index in 0..40 {
+                        let mut visible = !layers.hidden_sprites[index];
+                        if ui.checkbox(&mut visible, format!("{:02}", index)).changed() {
+                            layers.hidden_sprites[index] = !visible;
+                        }
+                        if index % 8 == 7 {
+                            ui.end_row();
+                        }
+                    }
+                });
+            });
+
+            ui.separator();
+            if ui.button("Show all").clicked() {
+                *layers = LayerVisibility::default();
+            }
+        }
+    }
+}
@@ -1,10 +1,15 @@
-use crate::emulator::EmulatorState;
-use crate::ui::{Memories, UIContext, UIState};
-use egui::{Align, Context, Ui};
+use crate::emulator::{EmulatorControlMessage, EmulatorState};
+use crate::gb::Memories;
+use crate::ui::{UIContext, UIState};
+use egui::{Align, Color32, Context, Label, RichText, Sense, Ui};
 use egui_extras::{Column, TableBuilder};
 
+// Number of frames a changed byte stays highlighted
+const CHANGE_HIGHLIGHT_FRAMES: u8 = 30;
+
 pub(crate) fn render(
     ui: &mut Ui,
+    ui_context: &mut UIContext,
     egui_context: &Context,
     ui_state: &mut UIState,
     emu_state: &EmulatorState,
@@ -16,9 +21,25 @@ pub(crate) fn render(
             egui::ComboBox::from_label("Select memory to view")
                 .selected_text(format!("{:?}", ui_state.selected_memory))
                 .show_ui(ui, |ui| {
+                    ui.selectable_value(
+                        &mut ui_state.selected_memory,
+                        Memories::Bus,
+                        "Full address space",
+                    );
+                    ui.selectable_value(
+                        &mut ui_state.selected_memory,
+                        Memories::ROMBank(0),
+                        "ROM banks",
+                    );
+                    ui.selectable_value(
+                        &mut ui_state.selected_memory,
+                        Memories::ExternalRAM(0),
+                        "External RAM banks",
+                    );
                     ui.selectable_value(&mut ui_state.selected_memory, Memories::WRAM1, "WRAM1");
                     ui.selectable_value(&mut ui_state.selected_memory, Memories::WRAM2, "WRAM2");
                     ui.selectable_value(&mut ui_state.selected_memory, Memories::HRAM, "HRAM");
+                    ui.selectable_value(&mut ui_state.selected_memory, Memories::IO, "IO");
                     ui.selectable_value(&mut ui_state.selected_memory, Memories::OAM, "OAM");
                     ui.selectable_value(
                         &mut ui_state.selected_memory,
@@ -31,17 +52,88 @@ pub(crate) fn render(
                         "Tile data",
                     );
                 });
+
+            match &mut ui_state.selected_memory {
+                Memories::ROMBank(bank) => {
+                    ui.add(
+                        egui::DragValue::new(bank)
+                            .range(0..=emu_state.rom_banks.max(1) - 1)
+                            .prefix("Bank "),
+                    );
+                }
+                Memories::ExternalRAM(bank) => {
+                    ui.add(
+                        egui::DragValue::new(bank)
+                            .range(0..=emu_state.ram_banks.max(1) - 1)
+                            .prefix("Bank "),
+                    );
+                }
+                _ => {}
+            }
             //ui.separator();
 
-            if ui_state.selected_memory == Memories::WRAM1
-                || ui_state.selected_memory == Memories::WRAM2
-                || ui_state.selected_memory == Memories::HRAM
-                || ui_state.selected_memory == Memories::BackgroundMaps
-                || ui_state.selected_memory == Memories::OAM
+            // Track which bytes changed since the previous frame
+            let view = &mut ui_context.memory_view;
+            if view.memory != ui_state.selected_memory || view.previous.len() != emu_state.ram.len()
             {
+                view.memory = ui_state.selected_memory.clone();
+                view.previous = emu_state.ram.clone();
+                view.changed = vec![0; emu_state.ram.len()];
+                view.search_match = None;
+                view.editing = None;
+            }
+            for (i, (previous, current)) in view.previous.iter().zip(&emu_state.ram).enumerate() {
+                if previous != current {
+                    view.changed[i] = CHANGE_HIGHLIGHT_FRAMES;
+                } else {
+                    view.changed[i] = view.changed[i].saturating_sub(1);
+                }
+            }
+            view.previous.clone_from(&emu_state.ram);
+
+            if ui_state.selected_memory != Memories::TileData {
+                let memory_prefix = match ui_state.selected_memory {
+                    Memories::ROMBank(0) => 0x0000,
+                    Memories::ROMBank(_) => 0x4000,
+                    Memories::ExternalRAM(_) => 0xA000,
+                    Memories::WRAM1 => 0xC000,
+                    Memories::WRAM2 => 0xD000,
+                    Memories::HRAM => 0xFF80,
+                    Memories::IO => 0xFF00,
+                    Memories::OAM => 0xFE00,
+                    Memories::BackgroundMaps => 0x9800,
+                    _ => 0x0,
+                };
+
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut view.goto).desired_width(50.0));
+                    if ui.button("Go to").clicked() {
+                        if let Ok(address) = usize::from_str_radix(view.goto.trim(), 16) {
+                            if address >= memory_prefix
+                                && address < memory_prefix + emu_state.ram.len()
+                            {
+                                view.scroll_to = Some((address - memory_prefix) >> 4);
+                            }
+                        }
+                    }
+
+                    ui.add(egui::TextEdit::singleline(&mut view.search).desired_width(80.0));
+                    if ui.button("Find").clicked() {
+                        let start = view.search_match.map_or(0, |(offset, _)| offset + 1);
+                        view.search_match = parse_hex_bytes(&view.search).and_then(|needle| {
+                            find_bytes(&emu_state.ram, &needle, start)
+                                .or_else(|| find_bytes(&emu_state.ram, &needle, 0))
+                                .map(|offset| (offset, needle.len()))
+                        });
+                        if let Some((offset, _)) = view.search_match {
+                            view.scroll_to = Some(offset >> 4);
+                        }
+                    }
+                });
+
                 // Memory table
                 //ui.label("Memory view");
-                let memory_table = TableBuilder::new(ui)
+                let mut memory_table = TableBuilder::new(ui)
                     .id_salt(0)
                     .striped(true)
                     .resizable(false)
@@ -51,14 +143,11 @@ pub(crate) fn render(
                     .column(Column::exact(2.0))
                     .columns(Column::exact(16.0).clip(false), 8);
 
-                let memory_prefix = match ui_state.selected_memory {
-                    Memories::WRAM1 => 0xC000,
-                    Memories::WRAM2 => 0xD000,
-                    Memories::HRAM => 0xFF80,
-                    Memories::OAM => 0xFE00,
-                    _ => 0x0,
-                };
+                if let Some(row) = view.scroll_to.take() {
+                    memory_table = memory_table.scroll_to_row(row, Some(Align::TOP));
+                }
 
+                let mut edited = None;
                 memory_table.body(|mut body| {
                     body.rows(18.0, emu_state.ram.len().div_ceil(16), |mut row| {
                         let row_index = row.index();
@@ -67,21 +156,69 @@ pub(crate) fn render(
                             ui.label(format!("{:#06X}:  ", memory_prefix + (row_index << 4)));
                         });
 
-                        for i in 0x00..=0x07 {
-                            row.col(|ui| {
-                                ui.label(format!("{:02X}", emu_state.ram[(row_index << 4) + i]));
-                            });
-                        }
+                        for i in 0x00..=0x0F {
+                            if i == 0x08 {
+                                row.col(|_| {});
+                            }
 
-                        row.col(|_| {});
+                            let offset = (row_index << 4) + i;
+                            if offset >= emu_state.ram.len() {
+                                row.col(|_| {});
+                                continue;
+                            }
 
-                        for i in 0x08..=0x0F {
                             row.col(|ui| {
-                                ui.label(format!("{:02X}", emu_state.ram[(row_index << 4) + i]));
+                                if let Some((editing_offset, text)) = &mut view.editing {
+                                    if *editing_offset == offset {
+                                        let response = ui.add(
+                                            egui::TextEdit::singleline(text)
+                                                .char_limit(2)
+                                                .desired_width(16.0),
+                                        );
+                                        response.request_focus();
+                                        if response.lost_focus() {
+                                            if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                                                edited = u8::from_str_radix(text.trim(), 16)
+                                                    .ok()
+                                                    .map(|value| (offset, value));
+                                            }
+                                            view.editing = None;
+                                        }
+                                        return;
+                                    }
+                                }
+
+                                let mut text =
+                                    RichText::new(format!("{:02X}", emu_state.ram[offset]));
+                                if view.changed[offset] > 0 {
+                                    text = text.color(Color32::YELLOW);
+                                }
+                                if view.search_match.is_some_and(|(start, length)| {
+                                    (start..start + length).contains(&offset)
+                                }) {
+                                    text = text.background_color(Color32::DARK_BLUE);
+                                }
+
+                                let response = ui.add(Label::new(text).sense(Sense::click()));
+                                if response.double_clicked() {
+                                    view.editing =
+                                        Some((offset, format!("{:02X}", emu_state.ram[offset])));
+                                }
                             });
                         }
                     })
                 });
+
+                if let Some((offset, value)) = edited {
+                    ui_state
+                        .tx_ui
+                        .send(EmulatorControlMessage::WriteMemory(
+                            ui_state.selected_memory.clone(),
+                            offset,
+                            value,
+                        ))
+                        .expect("Failed to send control message to emulator thread");
+                }
             }
 
             if ui_state.selected_memory == Memories::TileData {
@@ -136,3 +273,30 @@ pub(crate) fn render(
         }
     }
 }
+
+// Accepts bytes as hex pairs, optionally separated by spaces, e.g. "DEAD BE EF"
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    // Checked up front so the byte chunks below never split a multi-byte character
+    if digits.is_empty() || digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return None;
+    }
+
+    digits
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+fn find_bytes(memory: &[u8], needle: &[u8], start: usize) -> Option<usize> {
+    if start >= memory.len() {
+        return None;
+    }
+
+    memory[start..]
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|position| start + position)
+}
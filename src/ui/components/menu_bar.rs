@@ -1,5 +1,6 @@
 use crate::emulator::EmulatorControlMessage;
 use crate::emulator::EmulatorState;
+use crate::gb::video::VideoFormat;
 use crate::ui::{UIState, Views};
 use egui::text::LayoutJob;
 use egui::{
@@ -46,6 +47,27 @@ pub(crate) fn render(
                             ui.close_menu();
                         }
 
+                        if ui.button("Open GBS").clicked() {
+                            let path = FileDialog::new().add_filter("gbs", &["gbs"]).pick_file();
+
+                            if let Some(path) = path {
+                                ui_state
+                                    .tx_ui
+                                    .send(EmulatorControlMessage::LoadGbs(
+                                        path.to_str()
+                                            .expect("Failed to parse path to string")
+                                            .to_string(),
+                                    ))
+                                    .expect("Failed to send control message to emulator thread");
+                                ui_state
+                                    .tx_ui
+                                    .send(EmulatorControlMessage::Start)
+                                    .expect("Failed to send control message to emulator thread");
+                                ui_state.show_gbs_player = true;
+                            }
+                            ui.close_menu();
+                        }
+
                         ui.separator();
 
                         if ui.button("Open user folder").clicked() {}
@@ -62,6 +84,11 @@ pub(crate) fn render(
                         // Local 2 or 4 player coop
                     });
                     ui.menu_button("Options", |ui| {
+                        if ui.button("Settings").clicked() {
+                            ui_state.current_view = Views::Settings;
+                            ui.close_menu();
+                        }
+
                         // Graphics settings
                         // Game overview settings
                         // Input settings
@@ -92,6 +119,197 @@ pub(crate) fn render(
                         // Multi screen positioning
                     });
 
+                    ui.menu_button("Tools", |ui| {
+                        if ui.button("Cheats").clicked() {
+                            ui_state.show_cheats = true;
+                            ui.close_menu();
+                        }
+
+                        if ui.button("RAM search").clicked() {
+                            ui_state.show_ram_search = true;
+                            ui.close_menu();
+                        }
+
+                        if ui.button("IO registers").clicked() {
+                            ui_state.show_io_registers = true;
+                            ui.close_menu();
+                        }
+
+                        if ui.button("VRAM viewer").clicked() {
+                            ui_state.show_vram_viewer = true;
+                            ui.close_menu();
+                        }
+
+                        if ui.button("Audio debugger").clicked() {
+                            ui_state.show_audio_debugger = true;
+                            ui.close_menu();
+                        }
+
+                        if ui.button("Event viewer").clicked() {
+                            ui_state.show_event_viewer = true;
+                            ui.close_menu();
+                        }
+
+                        if ui.button("Layers").clicked() {
+                            ui_state.show_layers = true;
+                            ui.close_menu();
+                        }
+
+                        ui.checkbox(&mut ui_state.inspect_pixels, "Pixel inspector");
+
+                        ui.separator();
+
+                        if ui
+                            .add(egui::Button::new("Screenshot").shortcut_text("F12"))
+                            .clicked()
+                        {
+                            ui_state
+                                .tx_ui
+                                .send(EmulatorControlMessage::TakeScreenshot)
+                                .expect("Failed to send control message to emulator thread");
+                            ui.close_menu();
+                        }
+
+                        ui.separator();
+
+                        if ui.button("Load script").clicked() {
+                            let path = FileDialog::new().add_filter("lua", &["lua"]).pick_file();
+
+                            if let Some(path) = path {
+                                ui_state
+                                    .tx_ui
+                                    .send(EmulatorControlMessage::LoadScript(
+                                        path.to_str()
+                                            .expect("Failed to parse path to string")
+                                            .to_string(),
+                                    ))
+                                    .expect("Failed to send control message to emulator thread");
+                            }
+                            ui.close_menu();
+                        }
+
+                        if ui.button("Unload script").clicked() {
+                            ui_state
+                                .tx_ui
+                                .send(EmulatorControlMessage::UnloadScript)
+                                .expect("Failed to send control message to emulator thread");
+                            ui.close_menu();
+                        }
+
+                        ui.separator();
+
+                        let EmulatorState::GameBoy(gb_state) = emu_state;
+                        if gb_state.recording_audio {
+                            if ui.button("Stop audio recording").clicked() {
+                                ui_state
+                                    .tx_ui
+                                    .send(EmulatorControlMessage::StopAudioRecording)
+                                    .expect("Failed to send control message to emulator thread");
+                                ui.close_menu();
+                            }
+                        } else {
+                            if ui.button("Record audio").clicked() {
+                                let path =
+                                    FileDialog::new().add_filter("wav", &["wav"]).save_file();
+
+                                if let Some(path) = path {
+                                    ui_state
+                                        .tx_ui
+                                        .send(EmulatorControlMessage::StartAudioRecording(
+                                            path.to_str()
+                                                .expect("Failed to parse path to string")
+                                                .to_string(),
+                                            ui_state.record_stems,
+                                        ))
+                                        .expect(
+                                            "Failed to send control message to emulator thread",
+                                        );
+                                }
+                                ui.close_menu();
+                            }
+                            ui.checkbox(&mut ui_state.record_stems, "Record channel stems");
+                        }
+
+                        if gb_state.logging_vgm {
+                            if ui.button("Set VGM loop point").clicked() {
+                                ui_state
+                                    .tx_ui
+                                    .send(EmulatorControlMessage::SetVgmLoopPoint)
+                                    .expect("Failed to send control message to emulator thread");
+                                ui.close_menu();
+                            }
+
+                            if ui.button("Stop VGM export").clicked() {
+                                ui_state
+                                    .tx_ui
+                                    .send(EmulatorControlMessage::StopVgmLogging)
+                                    .expect("Failed to send control message to emulator thread");
+                                ui.close_menu();
+                            }
+                        } else if ui.button("Export VGM").clicked() {
+                            let path = FileDialog::new().add_filter("vgm", &["vgm"]).save_file();
+
+                            if let Some(path) = path {
+                                ui_state
+                                    .tx_ui
+                                    .send(EmulatorControlMessage::StartVgmLogging(
+                                        path.to_str()
+                                            .expect("Failed to parse path to string")
+                                            .to_string(),
+                                    ))
+                                    .expect("Failed to send control message to emulator thread");
+                            }
+                            ui.close_menu();
+                        }
+
+                        ui.separator();
+
+                        if gb_state.recording_video {
+                            if ui.button("Stop video recording").clicked() {
+                                ui_state
+                                    .tx_ui
+                                    .send(EmulatorControlMessage::StopVideoRecording)
+                                    .expect("Failed to send control message to emulator thread");
+                                ui.close_menu();
+                            }
+                        } else {
+                            if ui.button("Record video").clicked() {
+                                let format = ui_state.video_format;
+                                let path = FileDialog::new()
+                                    .add_filter(format.name(), &[format.extension()])
+                                    .save_file();
+
+                                if let Some(path) = path {
+                                    ui_state
+                                        .tx_ui
+                                        .send(EmulatorControlMessage::StartVideoRecording(
+                                            path.to_str()
+                                                .expect("Failed to parse path to string")
+                                                .to_string(),
+                                            format,
+                                            ui_state.video_filters,
+                                            ui_state.video_audio,
+                                        ))
+                                        .expect(
+                                            "Failed to send control message to emulator thread",
+                                        );
+                                }
+                                ui.close_menu();
+                            }
+                            ui.horizontal(|ui| {
+                                for format in VideoFormat::ALL {
+                                    ui.radio_value(
+                                        &mut ui_state.video_format,
+                                        format,
+                                        format.name(),
+                                    );
+                                }
+                            });
+                            ui.checkbox(&mut ui_state.video_filters, "Apply filters");
+                            ui.checkbox(&mut ui_state.video_audio, "Record audio to WAV");
+                        }
+                    });
+
                     ui.menu_button("Multiplayer", |ui| {
                         // Become host
                         // Kick players
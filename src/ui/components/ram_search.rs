@@ -0,0 +1,241 @@
+use crate::emulator::{EmulatorControlMessage, EmulatorState};
+use crate::gb::cheats::Cheat;
+use crate::gb::ram_search::{
+    location, Comparison, Endianness, MemoryLocation, SearchFormat, SearchTarget, ValueSize, Watch,
+};
+use crate::ui::UIState;
+use egui::{ComboBox, Context, Ui};
+use egui_extras::{Column, TableBuilder};
+
+pub(crate) fn render(
+    ui: &mut Ui,
+    egui_context: &Context,
+    ui_state: &mut UIState,
+    emu_state: &EmulatorState,
+) {
+    puffin::profile_scope!("UI - RAM search");
+    match emu_state {
+        EmulatorState::GameBoy(emu_state) => {
+            // Value interpretation
+            ui.horizontal(|ui| {
+                let format = &mut ui_state.ram_search_format;
+                ComboBox::from_id_salt("ram_search_size")
+                    .selected_text(match format.size {
+                        ValueSize::Byte => "8-bit",
+                        ValueSize::Word => "16-bit",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut format.size, ValueSize::Byte, "8-bit");
+                        ui.selectable_value(&mut format.size, ValueSize::Word, "16-bit");
+                    });
+                ComboBox::from_id_salt("ram_search_endianness")
+                    .selected_text(match format.endianness {
+                        Endianness::Little => "Little endian",
+                        Endianness::Big => "Big endian",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            &mut format.endianness,
+                            Endianness::Little,
+                            "Little endian",
+                        );
+                        ui.selectable_value(&mut format.endianness, Endianness::Big, "Big endian");
+                    });
+                ui.checkbox(&mut format.bcd, "BCD");
+            });
+
+            // Comparison
+            ui.horizontal(|ui| {
+                let value = ui_state.ram_search_value.trim().parse::<u32>().unwrap_or(0);
+                let comparisons = [
+                    (Comparison::Equal, "Equal to"),
+                    (Comparison::NotEqual, "Not equal to"),
+                    (Comparison::LessThan, "Less than"),
+                    (Comparison::GreaterThan, "Greater than"),
+                    (Comparison::IncreasedBy(value), "Increased by"),
+                    (Comparison::DecreasedBy(value), "Decreased by"),
+                ];
+                let selected = comparisons
+                    .iter()
+                    .position(|(comparison, _)| {
+                        std::mem::discriminant(comparison)
+                            == std::mem::discriminant(&ui_state.ram_search_comparison)
+                    })
+                    .unwrap_or(0);
+                let mut new_selection = selected;
+                ComboBox::from_id_salt("ram_search_comparison")
+                    .selected_text(comparisons[selected].1)
+                    .show_ui(ui, |ui| {
+                        for (index, (_, name)) in comparisons.iter().enumerate() {
+                            ui.selectable_value(&mut new_selection, index, *name);
+                        }
+                    });
+                ui_state.ram_search_comparison = comparisons[new_selection].0;
+
+                if !matches!(
+                    ui_state.ram_search_comparison,
+                    Comparison::IncreasedBy(_) | Comparison::DecreasedBy(_)
+                ) {
+                    ui.checkbox(&mut ui_state.ram_search_use_value, "Value");
+                }
+                ui.add(
+                    egui::TextEdit::singleline(&mut ui_state.ram_search_value).desired_width(60.0),
+                );
+            });
+
+            ui.horizontal(|ui| {
+                if ui.button("New search").clicked() {
+                    ui_state
+                        .tx_ui
+                        .send(EmulatorControlMessage::RamSearchReset)
+                        .expect("Failed to send control message to emulator thread");
+                }
+
+                if ui.button("Filter").clicked() {
+                    let target = match ui_state.ram_search_value.trim().parse::<u32>() {
+                        Ok(value) if ui_state.ram_search_use_value => SearchTarget::Value(value),
+                        _ => SearchTarget::Previous,
+                    };
+                    ui_state
+                        .tx_ui
+                        .send(EmulatorControlMessage::RamSearchFilter(
+                            ui_state.ram_search_format,
+                            ui_state.ram_search_comparison,
+                            target,
+                        ))
+                        .expect("Failed to send control message to emulator thread");
+                }
+
+                ui.label(format!(
+                    "{} candidates",
+                    emu_state.ram_search.candidate_count
+                ));
+            });
+
+            ui.separator();
+
+            let result_table = TableBuilder::new(ui)
+                .id_salt("ram_search_results")
+                .striped(true)
+                .resizable(false)
+                .max_scroll_height(300.0)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Column::auto().at_least(80.0))
+                .column(Column::auto().at_least(60.0))
+                .column(Column::auto().at_least(60.0))
+                .column(Column::remainder());
+
+            result_table
+                .header(20.0, |mut header| {
+                    header.col(|ui| {
+                        ui.strong("Address");
+                    });
+                    header.col(|ui| {
+                        ui.strong("Value");
+                    });
+                    header.col(|ui| {
+                        ui.strong("Previous");
+                    });
+                    header.col(|_| {});
+                })
+                .body(|mut body| {
+                    for result in &emu_state.ram_search.results {
+                        body.row(20.0, |mut row| {
+                            row.col(|ui| {
+                                ui.monospace(format_location(result.location));
+                            });
+                            row.col(|ui| {
+                                ui.label(result.value.to_string());
+                            });
+                            row.col(|ui| {
+                                ui.label(result.previous.to_string());
+                            });
+                            row.col(|ui| {
+                                if ui.button("Watch").clicked() {
+                                    let watch = Watch {
+                                        index: result.index,
+                                        format: ui_state.ram_search_format,
+                                    };
+                                    if !ui_state.watch_list.contains(&watch) {
+                                        ui_state.watch_list.push(watch);
+                                    }
+                                }
+                                if ui.button("Cheat").clicked() {
+                                    let format = ui_state.ram_search_format;
+                                    add_cheat(ui_state, result.index, format, result.value);
+                                }
+                            });
+                        });
+                    }
+                });
+
+            ui.separator();
+            ui.label("Watch list");
+
+            let mut removed = None;
+            let watch_table = TableBuilder::new(ui)
+                .id_salt("ram_search_watches")
+                .striped(true)
+                .resizable(false)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Column::auto().at_least(80.0))
+                .column(Column::auto().at_least(60.0))
+                .column(Column::remainder());
+
+            watch_table.body(|mut body| {
+                for (index, watch) in ui_state.watch_list.clone().iter().enumerate() {
+                    let value = emu_state
+                        .ram_search
+                        .watch_values
+                        .get(index)
+                        .copied()
+                        .flatten();
+                    body.row(20.0, |mut row| {
+                        row.col(|ui| {
+                            ui.monospace(format_location(location(watch.index)));
+                        });
+                        row.col(|ui| {
+                            ui.label(value.map_or("-".to_string(), |value| value.to_string()));
+                        });
+                        row.col(|ui| {
+                            if let Some(value) = value {
+                                if ui.button("Cheat").clicked() {
+                                    add_cheat(ui_state, watch.index, watch.format, value);
+                                }
+                            }
+                            if ui.button("Remove").clicked() {
+                                removed = Some(index);
+                            }
+                        });
+                    });
+                }
+            });
+
+            if let Some(index) = removed {
+                ui_state.watch_list.remove(index);
+            }
+        }
+    }
+}
+
+fn format_location(location: MemoryLocation) -> String {
+    match location.bank {
+        Some(bank) => format!("{:02X}:{:04X}", bank, location.address),
+        None => format!("{:04X}", location.address),
+    }
+}
+
+// Freezes the value by adding a raw cheat for every byte of it
+fn add_cheat(ui_state: &mut UIState, index: usize, format: SearchFormat, value: u32) {
+    for (offset, byte) in format.encode(value).iter().enumerate() {
+        let location = location(index + offset);
+        ui_state
+            .tx_ui
+            .send(EmulatorControlMessage::AddCheat(Cheat {
+                name: format!("RAM search {}", format_location(location)),
+                code: format!("{}:{:02X}", format_location(location), byte),
+                enabled: true,
+            }))
+            .expect("Failed to send control message to emulator thread");
+    }
+}
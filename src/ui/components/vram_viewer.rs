@@ -0,0 +1,451 @@
+use crate::emulator::EmulatorState;
+use crate::gb::ppu::registers::LCDC;
+use crate::gb::ppu::OAMAttributes;
+use crate::ui::{UIContext, VramViewState};
+use egui::{
+    pos2, vec2, Color32, ColorImage, Context, Rect, Sense, Stroke, StrokeKind, TextureOptions, Ui,
+};
+use egui_extras::{Column, TableBuilder};
+use log::{log, Level};
+use rfd::FileDialog;
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum VramTab {
+    Tiles,
+    Maps,
+    OAM,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum TilePalette {
+    Identity,
+    BGP,
+    OBP0,
+    OBP1,
+}
+
+pub(crate) fn render(
+    ui: &mut Ui,
+    ui_context: &mut UIContext,
+    egui_context: &Context,
+    emu_state: &EmulatorState,
+) {
+    puffin::profile_scope!("UI - VRAM viewer");
+    match emu_state {
+        EmulatorState::GameBoy(emu_state) => {
+            if emu_state.vram.len() != 0x2000
+                || emu_state.oam.len() != 160
+                || emu_state.io_registers.len() != 0x100
+            {
+                return;
+            }
+
+            ui.horizontal(|ui| {
+                let tab = &mut ui_context.vram_view.tab;
+                ui.selectable_value(tab, VramTab::Tiles, "Tiles");
+                ui.selectable_value(tab, VramTab::Maps, "BG maps");
+                ui.selectable_value(tab, VramTab::OAM, "OAM");
+            });
+            ui.separator();
+
+            let vram = &emu_state.vram;
+            let io = &emu_state.io_registers;
+            match ui_context.vram_view.tab {
+                VramTab::Tiles => render_tiles(ui, ui_context, egui_context, vram, io),
+                VramTab::Maps => render_maps(ui, ui_context, egui_context, vram, io),
+                VramTab::OAM => render_oam(ui, ui_context, egui_context, vram, &emu_state.oam, io),
+            }
+        }
+    }
+}
+
+fn render_tiles(
+    ui: &mut Ui,
+    ui_context: &mut UIContext,
+    egui_context: &Context,
+    vram: &[u8],
+    io: &[u8],
+) {
+    let view = &mut ui_context.vram_view;
+    ui.horizontal(|ui| {
+        ui.label("Palette");
+        egui::ComboBox::from_id_salt("vram_tile_palette")
+            .selected_text(palette_name(view.palette))
+            .show_ui(ui, |ui| {
+                for palette in [
+                    TilePalette::Identity,
+                    TilePalette::BGP,
+                    TilePalette::OBP0,
+                    TilePalette::OBP1,
+                ] {
+                    ui.selectable_value(&mut view.palette, palette, palette_name(palette));
+                }
+            });
+    });
+    let palette = match view.palette {
+        TilePalette::Identity => 0b11100100,
+        TilePalette::BGP => io[0x47],
+        TilePalette::OBP0 => io[0x48],
+        TilePalette::OBP1 => io[0x49],
+    };
+
+    // 384 tiles, 16 tiles per row
+    let mut image = ColorImage::new([16 * 8, 24 * 8], Color32::BLACK);
+    for tile in 0..384 {
+        draw_tile(
+            &mut image,
+            vram,
+            tile,
+            palette,
+            (tile % 16) * 8,
+            (tile / 16) * 8,
+            false,
+            false,
+            false,
+        );
+    }
+
+    let response = show_image(ui, view, egui_context, "vram_tiles", &image, 3.0);
+    if let Some(pointer) = response.hover_pos() {
+        let x = ((pointer.x - response.rect.left()) / 3.0 / 8.0) as usize;
+        let y = ((pointer.y - response.rect.top()) / 3.0 / 8.0) as usize;
+        let tile = y * 16 + x;
+        if x < 16 && tile < 384 {
+            response.on_hover_text(format!(
+                "Tile {} ({:#04X})\nAddress {:#06X}",
+                tile,
+                tile % 256,
+                0x8000 + tile * 16
+            ));
+        }
+    }
+
+    if ui.button("Export PNG").clicked() {
+        export_png(&image);
+    }
+}
+
+fn render_maps(
+    ui: &mut Ui,
+    ui_context: &mut UIContext,
+    egui_context: &Context,
+    vram: &[u8],
+    io: &[u8],
+) {
+    let view = &mut ui_context.vram_view;
+    let lcdc = LCDC::new_with_raw_value(io[0x40]);
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut view.map, 0, "9800");
+        ui.selectable_value(&mut view.map, 1, "9C00");
+    });
+
+    let map_base = 0x1800 + view.map * 0x400;
+    let mut image = ColorImage::new([256, 256], Color32::BLACK);
+    for index in 0..1024 {
+        let tile = tile_data_index(vram[map_base + index], lcdc.tile_addressing_mode());
+        draw_tile(
+            &mut image,
+            vram,
+            tile,
+            io[0x47],
+            (index % 32) * 8,
+            (index / 32) * 8,
+            false,
+            false,
+            false,
+        );
+    }
+
+    let response = show_image(ui, view, egui_context, "vram_map", &image, 2.0);
+    let painter = ui.painter_at(response.rect);
+    let to_screen = |x: f32, y: f32| response.rect.min + vec2(x, y) * 2.0;
+
+    // The viewport wraps around the edges of the map, so draw it in up to four parts
+    if lcdc.bg_tile_map() as usize == view.map {
+        let scx = io[0x43] as f32;
+        let scy = io[0x42] as f32;
+        for offset_x in [0.0, -256.0] {
+            for offset_y in [0.0, -256.0] {
+                let rect = Rect::from_min_max(
+                    to_screen(scx + offset_x, scy + offset_y),
+                    to_screen(scx + offset_x + 160.0, scy + offset_y + 144.0),
+                );
+                painter.rect_stroke(
+                    rect,
+                    0.0,
+                    Stroke::new(2.0, Color32::RED),
+                    StrokeKind::Inside,
+                );
+            }
+        }
+    }
+
+    // The window always starts at the top left of its map
+    if lcdc.window_enable() && lcdc.window_tile_map() as usize == view.map {
+        let window_width = 167.0 - io[0x4B] as f32;
+        let window_height = 144.0 - io[0x4A] as f32;
+        if window_width > 0.0 && window_height > 0.0 {
+            painter.rect_stroke(
+                Rect::from_min_max(to_screen(0.0, 0.0), to_screen(window_width, window_height)),
+                0.0,
+                Stroke::new(2.0, Color32::LIGHT_BLUE),
+                StrokeKind::Inside,
+            );
+        }
+    }
+
+    if let Some(pointer) = response.hover_pos() {
+        let x = ((pointer.x - response.rect.left()) / 2.0 / 8.0) as usize;
+        let y = ((pointer.y - response.rect.top()) / 2.0 / 8.0) as usize;
+        if x < 32 && y < 32 {
+            let map_index = y * 32 + x;
+            let tile_id = vram[map_base + map_index];
+            let tile = tile_data_index(tile_id, lcdc.tile_addressing_mode());
+            response.on_hover_text(format!(
+                "Map address {:#06X}\nTile {:#04X}\nTile address {:#06X}",
+                0x8000 + map_base + map_index,
+                tile_id,
+                0x8000 + tile * 16
+            ));
+        }
+    }
+
+    if ui.button("Export PNG").clicked() {
+        export_png(&image);
+    }
+}
+
+fn render_oam(
+    ui: &mut Ui,
+    ui_context: &mut UIContext,
+    egui_context: &Context,
+    vram: &[u8],
+    oam: &[u8],
+    io: &[u8],
+) {
+    let view = &mut ui_context.vram_view;
+    let lcdc = LCDC::new_with_raw_value(io[0x40]);
+    let height = if lcdc.obj_size() { 16 } else { 8 };
+
+    // All sprites side by side, used for the export
+    let mut sheet = ColorImage::new([40 * 8, height], Color32::TRANSPARENT);
+    let mut sprites = Vec::new();
+    for index in 0..40 {
+        let y = oam[index * 4];
+        let x = oam[index * 4 + 1];
+        let mut tile = oam[index * 4 + 2] as usize;
+        if height == 16 {
+            tile &= 0xFE;
+        }
+        let attributes = OAMAttributes::new_with_raw_value(oam[index * 4 + 3]);
+        let palette = if attributes.dmg_palette() {
+            io[0x49]
+        } else {
+            io[0x48]
+        };
+
+        let mut image = ColorImage::new([8, height], Color32::TRANSPARENT);
+        for part in 0..height / 8 {
+            // With y flip the tiles of an 8x16 sprite swap places as well
+            let part_y = if attributes.y_flip() {
+                height - 8 - part * 8
+            } else {
+                part * 8
+            };
+            draw_tile(
+                &mut image,
+                vram,
+                tile + part,
+                palette,
+                0,
+                part_y,
+                attributes.x_flip(),
+                attributes.y_flip(),
+                true,
+            );
+        }
+        for pixel_y in 0..height {
+            for pixel_x in 0..8 {
+                sheet[(index * 8 + pixel_x, pixel_y)] = image[(pixel_x, pixel_y)];
+            }
+        }
+        sprites.push((index, x, y, tile, attributes, image));
+    }
+
+    if ui.button("Export PNG").clicked() {
+        export_png(&sheet);
+    }
+
+    let oam_table = TableBuilder::new(ui)
+        .id_salt("vram_oam")
+        .striped(true)
+        .resizable(false)
+        .max_scroll_height(500.0)
+        .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+        .column(Column::auto())
+        .column(Column::auto())
+        .columns(Column::auto().at_least(30.0), 3)
+        .column(Column::remainder());
+
+    oam_table
+        .header(20.0, |mut header| {
+            for name in ["#", "Sprite", "X", "Y", "Tile", "Attributes"] {
+                header.col(|ui| {
+                    ui.strong(name);
+                });
+            }
+        })
+        .body(|mut body| {
+            for (index, x, y, tile, attributes, image) in &sprites {
+                body.row(height as f32 * 2.0 + 4.0, |mut row| {
+                    row.col(|ui| {
+                        ui.label(index.to_string());
+                    });
+                    row.col(|ui| {
+                        let response = show_image(
+                            ui,
+                            view,
+                            egui_context,
+                            &format!("oam_{}", index),
+                            image,
+                            2.0,
+                        );
+                        response.on_hover_text(format!(
+                            "OAM address {:#06X}\nTile {:#04X}\nTile address {:#06X}",
+                            0xFE00 + index * 4,
+                            tile,
+                            0x8000 + tile * 16
+                        ));
+                    });
+                    row.col(|ui| {
+                        // Stored with an offset of 8 horizontally and 16 vertically
+                        ui.label(format!("{}", *x as i16 - 8));
+                    });
+                    row.col(|ui| {
+                        ui.label(format!("{}", *y as i16 - 16));
+                    });
+                    row.col(|ui| {
+                        ui.label(format!("{:02X}", tile));
+                    });
+                    row.col(|ui| {
+                        let mut text = vec![if attributes.dmg_palette() {
+                            "OBP1"
+                        } else {
+                            "OBP0"
+                        }];
+                        if attributes.priority() {
+                            text.push("behind BG");
+                        }
+                        if attributes.x_flip() {
+                            text.push("X flip");
+                        }
+                        if attributes.y_flip() {
+                            text.push("Y flip");
+                        }
+                        ui.label(text.join(", "));
+                    });
+                });
+            }
+        });
+}
+
+// Converts a tile id from a map to an index into the 384 tiles of the tile data
+fn tile_data_index(tile_id: u8, unsigned_addressing: bool) -> usize {
+    if unsigned_addressing {
+        tile_id as usize
+    } else {
+        (256 + tile_id as i8 as isize) as usize
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_tile(
+    image: &mut ColorImage,
+    vram: &[u8],
+    tile: usize,
+    palette: u8,
+    x: usize,
+    y: usize,
+    x_flip: bool,
+    y_flip: bool,
+    // Objects don't draw color 0
+    transparent: bool,
+) {
+    for row in 0..8 {
+        let tile_lo = vram[tile * 16 + row * 2];
+        let tile_hi = vram[tile * 16 + row * 2 + 1];
+        for column in 0..8 {
+            let idx = 7 - column;
+            let color_id = (((tile_hi >> idx) & 1) << 1) | ((tile_lo >> idx) & 1);
+            let shade = (palette >> (color_id * 2)) & 0b11;
+            let color = match shade {
+                0 => 0xFF,
+                1 => 0xAA,
+                2 => 0x55,
+                _ => 0x00,
+            };
+            let pixel_x = if x_flip { 7 - column } else { column };
+            let pixel_y = if y_flip { 7 - row } else { row };
+            image[(x + pixel_x, y + pixel_y)] = if transparent && color_id == 0 {
+                Color32::TRANSPARENT
+            } else {
+                Color32::from_gray(color)
+            };
+        }
+    }
+}
+
+fn show_image(
+    ui: &mut Ui,
+    view: &mut VramViewState,
+    egui_context: &Context,
+    name: &str,
+    image: &ColorImage,
+    scale: f32,
+) -> egui::Response {
+    if let Some(texture) = view.textures.get_mut(name) {
+        texture.set(image.clone(), TextureOptions::NEAREST);
+    } else {
+        let texture = egui_context.load_texture(name, image.clone(), TextureOptions::NEAREST);
+        view.textures.insert(name.to_string(), texture);
+    }
+    let texture = &view.textures[name];
+    let size = texture.size_vec2() * scale;
+    let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+    ui.painter().image(
+        texture.id(),
+        rect,
+        Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+        Color32::WHITE,
+    );
+    response
+}
+
+fn export_png(image: &ColorImage) {
+    let path = FileDialog::new().add_filter("png", &["png"]).save_file();
+    if let Some(path) = path {
+        let pixels: Vec<u8> = image
+            .pixels
+            .iter()
+            .flat_map(|pixel| pixel.to_array())
+            .collect();
+        if let Err(err) = image::save_buffer(
+            &path,
+            &pixels,
+            image.size[0] as u32,
+            image.size[1] as u32,
+            image::ColorType::Rgba8,
+        ) {
+            log!(Level::Error, "Failed to export PNG: {}", err);
+        }
+    }
+}
+
+fn palette_name(palette: TilePalette) -> &'static str {
+    match palette {
+        TilePalette::Identity => "Identity",
+        TilePalette::BGP => "BGP",
+        TilePalette::OBP0 => "OBP0",
+        TilePalette::OBP1 => "OBP1",
+    }
+}
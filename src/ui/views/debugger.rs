@@ -19,7 +19,7 @@ pub(crate) fn render(
     egui::SidePanel::left("left_panel")
         .resizable(false)
         .show(egui_context, |ui| {
-            components::memory_viewer::render(ui, egui_context, ui_state, emu_state);
+            components::memory_viewer::render(ui, ui_context, egui_context, ui_state, emu_state);
         });
 
     egui::SidePanel::right("right_panel")
@@ -0,0 +1,331 @@
+use crate::audio::output_devices;
+use crate::config::{update_config, CustomPalette, PaletteSelection, RendererBackend};
+use crate::emulator::{EmulatorControlMessage, EmulatorState};
+use crate::gb::filters::{FrameBlending, Upscaler};
+use crate::gb::palette::{PalettePreset, Rgb};
+use crate::gb::screenshot::ScreenshotMode;
+use crate::ui::{components, UIContext, UIState};
+use egui::{Context, Ui};
+
+const SAMPLE_RATES: [u32; 4] = [32000, 44100, 48000, 96000];
+
+pub(crate) fn render(
+    egui_context: &Context,
+    ui_state: &mut UIState,
+    emu_state: &EmulatorState,
+    ui_context: &mut UIContext,
+) {
+    egui::TopBottomPanel::top("menu_bar").show(egui_context, |ui| {
+        components::menu_bar::render(ui, egui_context, ui_state, emu_state);
+    });
+
+    egui::CentralPanel::default().show(egui_context, |ui| {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.heading("Audio");
+            audio_settings(ui, ui_state, ui_context);
+
+            ui.separator();
+            ui.heading("Palette");
+            palette_settings(ui, ui_context, emu_state);
+
+            ui.separator();
+            ui.heading("Filters");
+            filter_settings(ui, ui_context);
+
+            ui.separator();
+            ui.heading("Renderer");
+            renderer_settings(ui, ui_context);
+
+            ui.separator();
+            ui.heading("Screenshots");
+            screenshot_settings(ui, ui_context);
+        });
+    });
+}
+
+fn audio_settings(ui: &mut Ui, ui_state: &mut UIState, ui_context: &mut UIContext) {
+    let settings = &mut ui_context.settings;
+    if settings.audio_devices.is_none() {
+        settings.audio_devices = Some(output_devices());
+    }
+
+    egui::Grid::new("audio_settings")
+        .num_columns(2)
+        .spacing([24.0, 8.0])
+        .show(ui, |ui| {
+            ui.label("Volume");
+            ui.add(egui::Slider::new(&mut ui_state.volume, 0.0..=100.0).suffix("%"));
+            ui.end_row();
+
+            ui.label("Output device");
+            ui.horizontal(|ui| {
+                let audio = &mut settings.audio;
+                egui::ComboBox::from_id_salt("audio_output_device")
+                    .selected_text(audio.output_device.as_deref().unwrap_or("Default"))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut audio.output_device, None, "Default");
+                        for device in settings.audio_devices.iter().flatten() {
+                            ui.selectable_value(
+                                &mut audio.output_device,
+                                Some(device.clone()),
+                                device,
+                            );
+                        }
+                    });
+                if ui.button("Refresh").clicked() {
+                    settings.audio_devices = None;
+                }
+            });
+            ui.end_row();
+
+            let audio = &mut settings.audio;
+            ui.label("Sample rate");
+            egui::ComboBox::from_id_salt("audio_sample_rate")
+                .selected_text(format!("{} Hz", audio.sample_rate))
+                .show_ui(ui, |ui| {
+                    for rate in SAMPLE_RATES {
+                        ui.selectable_value(&mut audio.sample_rate, rate, format!("{} Hz", rate));
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Latency");
+            ui.add(egui::Slider::new(&mut audio.latency_ms, 20..=250).suffix(" ms"));
+            ui.end_row();
+
+            ui.label("Dynamic rate control")
+                .on_hover_text("Slightly adjusts the resampling ratio to keep the buffer filled");
+            ui.checkbox(&mut audio.dynamic_rate_control, "");
+            ui.end_row();
+        });
+
+    if ui.button("Apply").clicked() {
+        let audio = settings.audio.clone();
+        update_config(|config| config.audio_config = audio);
+        ui_state
+            .tx_ui
+            .send(EmulatorControlMessage::ReloadAudio)
+            .expect("Failed to send control message to emulator thread");
+    }
+}
+
+fn palette_settings(ui: &mut Ui, ui_context: &mut UIContext, emu_state: &EmulatorState) {
+    let EmulatorState::GameBoy(gb_state) = emu_state;
+    let palette = &mut ui_context.settings.palette;
+
+    egui::Grid::new("palette_settings")
+        .num_columns(2)
+        .spacing([24.0, 8.0])
+        .show(ui, |ui| {
+            ui.label("Palette");
+            let mut selected = Some(palette.selected.clone());
+            selection_combo(ui, "palette_global", &mut selected, &palette.custom, None);
+            if let Some(selected) = selected {
+                palette.selected = selected;
+            }
+            ui.end_row();
+
+            if let Some(rom_name) = &gb_state.rom_name {
+                ui.label(format!("Palette for {}", rom_name));
+                let mut game = palette.per_game.get(rom_name).cloned();
+                selection_combo(
+                    ui,
+                    "palette_game",
+                    &mut game,
+                    &palette.custom,
+                    Some("Use global palette"),
+                );
+                match game {
+                    Some(game) => {
+                        palette.per_game.insert(rom_name.clone(), game);
+                    }
+                    None => {
+                        palette.per_game.remove(rom_name);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+
+    ui.add_space(8.0);
+    ui.label("Custom palettes");
+    let mut removed = None;
+    for (index, custom) in palette.custom.iter_mut().enumerate() {
+        ui.push_id(index, |ui| {
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut custom.name);
+                if ui.button("Remove").clicked() {
+                    removed = Some(index);
+                }
+            });
+            egui::Grid::new("custom_palette_colors").show(ui, |ui| {
+                color_row(ui, "BG", &mut custom.palette.bg);
+                color_row(ui, "OBJ0", &mut custom.palette.obj0);
+                color_row(ui, "OBJ1", &mut custom.palette.obj1);
+            });
+        });
+    }
+    if let Some(index) = removed {
+        palette.custom.remove(index);
+    }
+
+    if ui.button("Add custom palette").clicked() {
+        palette.custom.push(CustomPalette {
+            name: format!("Custom {}", palette.custom.len() + 1),
+            palette: PalettePreset::Grayscale.palette(),
+        });
+    }
+
+    ui.add_space(8.0);
+    if ui.button("Apply").clicked() {
+        let palette = palette.clone();
+        update_config(|config| config.palette_config = palette);
+    }
+}
+
+fn filter_settings(ui: &mut Ui, ui_context: &mut UIContext) {
+    let filters = &mut ui_context.settings.filters;
+
+    egui::Grid::new("filter_settings")
+        .num_columns(2)
+        .spacing([24.0, 8.0])
+        .show(ui, |ui| {
+            ui.label("Upscaling");
+            egui::ComboBox::from_id_salt("filter_upscaler")
+                .selected_text(filters.upscaler.name())
+                .show_ui(ui, |ui| {
+                    for upscaler in Upscaler::ALL {
+                        ui.selectable_value(&mut filters.upscaler, upscaler, upscaler.name());
+                    }
+                });
+            ui.end_row();
+
+            ui.label("LCD grid");
+            ui.checkbox(&mut filters.lcd_grid, "");
+            ui.end_row();
+
+            ui.label("Frame blending")
+                .on_hover_text("Smooths out flicker that games use for transparency");
+            egui::ComboBox::from_id_salt("filter_blending")
+                .selected_text(filters.blending.name())
+                .show_ui(ui, |ui| {
+                    for blending in FrameBlending::ALL {
+                        ui.selectable_value(&mut filters.blending, blending, blending.name());
+                    }
+                });
+            ui.end_row();
+        });
+
+    if ui.button("Apply").clicked() {
+        let filters = *filters;
+        update_config(|config| config.filter_config = filters);
+    }
+}
+
+fn renderer_settings(ui: &mut Ui, ui_context: &mut UIContext) {
+    let renderer = &mut ui_context.settings.renderer;
+
+    egui::Grid::new("renderer_settings")
+        .num_columns(2)
+        .spacing([24.0, 8.0])
+        .show(ui, |ui| {
+            ui.label("Backend").on_hover_text(
+                "Software draws the game screen without the Vulkan pipeline, for drivers that can't run it",
+            );
+            egui::ComboBox::from_id_salt("renderer_backend")
+                .selected_text(format!("{:?}", renderer))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(renderer, RendererBackend::Vulkan, "Vulkan");
+                    ui.selectable_value(renderer, RendererBackend::Software, "Software");
+                });
+            ui.end_row();
+        });
+
+    ui.label("Changes take effect after a restart");
+    if ui.button("Apply").clicked() {
+        let renderer = *renderer;
+        update_config(|config| config.renderer = renderer);
+    }
+}
+
+fn screenshot_settings(ui: &mut Ui, ui_context: &mut UIContext) {
+    let mode = &mut ui_context.settings.screenshot_mode;
+
+    egui::Grid::new("screenshot_settings")
+        .num_columns(2)
+        .spacing([24.0, 8.0])
+        .show(ui, |ui| {
+            ui.label("Format").on_hover_text(
+                "Raw stores the color IDs of the frame, which is useful for test fixtures",
+            );
+            egui::ComboBox::from_id_salt("screenshot_mode")
+                .selected_text(mode.name())
+                .show_ui(ui, |ui| {
+                    for option in ScreenshotMode::ALL {
+                        ui.selectable_value(mode, option, option.name());
+                    }
+                });
+            ui.end_row();
+        });
+
+    if ui.button("Apply").clicked() {
+        let mode = *mode;
+        update_config(|config| config.screenshot_mode = mode);
+    }
+}
+
+fn color_row(ui: &mut Ui, label: &str, colors: &mut [Rgb; 4]) {
+    ui.label(label);
+    for color in colors.iter_mut() {
+        ui.color_edit_button_srgb(color);
+    }
+    ui.end_row();
+}
+
+// None is only offered when a label for it is given
+fn selection_combo(
+    ui: &mut Ui,
+    id: &str,
+    selection: &mut Option<PaletteSelection>,
+    custom: &[CustomPalette],
+    none_label: Option<&str>,
+) {
+    let selected_text = match selection {
+        Some(selection) => selection_name(selection),
+        None => none_label.unwrap_or_default().to_string(),
+    };
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(selected_text)
+        .show_ui(ui, |ui| {
+            if let Some(none_label) = none_label {
+                ui.selectable_value(selection, None, none_label);
+            }
+            for preset in PalettePreset::ALL {
+                ui.selectable_value(
+                    selection,
+                    Some(PaletteSelection::Preset(preset)),
+                    preset.name(),
+                );
+            }
+            for palette in custom {
+                ui.selectable_value(
+                    selection,
+                    Some(PaletteSelection::Custom(palette.name.clone())),
+                    &palette.name,
+                );
+            }
+            ui.selectable_value(
+                selection,
+                Some(PaletteSelection::Automatic),
+                "Automatic (CGB)",
+            );
+        });
+}
+
+fn selection_name(selection: &PaletteSelection) -> String {
+    match selection {
+        PaletteSelection::Preset(preset) => preset.name().to_string(),
+        PaletteSelection::Custom(name) => name.clone(),
+        PaletteSelection::Automatic => "Automatic (CGB)".to_string(),
+    }
+}
@@ -11,12 +11,15 @@ use vulkano::command_buffer::{
 };
 use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
 use vulkano::descriptor_set::{DescriptorSet, WriteDescriptorSet};
-use vulkano::device::{DeviceExtensions, DeviceFeatures};
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::device::{
+    Device, DeviceCreateInfo, DeviceExtensions, DeviceFeatures, QueueCreateInfo, QueueFlags,
+};
 use vulkano::format::Format;
 use vulkano::image::sampler::{Sampler, SamplerCreateInfo};
 use vulkano::image::view::ImageView;
 use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
-use vulkano::instance::InstanceCreateInfo;
+use vulkano::instance::{Instance, InstanceCreateInfo};
 use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
 use vulkano::pipeline::graphics::color_blend::{
     AttachmentBlend, ColorBlendAttachmentState, ColorBlendState,
@@ -37,7 +40,7 @@ use vulkano::pipeline::{
 use vulkano::render_pass::{AttachmentLoadOp, AttachmentStoreOp};
 use vulkano::swapchain::{PresentMode, Surface};
 use vulkano::sync::GpuFuture;
-use vulkano::DeviceSize;
+use vulkano::{DeviceSize, VulkanLibrary};
 use vulkano_util::context::{VulkanoConfig, VulkanoContext};
 use vulkano_util::window::{VulkanoWindows, WindowDescriptor, WindowMode};
 use winit::event_loop::{ActiveEventLoop, EventLoop};
@@ -55,6 +58,10 @@ pub(crate) trait EmulatorRenderer: Send {
         vulkano_context: &VulkanoContext,
         vulkano_windows: &VulkanoWindows,
     );
+    // Renderers that present through an egui texture instead of a paint callback return it here
+    fn texture(&mut self, _egui_context: &egui::Context) -> Option<egui::TextureId> {
+        None
+    }
 }
 
 pub(crate) struct VulkanRenderer {
@@ -75,22 +82,48 @@ struct RenderContext {
 }
 
 impl VulkanRenderer {
-    pub(crate) fn new(event_loop: &EventLoop<()>) -> Self {
+    // Fails when there is no Vulkan driver or no device that supports what the renderers need,
+    // the caller then falls back to presenting through SoftwarePresenter
+    pub(crate) fn new(event_loop: &EventLoop<()>) -> Result<Self, String> {
+        let device_extensions = DeviceExtensions {
+            khr_swapchain: true,
+            khr_dynamic_rendering: true,
+            ..Default::default()
+        };
+        let device_features = DeviceFeatures {
+            dynamic_rendering: true,
+            ..Default::default()
+        };
+        let instance_create_info = InstanceCreateInfo {
+            enabled_extensions: Surface::required_extensions(event_loop)
+                .map_err(|err| err.to_string())?,
+            application_name: Some("Mnemosyne".parse().unwrap()),
+            ..Default::default()
+        };
+        let device_filter = Arc::new(move |physical_device: &PhysicalDevice| {
+            physical_device
+                .supported_extensions()
+                .contains(&device_extensions)
+                && physical_device
+                    .supported_features()
+                    .contains(&device_features)
+                && graphics_queue_family(physical_device).is_some()
+        });
+
+        // VulkanoContext::new panics on every error, so first create the instance and a device
+        // the same way it does
+        check_vulkan_support(
+            instance_create_info.clone(),
+            device_extensions,
+            device_features,
+            device_filter.as_ref(),
+        )?;
+
         let context = VulkanoContext::new(VulkanoConfig {
-            device_extensions: DeviceExtensions {
-                khr_swapchain: true,
-                khr_dynamic_rendering: true,
-                ..Default::default()
-            },
-            device_features: DeviceFeatures {
-                dynamic_rendering: true,
-                ..Default::default()
-            },
-            instance_create_info: InstanceCreateInfo {
-                enabled_extensions: Surface::required_extensions(event_loop).unwrap(),
-                application_name: Some("Mnemosyne".parse().unwrap()),
-                ..Default::default()
-            },
+            device_extensions,
+            device_features,
+            instance_create_info,
+            device_filter_fn: device_filter,
             ..Default::default()
         });
         let windows = VulkanoWindows::default();
@@ -131,7 +164,7 @@ impl VulkanRenderer {
         //         .unwrap();
         // }
 
-        VulkanRenderer {
+        Ok(VulkanRenderer {
             context,
             windows,
             command_buffer_allocator,
@@ -142,7 +175,7 @@ impl VulkanRenderer {
             // texture_sampler,
             emulator_renderer: None,
             rcx: None,
-        }
+        })
     }
 
     pub(crate) fn set_emulator_renderer(
@@ -330,3 +363,43 @@ impl VulkanRenderer {
         self.rcx = Some(RenderContext { viewport });
     }
 }
+
+fn graphics_queue_family(physical_device: &PhysicalDevice) -> Option<u32> {
+    physical_device
+        .queue_family_properties()
+        .iter()
+        .position(|properties| properties.queue_flags.intersects(QueueFlags::GRAPHICS))
+        .map(|index| index as u32)
+}
+
+fn check_vulkan_support(
+    instance_create_info: InstanceCreateInfo,
+    device_extensions: DeviceExtensions,
+    device_features: DeviceFeatures,
+    device_filter: &dyn Fn(&PhysicalDevice) -> bool,
+) -> Result<(), String> {
+    let library = VulkanLibrary::new().map_err(|err| err.to_string())?;
+    let instance = Instance::new(library, instance_create_info).map_err(|err| err.to_string())?;
+    let physical_device = instance
+        .enumerate_physical_devices()
+        .map_err(|err| err.to_string())?
+        .find(|physical_device| device_filter(physical_device))
+        .ok_or("No Vulkan device supports swapchains and dynamic rendering")?;
+    let queue_family_index =
+        graphics_queue_family(&physical_device).ok_or("No Vulkan device has a graphics queue")?;
+
+    Device::new(
+        physical_device,
+        DeviceCreateInfo {
+            queue_create_infos: vec![QueueCreateInfo {
+                queue_family_index,
+                ..Default::default()
+            }],
+            enabled_extensions: device_extensions,
+            enabled_features: device_features,
+            ..Default::default()
+        },
+    )
+    .map_err(|err| err.to_string())?;
+    Ok(())
+}
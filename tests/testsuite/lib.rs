@@ -1,28 +1,76 @@
+use std::path::Path;
+use Mnemosyne::audio::NullSink;
+use Mnemosyne::gb::filters::FilterSettings;
+use Mnemosyne::gb::palette::PalettePreset;
+use Mnemosyne::gb::run_until::{StopCondition, CYCLES_PER_FRAME};
+use Mnemosyne::gb::screenshot::{Screenshot, ScreenshotMode};
 use Mnemosyne::gb::GameBoy;
 
 mod test_blargg;
 mod test_dmg_acid2;
 mod test_mooneye_test_suite;
 mod test_mooneye_test_suite_wilbertpol;
+mod test_rom_matrix;
+mod test_sm83;
+
+// Mooneye ROMs finish within a few seconds, this only guards against hangs. Counted in M-cycles as
+// no frames are produced while the LCD is off
+const MOONEYE_CYCLE_LIMIT: u64 = 1200 * CYCLES_PER_FRAME;
 
 fn setup(rom: &str) -> GameBoy {
-    let mut gameboy = GameBoy::new();
+    let mut gameboy = GameBoy::with_audio_sink(Box::new(NullSink::default()));
     gameboy.load_rom(rom);
     gameboy.skip_boot_rom();
     gameboy
 }
 
+// Compares the color IDs of the last frame with a grayscale reference screenshot, where 0xFF is
+// color 0 and 0x00 is color 3. Returns the number of pixels that differ
+fn screen_differences(gameboy: &GameBoy, reference: impl AsRef<Path>) -> Result<usize, String> {
+    let output = gameboy.screenshot(
+        ScreenshotMode::Raw,
+        &PalettePreset::Grayscale.palette(),
+        &FilterSettings::default(),
+    );
+    let expected = Screenshot::load_png(reference, true)?;
+    if (output.width, output.height) != (expected.width, expected.height) {
+        return Err(format!(
+            "Reference is {}x{}",
+            expected.width, expected.height
+        ));
+    }
+
+    Ok(output
+        .pixels
+        .iter()
+        .zip(&expected.pixels)
+        .filter(|(color, luma)| **color != 3 - ((**luma as u16 * 3 + 127) / 255) as u8)
+        .count())
+}
+
+fn assert_screen_matches(gameboy: &GameBoy, reference: &str) {
+    let different = screen_differences(gameboy, reference).unwrap();
+    assert_eq!(
+        different, 0,
+        "{} pixels differ from {}",
+        different, reference
+    );
+}
+
 fn run_mooneye_test(rom: &str) {
-    let mut gameboy = GameBoy::new();
+    let mut gameboy = GameBoy::with_audio_sink(Box::new(NullSink::default()));
     gameboy.load_rom(rom);
     gameboy.skip_boot_rom();
 
-    loop {
-        let (breakpoint, _) = gameboy.tick();
-        if breakpoint {
-            break;
-        }
-    }
+    let reason = gameboy.run_until(&[
+        StopCondition::SoftwareBreakpoint,
+        StopCondition::Cycles(MOONEYE_CYCLE_LIMIT),
+    ]);
+    assert_eq!(
+        reason.condition,
+        StopCondition::SoftwareBreakpoint,
+        "Test did not finish"
+    );
 
     let register = gameboy.dump_registers();
     assert_eq!(
@@ -1,14 +1,26 @@
-use image::{GenericImageView, ImageReader};
+use crate::assert_screen_matches;
+use Mnemosyne::audio::NullSink;
+use Mnemosyne::gb::run_until::{StopCondition, CYCLES_PER_FRAME};
 use Mnemosyne::gb::GameBoy;
 
 pub(crate) fn setup(rom: &str) -> GameBoy {
-    let mut gameboy = GameBoy::new();
+    let mut gameboy = GameBoy::with_audio_sink(Box::new(NullSink::default()));
     gameboy.load_rom(rom);
     gameboy.skip_boot_rom();
     gameboy
 }
 
+// Blargg ROMs report their result on serial, the cycle limit only guards against hangs and also
+// holds while the LCD is off
+fn run_until_passed(gameboy: &mut GameBoy) {
+    gameboy.run_until(&[
+        StopCondition::SerialContains("Passed\n".to_string()),
+        StopCondition::Cycles(3600 * CYCLES_PER_FRAME),
+    ]);
+}
+
 mod cpu_instrs {
+    use super::run_until_passed;
     use crate::setup;
 
     #[test]
@@ -17,9 +29,7 @@ mod cpu_instrs {
             "./tests/game-boy-test-roms/artifacts/blargg/cpu_instrs/individual/01-special.gb",
         );
 
-        for _ in 0..1500000 {
-            gameboy.tick();
-        }
+        run_until_passed(&mut gameboy);
         let serial_data = gameboy.serial_buffer();
         println!("{:}", serial_data.iter().collect::<String>());
         let expected_data = [
@@ -35,9 +45,7 @@ mod cpu_instrs {
             "./tests/game-boy-test-roms/artifacts/blargg/cpu_instrs/individual/02-interrupts.gb",
         );
 
-        for _ in 0..200000 {
-            gameboy.tick();
-        }
+        run_until_passed(&mut gameboy);
         let serial_data = gameboy.serial_buffer();
         println!("{:}", serial_data.iter().collect::<String>());
         let expected_data = [
@@ -53,9 +61,7 @@ mod cpu_instrs {
             "./tests/game-boy-test-roms/artifacts/blargg/cpu_instrs/individual/03-op sp,hl.gb",
         );
 
-        for _ in 0..1250000 {
-            gameboy.tick();
-        }
+        run_until_passed(&mut gameboy);
         let serial_data = gameboy.serial_buffer();
         println!("{:}", serial_data.iter().collect::<String>());
         let expected_data = [
@@ -71,9 +77,7 @@ mod cpu_instrs {
             "./tests/game-boy-test-roms/artifacts/blargg/cpu_instrs/individual/04-op r,imm.gb",
         );
 
-        for _ in 0..1500000 {
-            gameboy.tick();
-        }
+        run_until_passed(&mut gameboy);
         let serial_data = gameboy.serial_buffer();
         println!("{:}", serial_data.iter().collect::<String>());
         let expected_data = [
@@ -88,9 +92,7 @@ mod cpu_instrs {
         let mut gameboy =
             setup("./tests/game-boy-test-roms/artifacts/blargg/cpu_instrs/individual/05-op rp.gb");
 
-        for _ in 0..2000000 {
-            gameboy.tick();
-        }
+        run_until_passed(&mut gameboy);
         let serial_data = gameboy.serial_buffer();
         println!("{:}", serial_data.iter().collect::<String>());
         let expected_data = [
@@ -105,9 +107,7 @@ mod cpu_instrs {
         let mut gameboy =
             setup("./tests/game-boy-test-roms/artifacts/blargg/cpu_instrs/individual/06-ld r,r.gb");
 
-        for _ in 0..300000 {
-            gameboy.tick();
-        }
+        run_until_passed(&mut gameboy);
         let serial_data = gameboy.serial_buffer();
         println!("{:}", serial_data.iter().collect::<String>());
         let expected_data = [
@@ -123,9 +123,7 @@ mod cpu_instrs {
             "./tests/game-boy-test-roms/artifacts/blargg/cpu_instrs/individual/07-jr,jp,call,ret,rst.gb",
         );
 
-        for _ in 0..400000 {
-            gameboy.tick();
-        }
+        run_until_passed(&mut gameboy);
         let serial_data = gameboy.serial_buffer();
         println!("{:}", serial_data.iter().collect::<String>());
         let expected_data = [
@@ -141,9 +139,7 @@ mod cpu_instrs {
             "./tests/game-boy-test-roms/artifacts/blargg/cpu_instrs/individual/08-misc instrs.gb",
         );
 
-        for _ in 0..260000 {
-            gameboy.tick();
-        }
+        run_until_passed(&mut gameboy);
         let serial_data = gameboy.serial_buffer();
         println!("{:}", serial_data.iter().collect::<String>());
         let expected_data = [
@@ -158,9 +154,7 @@ mod cpu_instrs {
         let mut gameboy =
             setup("./tests/game-boy-test-roms/artifacts/blargg/cpu_instrs/individual/09-op r,r.gb");
 
-        for _ in 0..5000000 {
-            gameboy.tick();
-        }
+        run_until_passed(&mut gameboy);
         let serial_data = gameboy.serial_buffer();
         println!("{:}", serial_data.iter().collect::<String>());
         let expected_data = [
@@ -176,9 +170,7 @@ mod cpu_instrs {
             "./tests/game-boy-test-roms/artifacts/blargg/cpu_instrs/individual/10-bit ops.gb",
         );
 
-        for _ in 0..7000000 {
-            gameboy.tick();
-        }
+        run_until_passed(&mut gameboy);
         let serial_data = gameboy.serial_buffer();
         println!("{:}", serial_data.iter().collect::<String>());
         let expected_data = [
@@ -194,9 +186,7 @@ mod cpu_instrs {
             "./tests/game-boy-test-roms/artifacts/blargg/cpu_instrs/individual/11-op a,(hl).gb",
         );
 
-        for _ in 0..8000000 {
-            gameboy.tick();
-        }
+        run_until_passed(&mut gameboy);
         let serial_data = gameboy.serial_buffer();
         println!("{:}", serial_data.iter().collect::<String>());
         let expected_data = [
@@ -211,113 +201,54 @@ mod cpu_instrs {
 fn test_oam_bug() {
     let mut gameboy = setup("./tests/game-boy-test-roms/artifacts/blargg/oam_bug/oam_bug.gb");
 
-    let mut cycles = 0;
-    while cycles < (21.0 * 4194304.0 / 4.0) as u64 {
-        let (hit_breakpoint_now, cycles_spent) = gameboy.tick();
-        cycles += cycles_spent as u64;
-    }
-    let mut output_img = vec![0; 160 * 144 * 4];
-    let frame_buffer = gameboy.get_framebuffer();
-
-    for idx in 0..(160 * 144) {
-        let color: u8 = match frame_buffer[idx] {
-            0 => 0xFF,
-            1 => 0xAA,
-            2 => 0x55,
-            3 => 0x00,
-            _ => panic!("Received invalid color code"),
-        };
-        output_img[idx * 4] = color;
-        output_img[idx * 4 + 1] = color;
-        output_img[idx * 4 + 2] = color;
-        output_img[idx * 4 + 3] = 0xFF;
-    }
-
-    let img =
-        ImageReader::open("./tests/game-boy-test-roms/artifacts/blargg/oam_bug/oam_bug-dmg.png")
-            .unwrap()
-            .decode()
-            .unwrap();
-    let test = img.pixels().flat_map(|a| a.2.0).collect::<Vec<u8>>();
-
-    assert_eq!(output_img, test);
+    gameboy.run_until(&[StopCondition::Cycles(21 * 4194304 / 4)]);
+    assert_screen_matches(
+        &gameboy,
+        "./tests/game-boy-test-roms/artifacts/blargg/oam_bug/oam_bug-dmg.png",
+    );
 }
 
 #[test]
 fn test_dmg_sound() {
     let mut gameboy = setup("./tests/game-boy-test-roms/artifacts/blargg/dmg_sound/dmg_sound.gb");
 
-    let mut cycles = 0;
-    while cycles < (37.0 * 4194304.0 / 4.0) as u64 {
-        let (hit_breakpoint_now, cycles_spent) = gameboy.tick();
-        cycles += cycles_spent as u64;
-    }
-    let mut output_img = vec![0; 160 * 144 * 4];
-    let frame_buffer = gameboy.get_framebuffer();
-
-    for idx in 0..(160 * 144) {
-        let color: u8 = match frame_buffer[idx] {
-            0 => 0xFF,
-            1 => 0xAA,
-            2 => 0x55,
-            3 => 0x00,
-            _ => panic!("Received invalid color code"),
-        };
-        output_img[idx * 4] = color;
-        output_img[idx * 4 + 1] = color;
-        output_img[idx * 4 + 2] = color;
-        output_img[idx * 4 + 3] = 0xFF;
-    }
-
-    let img = ImageReader::open(
+    gameboy.run_until(&[StopCondition::Cycles(37 * 4194304 / 4)]);
+    assert_screen_matches(
+        &gameboy,
         "./tests/game-boy-test-roms/artifacts/blargg/dmg_sound/dmg_sound-dmg.png",
-    )
-    .unwrap()
-    .decode()
-    .unwrap();
-    let test = img.pixels().flat_map(|a| a.2.0).collect::<Vec<u8>>();
-
-    assert_eq!(output_img, test);
+    );
 }
 
 mod mem_timing {
-    use crate::setup;
-    use image::{GenericImageView, ImageReader};
+    use crate::{assert_screen_matches, setup};
+    use Mnemosyne::gb::run_until::{StopCondition, CYCLES_PER_FRAME};
+    use Mnemosyne::gb::GameBoy;
+
+    // These used to run a fixed number of instructions. Instructions take 1 to 6 M-cycles and
+    // the memory accesses these ROMs time take 2 to 4, so 4 M-cycles per instruction of the old
+    // count is an upper bound with margin. The ROMs that don't report on serial run into it
+    fn run_until_done(gameboy: &mut GameBoy, instructions: u64) {
+        gameboy.run_until(&[
+            StopCondition::SerialContains("Passed".to_string()),
+            StopCondition::Cycles(instructions * 4),
+        ]);
+        // The result is printed to serial before the text reaches the screen
+        gameboy.run_until(&[
+            StopCondition::Frames(3),
+            StopCondition::Cycles(3 * CYCLES_PER_FRAME),
+        ]);
+    }
 
     #[test]
     fn test_v1() {
         let mut gameboy =
             setup("./tests/game-boy-test-roms/artifacts/blargg/mem_timing/mem_timing.gb");
 
-        for _ in 0..700000 {
-            gameboy.tick();
-        }
-        let mut output_img = vec![0; 160 * 144 * 4];
-        let frame_buffer = gameboy.get_framebuffer();
-
-        for idx in 0..(160 * 144) {
-            let color: u8 = match frame_buffer[idx] {
-                0 => 0xFF,
-                1 => 0xAA,
-                2 => 0x55,
-                3 => 0x00,
-                _ => panic!("Received invalid color code"),
-            };
-            output_img[idx * 4] = color;
-            output_img[idx * 4 + 1] = color;
-            output_img[idx * 4 + 2] = color;
-            output_img[idx * 4 + 3] = 0xFF;
-        }
-
-        let img = ImageReader::open(
+        run_until_done(&mut gameboy, 700000);
+        assert_screen_matches(
+            &gameboy,
             "./tests/game-boy-test-roms/artifacts/blargg/mem_timing/mem_timing-dmg-cgb.png",
-        )
-        .unwrap()
-        .decode()
-        .unwrap();
-        let test = img.pixels().flat_map(|a| a.2.0).collect::<Vec<u8>>();
-
-        assert_eq!(output_img, test);
+        );
     }
 
     #[test]
@@ -325,35 +256,11 @@ mod mem_timing {
         let mut gameboy =
             setup("./tests/game-boy-test-roms/artifacts/blargg/mem_timing-2/mem_timing.gb");
 
-        for _ in 0..1250000 {
-            gameboy.tick();
-        }
-        let mut output_img = vec![0; 160 * 144 * 4];
-        let frame_buffer = gameboy.get_framebuffer();
-
-        for idx in 0..(160 * 144) {
-            let color: u8 = match frame_buffer[idx] {
-                0 => 0xFF,
-                1 => 0xAA,
-                2 => 0x55,
-                3 => 0x00,
-                _ => panic!("Received invalid color code"),
-            };
-            output_img[idx * 4] = color;
-            output_img[idx * 4 + 1] = color;
-            output_img[idx * 4 + 2] = color;
-            output_img[idx * 4 + 3] = 0xFF;
-        }
-
-        let img = ImageReader::open(
+        run_until_done(&mut gameboy, 1250000);
+        assert_screen_matches(
+            &gameboy,
             "./tests/game-boy-test-roms/artifacts/blargg/mem_timing-2/mem_timing-dmg-cgb.png",
-        )
-        .unwrap()
-        .decode()
-        .unwrap();
-        let test = img.pixels().flat_map(|a| a.2.0).collect::<Vec<u8>>();
-
-        assert_eq!(output_img, test);
+        );
     }
 }
 
@@ -362,9 +269,7 @@ fn test_instr_timing() {
     let mut gameboy =
         setup("./tests/game-boy-test-roms/artifacts/blargg/instr_timing/instr_timing.gb");
 
-    for _ in 0..300000 {
-        gameboy.tick();
-    }
+    run_until_passed(&mut gameboy);
     let serial_data = gameboy.serial_buffer();
     println!("{:}", serial_data.iter().collect::<String>());
     let expected_data = [
@@ -378,33 +283,9 @@ fn test_instr_timing() {
 fn test_halt_bug() {
     let mut gameboy = setup("./tests/game-boy-test-roms/artifacts/blargg/halt_bug.gb");
 
-    let mut cycles = 0;
-    while cycles < (2.0 * 4194304.0 / 4.0) as u64 {
-        let (hit_breakpoint_now, cycles_spent) = gameboy.tick();
-        cycles += cycles_spent as u64;
-    }
-    let mut output_img = vec![0; 160 * 144 * 4];
-    let frame_buffer = gameboy.get_framebuffer();
-
-    for idx in 0..(160 * 144) {
-        let color: u8 = match frame_buffer[idx] {
-            0 => 0xFF,
-            1 => 0xAA,
-            2 => 0x55,
-            3 => 0x00,
-            _ => panic!("Received invalid color code"),
-        };
-        output_img[idx * 4] = color;
-        output_img[idx * 4 + 1] = color;
-        output_img[idx * 4 + 2] = color;
-        output_img[idx * 4 + 3] = 0xFF;
-    }
-
-    let img = ImageReader::open("./tests/game-boy-test-roms/artifacts/blargg/halt_bug-dmg-cgb.png")
-        .unwrap()
-        .decode()
-        .unwrap();
-    let test = img.pixels().flat_map(|a| a.2.0).collect::<Vec<u8>>();
-
-    assert_eq!(output_img, test);
+    gameboy.run_until(&[StopCondition::Cycles(2 * 4194304 / 4)]);
+    assert_screen_matches(
+        &gameboy,
+        "./tests/game-boy-test-roms/artifacts/blargg/halt_bug-dmg-cgb.png",
+    );
 }
@@ -1,18 +1,23 @@
-use image::{GenericImageView, ImageReader};
+use crate::{assert_screen_matches, MOONEYE_CYCLE_LIMIT};
 use test_case::test_matrix;
+use Mnemosyne::audio::NullSink;
+use Mnemosyne::gb::run_until::StopCondition;
 use Mnemosyne::gb::GameBoy;
 
 fn run_mooneye_test(rom: &str) {
-    let mut gameboy = GameBoy::new();
+    let mut gameboy = GameBoy::with_audio_sink(Box::new(NullSink::default()));
     gameboy.load_rom(rom);
     gameboy.skip_boot_rom();
 
-    loop {
-        let (breakpoint, _) = gameboy.tick();
-        if breakpoint {
-            break;
-        }
-    }
+    let reason = gameboy.run_until(&[
+        StopCondition::SoftwareBreakpoint,
+        StopCondition::Cycles(MOONEYE_CYCLE_LIMIT),
+    ]);
+    assert_eq!(
+        reason.condition,
+        StopCondition::SoftwareBreakpoint,
+        "Test did not finish"
+    );
 
     let register = gameboy.dump_registers();
     assert_eq!(
@@ -142,43 +147,21 @@ fn emulator_only_mbc5(test: &str) {
 
 #[test]
 fn manual_only() {
-    let mut gameboy = GameBoy::new();
+    let mut gameboy = GameBoy::with_audio_sink(Box::new(NullSink::default()));
     gameboy.load_rom(
         "./tests/game-boy-test-roms/artifacts/mooneye-test-suite/manual-only/sprite_priority.gb",
     );
     gameboy.skip_boot_rom();
 
-    loop {
-        let (breakpoint, _) = gameboy.tick();
-        if breakpoint {
-            break;
-        }
-    }
-
-    let mut output_img = vec![0; 160 * 144 * 4];
-    let frame_buffer = gameboy.get_framebuffer();
-
-    for idx in 0..(160 * 144) {
-        let color: u8 = match frame_buffer[idx] {
-            0 => 0xFF,
-            1 => 0xAA,
-            2 => 0x55,
-            3 => 0x00,
-            _ => panic!("Received invalid color code"),
-        };
-        output_img[idx * 4] = color;
-        output_img[idx * 4 + 1] = color;
-        output_img[idx * 4 + 2] = color;
-        output_img[idx * 4 + 3] = 0xFF;
-    }
-
-    let img = ImageReader::open(
-            "./tests/game-boy-test-roms/artifacts/mooneye-test-suite/manual-only/sprite_priority-dmg.png",
-        )
-        .unwrap()
-        .decode()
-        .unwrap();
-    let test = img.pixels().flat_map(|a| a.2.0).collect::<Vec<u8>>();
-
-    assert_eq!(output_img, test);
+    let reason = gameboy.run_until(&[
+        StopCondition::SoftwareBreakpoint,
+        StopCondition::Cycles(MOONEYE_CYCLE_LIMIT),
+    ]);
+    assert_eq!(
+        reason.condition,
+        StopCondition::SoftwareBreakpoint,
+        "Test did not finish"
+    );
+
+    assert_screen_matches(&gameboy, "./tests/game-boy-test-roms/artifacts/mooneye-test-suite/manual-only/sprite_priority-dmg.png");
 }
@@ -1,17 +1,23 @@
+use crate::MOONEYE_CYCLE_LIMIT;
 use test_case::test_matrix;
+use Mnemosyne::audio::NullSink;
+use Mnemosyne::gb::run_until::StopCondition;
 use Mnemosyne::gb::GameBoy;
 
 fn run_mooneye_test(rom: &str) {
-    let mut gameboy = GameBoy::new();
+    let mut gameboy = GameBoy::with_audio_sink(Box::new(NullSink::default()));
     gameboy.load_rom(rom);
     gameboy.skip_boot_rom();
 
-    loop {
-        let (breakpoint, _) = gameboy.tick();
-        if breakpoint {
-            break;
-        }
-    }
+    let reason = gameboy.run_until(&[
+        StopCondition::SoftwareBreakpoint,
+        StopCondition::Cycles(MOONEYE_CYCLE_LIMIT),
+    ]);
+    assert_eq!(
+        reason.condition,
+        StopCondition::SoftwareBreakpoint,
+        "Test did not finish"
+    );
 
     let register = gameboy.dump_registers();
     assert_eq!(
@@ -0,0 +1,505 @@
+use crate::{screen_differences, setup};
+use serde_json::{json, Value};
+use std::any::Any;
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use Mnemosyne::gb::run_until::{StopCondition, CYCLES_PER_FRAME};
+use Mnemosyne::gb::GameBoy;
+
+const ARTIFACTS: &str = "./tests/game-boy-test-roms/artifacts";
+const EXPECTED_FAILURES: &str = "./tests/testsuite/expected_failures.txt";
+const REPORT_FOLDER: &str = "./target/test-roms";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Protocol {
+    // LD B,B with B, C, D, E, H and L set to 3, 5, 8, 13, 21 and 34 on success
+    Mooneye,
+    // "Passed" or "Failed" on serial, ROMs that only draw their result fall back to the reference
+    // screenshot
+    Blargg,
+    // LD B,B or the frame limit, after which the screen is compared to the reference
+    Screenshot,
+    // 0x01 at 0xFF82 on success, 0xFF on failure
+    Memory,
+}
+
+impl Protocol {
+    fn name(&self) -> &'static str {
+        match self {
+            Protocol::Mooneye => "mooneye",
+            Protocol::Blargg => "blargg",
+            Protocol::Screenshot => "screenshot",
+            Protocol::Memory => "memory",
+        }
+    }
+}
+
+struct Suite {
+    // Folder under the artifacts folder
+    folder: &'static str,
+    protocol: Protocol,
+    // Appended to the ROM name to find the DMG reference screenshot next to it
+    references: &'static [&'static str],
+    // Subfolders that are skipped, e.g. CGB-only tests and tools
+    exclude: &'static [&'static str],
+    // Guards against ROMs that never report a result
+    frame_limit: u64,
+}
+
+const SUITES: [Suite; 8] = [
+    Suite {
+        folder: "blargg",
+        protocol: Protocol::Blargg,
+        references: &["-dmg.png", "-dmg-cgb.png"],
+        exclude: &["cgb_sound", "interrupt_time"],
+        frame_limit: 3600,
+    },
+    Suite {
+        folder: "mooneye-test-suite",
+        protocol: Protocol::Mooneye,
+        references: &["-dmg.png"],
+        exclude: &["utils"],
+        frame_limit: 1200,
+    },
+    Suite {
+        folder: "mooneye-test-suite-wilbertpol",
+        protocol: Protocol::Mooneye,
+        references: &["-dmg.png"],
+        exclude: &[],
+        frame_limit: 1200,
+    },
+    Suite {
+        folder: "age-test-roms",
+        protocol: Protocol::Mooneye,
+        references: &["-dmg.png", "-dmgC.png"],
+        exclude: &[],
+        frame_limit: 1200,
+    },
+    Suite {
+        folder: "same-suite",
+        protocol: Protocol::Mooneye,
+        references: &["-dmg.png"],
+        exclude: &[],
+        frame_limit: 1200,
+    },
+    Suite {
+        folder: "dmg-acid2",
+        protocol: Protocol::Screenshot,
+        references: &["-dmg.png"],
+        exclude: &[],
+        frame_limit: 600,
+    },
+    Suite {
+        folder: "mealybug-tearoom-tests",
+        protocol: Protocol::Screenshot,
+        references: &["_dmg_blob.png", "-dmg.png"],
+        exclude: &[],
+        frame_limit: 600,
+    },
+    Suite {
+        folder: "gbmicrotest",
+        protocol: Protocol::Memory,
+        references: &[],
+        exclude: &[],
+        frame_limit: 120,
+    },
+];
+
+struct TestRom {
+    suite: &'static str,
+    // Path relative to the artifacts folder, as used in the expected failures list
+    name: String,
+    path: PathBuf,
+    protocol: Protocol,
+    reference: Option<PathBuf>,
+    frame_limit: u64,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Outcome {
+    Pass,
+    Fail(String),
+    Skip(String),
+}
+
+impl Outcome {
+    fn name(&self) -> &'static str {
+        match self {
+            Outcome::Pass => "pass",
+            Outcome::Fail(_) => "fail",
+            Outcome::Skip(_) => "skip",
+        }
+    }
+
+    fn detail(&self) -> &str {
+        match self {
+            Outcome::Pass => "",
+            Outcome::Fail(detail) | Outcome::Skip(detail) => detail,
+        }
+    }
+}
+
+fn discover() -> Vec<TestRom> {
+    let artifacts = Path::new(ARTIFACTS);
+    assert!(
+        artifacts.is_dir(),
+        "{} is missing, run `git submodule update --init`",
+        ARTIFACTS
+    );
+
+    let mut roms = Vec::new();
+    for suite in &SUITES {
+        let folder = artifacts.join(suite.folder);
+        let mut paths = Vec::new();
+        collect_roms(&folder, &mut paths);
+        paths.sort();
+
+        for path in paths {
+            let relative = path.strip_prefix(&folder).unwrap();
+            if suite
+                .exclude
+                .iter()
+                .any(|excluded| relative.starts_with(excluded))
+                || !runs_on_dmg(&path)
+            {
+                continue;
+            }
+
+            let reference = find_reference(&path, suite.references);
+            // Tests that are judged by looking at the screen ship a reference screenshot
+            let protocol = match suite.protocol {
+                Protocol::Mooneye if reference.is_some() => Protocol::Screenshot,
+                protocol => protocol,
+            };
+            roms.push(TestRom {
+                suite: suite.folder,
+                name: path
+                    .strip_prefix(artifacts)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/"),
+                path,
+                protocol,
+                reference,
+                frame_limit: suite.frame_limit,
+            });
+        }
+    }
+    roms
+}
+
+fn collect_roms(folder: &Path, roms: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(folder) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_roms(&path, roms);
+        } else if path.extension().is_some_and(|extension| extension == "gb") {
+            roms.push(path);
+        }
+    }
+}
+
+// Model suffixes follow the mooneye convention, e.g. `-dmgABC`, `-GS` or `-cgb`, and a ROM may
+// list several of them, e.g. `-dmgC-cgbBC`. ROMs without one are assumed to run on every model
+fn runs_on_dmg(path: &Path) -> bool {
+    let name = path.file_stem().unwrap().to_string_lossy();
+    let models: Vec<bool> = name.split('-').skip(1).filter_map(dmg_models).collect();
+    models.is_empty() || models.contains(&true)
+}
+
+// Whether a suffix includes a DMG model, None when it doesn't name any models
+fn dmg_models(suffix: &str) -> Option<bool> {
+    if suffix.contains("dmg") {
+        return Some(suffix != "dmg0");
+    }
+    if !suffix.is_empty() && suffix.chars().all(|model| "GSCA".contains(model)) {
+        return Some(suffix.contains('G'));
+    }
+    ["cgb", "sgb", "mgb", "agb", "ags", "ncm"]
+        .iter()
+        .any(|model| suffix.contains(model))
+        .then_some(false)
+}
+
+fn find_reference(rom: &Path, suffixes: &[&str]) -> Option<PathBuf> {
+    let name = rom.file_stem()?.to_string_lossy();
+    suffixes
+        .iter()
+        .map(|suffix| rom.with_file_name(format!("{}{}", name, suffix)))
+        .find(|path| path.is_file())
+}
+
+fn run_rom(rom: &TestRom) -> Outcome {
+    let mut gameboy = setup(rom.path.to_str().unwrap());
+    // No frames are produced while the LCD is off, the cycle limit covers ROMs that keep it off
+    let frame_limit = StopCondition::Frames(rom.frame_limit);
+    let cycle_limit = StopCondition::Cycles(rom.frame_limit * CYCLES_PER_FRAME);
+
+    match rom.protocol {
+        Protocol::Mooneye => {
+            let reason =
+                gameboy.run_until(&[StopCondition::SoftwareBreakpoint, frame_limit, cycle_limit]);
+            if reason.condition != StopCondition::SoftwareBreakpoint {
+                return Outcome::Fail(format!("No breakpoint after {} frames", rom.frame_limit));
+            }
+            let registers = gameboy.dump_registers();
+            let values = [
+                registers.B,
+                registers.C,
+                registers.D,
+                registers.E,
+                registers.H,
+                registers.L,
+            ];
+            if values == [3, 5, 8, 13, 21, 34] {
+                Outcome::Pass
+            } else {
+                Outcome::Fail(format!("Registers B-L are {:?}", values))
+            }
+        }
+        Protocol::Blargg => {
+            gameboy.run_until(&[
+                StopCondition::SerialContains("Passed".to_string()),
+                StopCondition::SerialContains("Failed".to_string()),
+                frame_limit,
+                cycle_limit,
+            ]);
+            let serial = gameboy.serial_buffer().iter().collect::<String>();
+            if serial.contains("Passed") {
+                Outcome::Pass
+            } else if serial.contains("Failed") {
+                Outcome::Fail(serial.split_whitespace().collect::<Vec<_>>().join(" "))
+            } else if let Some(reference) = &rom.reference {
+                compare_screenshot(&gameboy, reference)
+            } else {
+                Outcome::Fail("No result on serial".to_string())
+            }
+        }
+        Protocol::Screenshot => {
+            let Some(reference) = &rom.reference else {
+                return Outcome::Skip("No DMG reference screenshot".to_string());
+            };
+            gameboy.run_until(&[StopCondition::SoftwareBreakpoint, frame_limit, cycle_limit]);
+            compare_screenshot(&gameboy, reference)
+        }
+        Protocol::Memory => {
+            let reason = gameboy.run_until(&[
+                StopCondition::MemoryEquals(0xFF82, 0x01),
+                StopCondition::MemoryEquals(0xFF82, 0xFF),
+                frame_limit,
+                cycle_limit,
+            ]);
+            match reason.condition {
+                StopCondition::MemoryEquals(_, 0x01) => Outcome::Pass,
+                StopCondition::MemoryEquals(..) => {
+                    Outcome::Fail("Failure reported at 0xFF82".to_string())
+                }
+                _ => Outcome::Fail(format!("No result after {} frames", rom.frame_limit)),
+            }
+        }
+    }
+}
+
+fn compare_screenshot(gameboy: &GameBoy, reference: &Path) -> Outcome {
+    match screen_differences(gameboy, reference) {
+        Ok(0) => Outcome::Pass,
+        Ok(different) => Outcome::Fail(format!("{} pixels differ from the reference", different)),
+        Err(err) => Outcome::Fail(err),
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast::<&str>()
+            .map(|message| message.to_string())
+            .unwrap_or_default(),
+    };
+    format!("Panicked: {}", message)
+}
+
+// ROMs are spread over all cores, a panicking ROM only fails itself
+fn run_all(roms: &[TestRom]) -> Vec<Outcome> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(rom) = roms.get(index) else {
+                    break;
+                };
+                let outcome = panic::catch_unwind(AssertUnwindSafe(|| run_rom(rom)))
+                    .unwrap_or_else(panic_message);
+                println!("{}: {}", rom.name, outcome.name());
+                results.lock().unwrap().push((index, outcome));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, outcome)| outcome).collect()
+}
+
+fn load_expected_failures() -> BTreeSet<String> {
+    fs::read_to_string(EXPECTED_FAILURES)
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+fn save_expected_failures(roms: &[TestRom], outcomes: &[Outcome]) {
+    let mut contents = String::from(
+        "# Test ROMs that are known to fail, one path relative to tests/game-boy-test-roms/artifacts\n\
+         # per line. Regenerate with `UPDATE_EXPECTED_FAILURES=1 cargo test --test testsuite -- --ignored test_rom_matrix`\n",
+    );
+    for (rom, outcome) in roms.iter().zip(outcomes) {
+        if let Outcome::Fail(_) = outcome {
+            contents.push_str(&rom.name);
+            contents.push('\n');
+        }
+    }
+    fs::write(EXPECTED_FAILURES, contents).expect("Failed to write expected failures");
+}
+
+// Per suite counts of passed, failed and skipped ROMs
+fn summary(roms: &[TestRom], outcomes: &[Outcome]) -> Vec<(&'static str, [usize; 3])> {
+    SUITES
+        .iter()
+        .map(|suite| {
+            let mut counts = [0; 3];
+            for (rom, outcome) in roms.iter().zip(outcomes) {
+                if rom.suite == suite.folder {
+                    match outcome {
+                        Outcome::Pass => counts[0] += 1,
+                        Outcome::Fail(_) => counts[1] += 1,
+                        Outcome::Skip(_) => counts[2] += 1,
+                    }
+                }
+            }
+            (suite.folder, counts)
+        })
+        .collect()
+}
+
+fn save_report(roms: &[TestRom], outcomes: &[Outcome], expected_failures: &BTreeSet<String>) {
+    let mut markdown = String::from("# Test ROM results\n\n");
+    markdown.push_str("| Suite | Passed | Failed | Skipped |\n|---|---|---|---|\n");
+    for (suite, [passed, failed, skipped]) in summary(roms, outcomes) {
+        let _ = writeln!(
+            markdown,
+            "| {} | {} | {} | {} |",
+            suite, passed, failed, skipped
+        );
+    }
+    markdown.push_str("\n| ROM | Protocol | Result | Expected failure | Detail |\n");
+    markdown.push_str("|---|---|---|---|---|\n");
+
+    let suites: Vec<Value> = summary(roms, outcomes)
+        .into_iter()
+        .map(|(suite, [passed, failed, skipped])| {
+            json!({
+                "suite": suite,
+                "passed": passed,
+                "failed": failed,
+                "skipped": skipped,
+            })
+        })
+        .collect();
+
+    let mut results = Vec::new();
+    for (rom, outcome) in roms.iter().zip(outcomes) {
+        let expected_failure = expected_failures.contains(&rom.name);
+        let _ = writeln!(
+            markdown,
+            "| `{}` | {} | {} | {} | {} |",
+            rom.name,
+            rom.protocol.name(),
+            outcome.name(),
+            if expected_failure { "yes" } else { "" },
+            outcome.detail().replace('|', "\\|")
+        );
+        results.push(json!({
+            "rom": rom.name,
+            "suite": rom.suite,
+            "protocol": rom.protocol.name(),
+            "result": outcome.name(),
+            "expected_failure": expected_failure,
+            "detail": outcome.detail(),
+        }));
+    }
+    let json = serde_json::to_string_pretty(&json!({ "summary": suites, "results": results }))
+        .expect("Failed to serialize JSON report");
+
+    fs::create_dir_all(REPORT_FOLDER).expect("Failed to create report folder");
+    fs::write(Path::new(REPORT_FOLDER).join("report.md"), markdown)
+        .expect("Failed to write Markdown report");
+    fs::write(Path::new(REPORT_FOLDER).join("report.json"), json)
+        .expect("Failed to write JSON report");
+}
+
+#[test]
+#[ignore = "runs every test ROM, use `cargo test --test testsuite -- --ignored test_rom_matrix`"]
+fn test_rom_matrix() {
+    let roms = discover();
+    let outcomes = run_all(&roms);
+    let expected_failures = load_expected_failures();
+    save_report(&roms, &outcomes, &expected_failures);
+
+    if env::var_os("UPDATE_EXPECTED_FAILURES").is_some() {
+        save_expected_failures(&roms, &outcomes);
+        return;
+    }
+
+    let mut regressions = Vec::new();
+    let mut fixed = Vec::new();
+    for (rom, outcome) in roms.iter().zip(&outcomes) {
+        let expected_failure = expected_failures.contains(&rom.name);
+        match outcome {
+            Outcome::Fail(detail) if !expected_failure => {
+                regressions.push(format!("{}: {}", rom.name, detail))
+            }
+            Outcome::Pass if expected_failure => fixed.push(rom.name.clone()),
+            _ => {}
+        }
+    }
+
+    assert!(
+        regressions.is_empty() && fixed.is_empty(),
+        "Regressions:\n{}\n\nNow passing, remove from {}:\n{}",
+        regressions.join("\n"),
+        EXPECTED_FAILURES,
+        fixed.join("\n")
+    );
+}
+
+#[test]
+fn test_runs_on_dmg() {
+    let runs = |name: &str| runs_on_dmg(Path::new(name));
+    assert!(runs("blargg/cpu_instrs/individual/01-special.gb"));
+    assert!(runs("acceptance/boot_hwio-dmgABCmgb.gb"));
+    assert!(runs("acceptance/boot_regs-dmgABC.gb"));
+    assert!(!runs("acceptance/boot_div-dmg0.gb"));
+    assert!(!runs("acceptance/boot_div-S.gb"));
+    assert!(runs("acceptance/ppu/intr_2_mode0_timing_sprites-GS.gb"));
+    assert!(!runs("acceptance/boot_regs-cgb.gb"));
+    assert!(runs("age-test-roms/halt/ei-halt-dmgC-cgbBCE.gb"));
+    assert!(!runs("age-test-roms/vram/vram-read-cgbBCE.gb"));
+}
@@ -0,0 +1,190 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs;
+use Mnemosyne::gb::bus::{BusAccess, TestBus};
+use Mnemosyne::gb::cpu::CPU;
+use Mnemosyne::gb::registers::Registers;
+
+// https://github.com/SingleStepTests/sm83, one JSON file per opcode
+const TEST_FOLDER: &str = "./tests/sm83/v1";
+// STOP and HALT depend on hardware outside of the CPU
+const SKIPPED: [&str; 2] = ["10", "76"];
+
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    sp: u16,
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    ime: u8,
+    ie: Option<u8>,
+    ram: Vec<(u16, u8)>,
+}
+
+#[derive(Deserialize)]
+struct TestCase {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    // `[address, value, "r-m"]` for reads, `"-wm"` for writes and null or `"---"` for idle cycles
+    cycles: Vec<Value>,
+}
+
+fn expected_access(cycle: &Value) -> Option<BusAccess> {
+    let cycle = cycle.as_array()?;
+    let address = cycle.first()?.as_u64()? as u16;
+    let value = cycle.get(1)?.as_u64()? as u8;
+    let kind = cycle.get(2)?.as_str()?;
+    if kind.contains('r') {
+        Some(BusAccess::Read(address, value))
+    } else if kind.contains('w') {
+        Some(BusAccess::Write(address, value))
+    } else {
+        None
+    }
+}
+
+fn run_case(case: &TestCase) -> Result<(), String> {
+    let initial = &case.initial;
+    let mut bus = TestBus::new();
+    for &(address, value) in &initial.ram {
+        bus.memory[address as usize] = value;
+    }
+    if let Some(ie) = initial.ie {
+        bus.memory[0xFFFF] = ie;
+    }
+
+    let mut registers = Registers::new();
+    registers.A = initial.a;
+    registers.B = initial.b;
+    registers.C = initial.c;
+    registers.D = initial.d;
+    registers.E = initial.e;
+    registers.F = initial.f;
+    registers.H = initial.h;
+    registers.L = initial.l;
+    registers.SP = initial.sp;
+    registers.PC = initial.pc;
+    registers.IME = initial.ime != 0;
+
+    let mut cpu = CPU::new(registers, bus);
+    cpu.step();
+
+    let expected = &case.expected;
+    let registers = &cpu.registers;
+    let mut errors = Vec::new();
+    let comparisons = [
+        ("A", registers.A as u16, expected.a as u16),
+        ("B", registers.B as u16, expected.b as u16),
+        ("C", registers.C as u16, expected.c as u16),
+        ("D", registers.D as u16, expected.d as u16),
+        ("E", registers.E as u16, expected.e as u16),
+        ("F", registers.F as u16, expected.f as u16),
+        ("H", registers.H as u16, expected.h as u16),
+        ("L", registers.L as u16, expected.l as u16),
+        ("SP", registers.SP, expected.sp),
+        ("PC", registers.PC, expected.pc),
+        ("IME", registers.IME as u16, expected.ime as u16),
+    ];
+    for (name, actual, expected) in comparisons {
+        if actual != expected {
+            errors.push(format!(
+                "{} is {:#06X}, expected {:#06X}",
+                name, actual, expected
+            ));
+        }
+    }
+
+    let bus = cpu.bus();
+    for &(address, value) in &expected.ram {
+        let actual = bus.memory[address as usize];
+        if actual != value {
+            errors.push(format!(
+                "RAM {:#06X} is {:#04X}, expected {:#04X}",
+                address, actual, value
+            ));
+        }
+    }
+
+    if bus.cycles.len() != case.cycles.len() {
+        errors.push(format!(
+            "Took {} M-cycles, expected {}",
+            bus.cycles.len(),
+            case.cycles.len()
+        ));
+    } else {
+        for (index, (actual, expected)) in bus.cycles.iter().zip(&case.cycles).enumerate() {
+            let expected = expected_access(expected);
+            if actual.as_slice() != expected.as_slice() {
+                errors.push(format!(
+                    "M-cycle {} accessed {:?}, expected {:?}",
+                    index, actual, expected
+                ));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join(", "))
+    }
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests sm83 JSON files, clone https://github.com/SingleStepTests/sm83 into tests/sm83"]
+fn test_sm83_single_step() {
+    let mut files = fs::read_dir(TEST_FOLDER)
+        .expect("Missing SingleStepTests sm83 JSON files")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let mut passed = Vec::new();
+    let mut skipped = Vec::new();
+    let mut failures = Vec::new();
+    for file in files {
+        let opcode = file.file_stem().unwrap().to_string_lossy().to_string();
+        if SKIPPED.contains(&opcode.as_str()) {
+            skipped.push(opcode);
+            continue;
+        }
+
+        let cases: Vec<TestCase> =
+            serde_json::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+        // Only the first failing case of every opcode is reported
+        match cases
+            .iter()
+            .find_map(|case| run_case(case).err().map(|err| (case, err)))
+        {
+            Some((case, err)) => {
+                failures.push(format!("{} (case {}): {}", opcode, case.name, err));
+            }
+            None => passed.push(opcode),
+        }
+    }
+
+    println!("{} opcodes passed: {}", passed.len(), passed.join(" "));
+    println!(
+        "{} opcodes skipped (STOP and HALT): {}",
+        skipped.len(),
+        skipped.join(" ")
+    );
+    assert!(
+        failures.is_empty(),
+        "{} opcodes failed:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}